@@ -0,0 +1,787 @@
+use std::{collections::HashMap, fmt, ops::Range};
+
+use ratatui::{
+    style::Stylize,
+    text::{Line, Text},
+};
+
+use crate::theme::Theme;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Error,
+    Warning,
+    Note,
+    Help,
+    Suggestion,
+}
+
+impl Kind {
+    /// Parses both the annotation spelling (`ERROR`, `WARN`) and the rustc one (`error`, `warning`).
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s.to_ascii_lowercase().as_str() {
+            "error" => Kind::Error,
+            "warn" | "warning" => Kind::Warning,
+            "note" => Kind::Note,
+            "help" => Kind::Help,
+            "suggestion" => Kind::Suggestion,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Error => "ERROR",
+            Kind::Warning => "WARN",
+            Kind::Note => "NOTE",
+            Kind::Help => "HELP",
+            Kind::Suggestion => "SUGGESTION",
+        })
+    }
+}
+
+/// A `//~ KIND msg` comment in the test source.
+#[derive(Debug, Clone)]
+pub struct Annotation {
+    /// 1-based line the annotation refers to, `None` for `//~?`.
+    pub line: Option<usize>,
+    /// 1-based line the comment is written on.
+    pub source_line: usize,
+    /// Byte offsets of the `//` and of the message inside the source line.
+    pub comment_start: usize,
+    pub msg_start: usize,
+    pub revision: Option<String>,
+    pub kind: Kind,
+    pub msg: String,
+}
+
+/// A diagnostic emitted by rustc, as rendered in the stderr.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// 1-based line in the test file, `None` if it points somewhere else.
+    pub line: Option<usize>,
+    pub kind: Kind,
+    pub code: Option<String>,
+    pub msg: String,
+}
+
+impl Diagnostic {
+    fn matches(&self, annotation: &Annotation) -> bool {
+        self.kind == annotation.kind
+            && (annotation.line.is_none() || self.line == annotation.line)
+            && (self.msg.contains(&annotation.msg)
+                || self.code.as_deref() == Some(annotation.msg.as_str()))
+    }
+}
+
+pub fn parse_annotations(code: &str) -> Vec<Annotation> {
+    let mut annotations: Vec<Annotation> = vec![];
+    for (i, line) in code.lines().enumerate() {
+        let source_line = i + 1;
+        let Some(comment_start) = line.find("//") else {
+            continue;
+        };
+        let rest = &line[comment_start + 2..];
+        let (revision, rest) = match rest.strip_prefix('[') {
+            Some(rest) => match rest.split_once(']') {
+                Some((rev, rest)) => (Some(rev.to_owned()), rest),
+                None => continue,
+            },
+            None => (None, rest),
+        };
+        let Some(rest) = rest.strip_prefix('~') else {
+            continue;
+        };
+
+        let (target, rest) = if let Some(rest) = rest.strip_prefix('|') {
+            let prev = annotations.last().and_then(|a| a.line);
+            (prev.or(Some(source_line)), rest)
+        } else if let Some(rest) = rest.strip_prefix('?') {
+            (None, rest)
+        } else if rest.starts_with('^') {
+            let carets = rest.bytes().take_while(|&b| b == b'^').count();
+            (Some(source_line.saturating_sub(carets)), &rest[carets..])
+        } else if rest.starts_with('v') {
+            let arrows = rest.bytes().take_while(|&b| b == b'v').count();
+            (Some(source_line + arrows), &rest[arrows..])
+        } else {
+            (Some(source_line), rest)
+        };
+
+        let rest = rest.trim_start();
+        let (kind, msg) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let Some(kind) = Kind::parse(kind.trim_end_matches(':')) else {
+            continue;
+        };
+        let msg = msg.trim_start();
+        annotations.push(Annotation {
+            line: target,
+            source_line,
+            comment_start,
+            msg_start: line.len() - msg.len(),
+            revision,
+            kind,
+            msg: msg.trim_end().to_owned(),
+        });
+    }
+    annotations
+}
+
+/// Parses the top level diagnostics and their `= note:` children out of a rendered stderr.
+/// Only locations inside `file_name` (as in `$DIR/file_name`) are kept.
+pub fn parse_diagnostics(stderr: &str, file_name: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    // Index of the current top level diagnostic, and whether its location is known yet.
+    let mut parent = None;
+    let mut children = vec![];
+
+    for line in stderr.lines() {
+        // The source lines, `LL | ...` once normalized, belong to the diagnostic they're in even
+        // if they look like a header.
+        let is_source = line
+            .split_once(" |")
+            .is_some_and(|(number, _)| number == "LL" || number.parse::<usize>().is_ok());
+        if !line.starts_with(char::is_whitespace) && !is_source {
+            let Some((head, msg)) = line.split_once(": ") else {
+                parent = None;
+                continue;
+            };
+            let (kind, code) = match head.split_once('[') {
+                Some((kind, code)) => (kind, Some(code.trim_end_matches(']').to_owned())),
+                None => (head, None),
+            };
+            let Some(kind) = Kind::parse(kind) else {
+                parent = None;
+                continue;
+            };
+            diagnostics.push(Diagnostic {
+                line: None,
+                kind,
+                code,
+                msg: msg.to_owned(),
+            });
+            parent = Some((diagnostics.len() - 1, false));
+            continue;
+        }
+
+        let Some((idx, located)) = parent else {
+            continue;
+        };
+        let line = line.trim_start();
+        if let Some(location) = line.strip_prefix("--> ") {
+            if !located {
+                diagnostics[idx].line = location_line(location, file_name);
+                parent = Some((idx, true));
+            }
+        } else if let Some(child) = line.strip_prefix("= ") {
+            let Some((kind, msg)) = child.split_once(": ") else {
+                continue;
+            };
+            let Some(kind) = Kind::parse(kind) else {
+                continue;
+            };
+            children.push((idx, diagnostics.len()));
+            diagnostics.push(Diagnostic {
+                line: None,
+                kind,
+                code: None,
+                msg: msg.to_owned(),
+            });
+        }
+    }
+
+    for (parent, child) in children {
+        diagnostics[child].line = diagnostics[parent].line;
+    }
+    diagnostics.retain(|d| d.line.is_some());
+    diagnostics
+}
+
+/// Extracts the line out of `$DIR/file.rs:12:5` if it points to `file_name`.
+pub fn location_line(location: &str, file_name: &str) -> Option<usize> {
+    let mut parts = location.rsplitn(3, ':');
+    let _col = parts.next()?;
+    let line = parts.next()?.parse().ok()?;
+    let path = parts.next()?;
+    (path.strip_prefix("$DIR/")? == file_name).then_some(line)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Comparison {
+    pub matched: Vec<(Annotation, Diagnostic)>,
+    pub unmatched: Vec<Annotation>,
+    pub unannotated: Vec<Diagnostic>,
+}
+
+impl Comparison {
+    /// Matches annotations against diagnostics the way compiletest does: notes and helps only
+    /// need annotations if the test annotates at least one of that kind.
+    pub fn new(annotations: Vec<Annotation>, mut diagnostics: Vec<Diagnostic>) -> Self {
+        let mut this = Self::default();
+        let annotates = |kind| annotations.iter().any(|a| a.kind == kind);
+        let check_notes = annotates(Kind::Note);
+        let check_helps = annotates(Kind::Help);
+        let check_suggestions = annotates(Kind::Suggestion);
+
+        for annotation in annotations {
+            match diagnostics.iter().position(|d| d.matches(&annotation)) {
+                Some(i) => this.matched.push((annotation, diagnostics.remove(i))),
+                None => this.unmatched.push(annotation),
+            }
+        }
+
+        this.unannotated = diagnostics
+            .into_iter()
+            .filter(|d| match d.kind {
+                Kind::Error | Kind::Warning => true,
+                Kind::Note => check_notes,
+                Kind::Help => check_helps,
+                Kind::Suggestion => check_suggestions,
+            })
+            .collect();
+        this
+    }
+
+    pub fn from_sources(test_code: &str, stderr: &str, file_name: &str) -> Self {
+        Self::new(
+            parse_annotations(test_code),
+            parse_diagnostics(stderr, file_name),
+        )
+    }
+
+    pub fn to_text(&self, theme: &Theme) -> Text<'static> {
+        fn line_no(line: Option<usize>) -> String {
+            line.map_or("?".to_owned(), |l| l.to_string())
+        }
+
+        let mut rows: Vec<(Option<usize>, Line<'static>)> = vec![];
+        for (annotation, diagnostic) in &self.matched {
+            rows.push((
+                annotation.line,
+                format!(
+                    "  ok      {:>4}: {} {}",
+                    line_no(annotation.line),
+                    annotation.kind,
+                    diagnostic.msg
+                )
+                .into(),
+            ));
+        }
+        for annotation in &self.unmatched {
+            let rev = annotation
+                .revision
+                .as_ref()
+                .map(|r| format!("[{r}] "))
+                .unwrap_or_default();
+            rows.push((
+                annotation.line,
+                Line::styled(
+                    format!(
+                        "- missing {:>4}: {rev}{} {}",
+                        line_no(annotation.line),
+                        annotation.kind,
+                        annotation.msg
+                    ),
+                    theme.delete,
+                ),
+            ));
+        }
+        for diagnostic in &self.unannotated {
+            rows.push((
+                diagnostic.line,
+                Line::styled(
+                    format!(
+                        "+ extra   {:>4}: {} {}",
+                        line_no(diagnostic.line),
+                        diagnostic.kind,
+                        diagnostic.msg
+                    ),
+                    theme.insert,
+                ),
+            ));
+        }
+        rows.sort_by_key(|(line, _)| *line);
+
+        let mut lines = vec![Line::from(format!(
+            "{} matched, {} unmatched annotations, {} unannotated diagnostics",
+            self.matched.len(),
+            self.unmatched.len(),
+            self.unannotated.len()
+        ))
+        .bold()];
+        lines.extend(rows.into_iter().map(|(_, line)| line));
+        lines.into()
+    }
+}
+
+enum Edit {
+    /// Replace the message of the annotation starting at the byte offset.
+    Update(usize, String),
+    /// Remove the comment starting at the byte offset, and the whole line if nothing is left.
+    Remove(usize),
+    Append(String),
+    /// Insert lines below with annotations of the line at the index.
+    InsertAfter(usize, Vec<String>),
+}
+
+/// A line of the rewritten test.
+struct Row {
+    text: String,
+    /// Index of the line in the test before the rewrite, `None` for the added lines.
+    origin: Option<usize>,
+    /// The original index of the line the annotation of the line points to, `Some(None)` for
+    /// `//~?` and `None` without an annotation.
+    target: Option<Option<usize>>,
+}
+
+/// Makes the `//~^`, `//~v` and `//~|` annotations of `rows` point to the lines they pointed to
+/// before lines were removed or added between them and their targets.
+fn retarget(rows: &mut [Row]) {
+    let position: HashMap<usize, usize> = rows
+        .iter()
+        .enumerate()
+        .filter_map(|(i, row)| Some((row.origin?, i)))
+        .collect();
+    // Where the previous annotation points, the one `//~|` refers to.
+    let mut previous = None;
+    for (i, row) in rows.iter_mut().enumerate() {
+        let Some(target) = row.target else {
+            continue;
+        };
+        let Some(target) = target.and_then(|target| position.get(&target).copied()) else {
+            previous = None;
+            continue;
+        };
+        if let Some(marker) = marker_range(&row.text) {
+            let current = &row.text[marker.clone()];
+            let wanted = if current == "|" && previous == Some(target) {
+                "|".to_owned()
+            } else if target <= i {
+                "^".repeat(i - target)
+            } else {
+                "v".repeat(target - i)
+            };
+            if current != wanted {
+                row.text.replace_range(marker, &wanted);
+            }
+        }
+        previous = Some(target);
+    }
+}
+
+/// The byte range of the `^^`, `v` or `|` after the `//~` of `line`, empty for a plain `//~`.
+fn marker_range(line: &str) -> Option<Range<usize>> {
+    let comment = line.find("//")? + 2;
+    let mut start = comment;
+    if line[start..].starts_with('[') {
+        start += line[start..].find(']')? + 1;
+    }
+    start += line[start..].strip_prefix('~').map(|_| 1)?;
+    let len = line[start..]
+        .find(|c| !matches!(c, '^' | 'v' | '|'))
+        .unwrap_or(line.len() - start);
+    Some(start..start + len)
+}
+
+/// Rewrites the annotations of `code` so they match the emitted diagnostics: stale messages are
+/// updated in place, annotations without a diagnostic are removed and unannotated diagnostics
+/// get a new annotation.
+///
+/// New annotations go at the end of the line they refer to, or as `//~|` after its last
+/// annotation. This shifts the following lines, so the test has to be rerun afterwards.
+pub fn rewrite(code: &str, comparison: &Comparison) -> String {
+    let all = parse_annotations(code);
+    let mut extra: Vec<&Diagnostic> = comparison.unannotated.iter().collect();
+    let mut edits: Vec<(usize, Edit)> = vec![];
+    let mut removed = vec![];
+
+    for annotation in &comparison.unmatched {
+        let idx = annotation.source_line - 1;
+        let same_spot = extra
+            .iter()
+            .position(|d| d.kind == annotation.kind && d.line == annotation.line);
+        match same_spot {
+            Some(i) => {
+                let msg = extra.remove(i).msg.clone();
+                edits.push((idx, Edit::Update(annotation.msg_start, msg)));
+            }
+            None => {
+                removed.push(annotation.source_line);
+                edits.push((idx, Edit::Remove(annotation.comment_start)));
+            }
+        }
+    }
+
+    let lines: Vec<&str> = code.lines().collect();
+    let mut targets: Vec<usize> = extra.iter().filter_map(|d| d.line).collect();
+    targets.sort_unstable();
+    targets.dedup();
+    for target in targets {
+        let Some(target_line) = lines.get(target - 1) else {
+            continue;
+        };
+        let mut new = extra
+            .iter()
+            .filter(|d| d.line == Some(target))
+            .map(|d| format!("{} {}", d.kind, d.msg));
+        let anchor = all
+            .iter()
+            .filter(|a| a.line == Some(target) && !removed.contains(&a.source_line))
+            .map(|a| a.source_line)
+            .max();
+        let anchor = match anchor {
+            Some(anchor) => anchor,
+            None => {
+                let first = new.next().expect("targets come from the diagnostics");
+                edits.push((target - 1, Edit::Append(format!(" //~ {first}"))));
+                target
+            }
+        };
+        let indent_len = lines[anchor - 1].len() - lines[anchor - 1].trim_start().len();
+        let indent = &target_line[..indent_len.min(target_line.len())];
+        let inserted: Vec<_> = new.map(|msg| format!("{indent}//~| {msg}")).collect();
+        if !inserted.is_empty() {
+            edits.push((anchor - 1, Edit::InsertAfter(target - 1, inserted)));
+        }
+    }
+
+    // Go bottom up so that the indices of the lines still to edit don't move.
+    let mut rows: Vec<Row> = lines
+        .iter()
+        .enumerate()
+        .map(|(idx, line)| Row {
+            text: (*line).to_owned(),
+            origin: Some(idx),
+            target: all
+                .iter()
+                .find(|a| a.source_line == idx + 1)
+                .map(|a| a.line.map(|line| line - 1)),
+        })
+        .collect();
+    // Appending has to happen after removing a comment on the same line, and inserting below a
+    // line before touching it.
+    edits.sort_by_key(|(idx, edit)| {
+        let order = match edit {
+            Edit::Append(_) => 0,
+            Edit::Update(..) | Edit::Remove(_) => 1,
+            Edit::InsertAfter(..) => 2,
+        };
+        (*idx, order)
+    });
+    for (idx, edit) in edits.into_iter().rev() {
+        let row = &mut rows[idx];
+        match edit {
+            Edit::Update(msg_start, msg) => {
+                let line = &mut row.text;
+                line.truncate(msg_start);
+                if !line.ends_with(char::is_whitespace) {
+                    line.push(' ');
+                }
+                line.push_str(&msg);
+            }
+            Edit::Remove(comment_start) => {
+                let line = &mut row.text;
+                line.truncate(comment_start);
+                line.truncate(line.trim_end().len());
+                row.target = None;
+                if line.is_empty() {
+                    rows.remove(idx);
+                }
+            }
+            Edit::Append(annotation) => {
+                row.text.push_str(&annotation);
+                row.target = Some(Some(idx));
+            }
+            Edit::InsertAfter(target, new) => {
+                let new = new.into_iter().map(|text| Row {
+                    text,
+                    origin: None,
+                    target: Some(Some(target)),
+                });
+                rows.splice(idx + 1..idx + 1, new);
+            }
+        }
+    }
+    retarget(&mut rows);
+    let lines: Vec<String> = rows.into_iter().map(|row| row.text).collect();
+
+    let mut code_out = lines.join("\n");
+    if code.ends_with('\n') {
+        code_out.push('\n');
+    }
+    code_out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "\
+//@ revisions: a b
+fn main() {
+    let x: u32 = \"a\"; //~ ERROR mismatched types
+    foo();
+    //~^ ERROR cannot find function `foo` in this scope
+    //~| HELP a local variable with a similar name exists
+    //[a]~v WARN unused variable
+    let y = 1;
+}
+//~? ERROR aborting
+";
+
+    const STDERR: &str = "\
+error[E0425]: cannot find function `foo` in this scope
+  --> $DIR/annotated.rs:4:5
+   |
+LL |     foo();
+   |     ^^^ not found in this scope
+
+error[E0308]: mismatched types
+  --> $DIR/annotated.rs:3:18
+   |
+LL |     let x: u32 = \"a\";
+   |            ---   ^^^ expected `u32`, found `&str`
+   |            |
+   |            expected due to this
+
+warning: unused variable: `x`
+  --> $DIR/annotated.rs:3:9
+   |
+LL |     let x: u32 = \"a\";
+   |         ^ help: if this is intentional, prefix it with an underscore: `_x`
+   |
+   = note: `#[warn(unused_variables)]` on by default
+
+error: aborting due to 2 previous errors; 1 warning emitted
+";
+
+    #[test]
+    fn parses_the_annotations() {
+        let annotations = parse_annotations(CODE);
+        let lines: Vec<_> = annotations
+            .iter()
+            .map(|a| (a.line, a.kind, a.msg.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (Some(3), Kind::Error, "mismatched types"),
+                (
+                    Some(4),
+                    Kind::Error,
+                    "cannot find function `foo` in this scope"
+                ),
+                (
+                    Some(4),
+                    Kind::Help,
+                    "a local variable with a similar name exists"
+                ),
+                (Some(8), Kind::Warning, "unused variable"),
+                (None, Kind::Error, "aborting"),
+            ]
+        );
+        assert_eq!(annotations[3].revision.as_deref(), Some("a"));
+        let first = &annotations[0];
+        assert_eq!(
+            &CODE.lines().nth(2).unwrap()[first.msg_start..],
+            "mismatched types"
+        );
+    }
+
+    #[test]
+    fn parses_the_diagnostics_of_the_test() {
+        let diagnostics = parse_diagnostics(STDERR, "annotated.rs");
+        let lines: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.line, d.kind, d.code.as_deref()))
+            .collect();
+        // The note is on the line of its parent, the summary is nowhere.
+        assert_eq!(
+            lines,
+            [
+                (Some(4), Kind::Error, Some("E0425")),
+                (Some(3), Kind::Error, Some("E0308")),
+                (Some(3), Kind::Warning, None),
+                (Some(3), Kind::Note, None),
+            ]
+        );
+        assert_eq!(
+            location_line("$DIR/annotated.rs:12:5", "annotated.rs"),
+            Some(12)
+        );
+        assert_eq!(
+            location_line("$DIR/auxiliary/dep.rs:1:1", "annotated.rs"),
+            None
+        );
+    }
+
+    #[test]
+    fn compares_like_compiletest() {
+        let comparison = Comparison::from_sources(CODE, STDERR, "annotated.rs");
+        let matched: Vec<_> = comparison
+            .matched
+            .iter()
+            .map(|(a, _)| a.msg.as_str())
+            .collect();
+        assert_eq!(
+            matched,
+            [
+                "mismatched types",
+                "cannot find function `foo` in this scope"
+            ]
+        );
+        // The warning is annotated on the wrong line, and aborting isn't in the diagnostics.
+        let unmatched: Vec<_> = comparison.unmatched.iter().map(|a| a.kind).collect();
+        assert_eq!(unmatched, [Kind::Help, Kind::Warning, Kind::Error]);
+        // The note doesn't need an annotation as no note is annotated.
+        let unannotated: Vec<_> = comparison.unannotated.iter().map(|d| d.kind).collect();
+        assert_eq!(unannotated, [Kind::Warning]);
+    }
+
+    fn error(line: usize, msg: &str) -> Diagnostic {
+        Diagnostic {
+            line: Some(line),
+            kind: Kind::Error,
+            code: None,
+            msg: msg.to_owned(),
+        }
+    }
+
+    /// `code` rewritten for `diagnostics`, checking that it then matches them, at the lines they
+    /// moved to.
+    fn rewritten(code: &str, diagnostics: &[Diagnostic], moved: &[Diagnostic]) -> String {
+        let comparison = Comparison::new(parse_annotations(code), diagnostics.to_vec());
+        let code = rewrite(code, &comparison);
+        let comparison = Comparison::new(parse_annotations(&code), moved.to_vec());
+        assert!(comparison.unmatched.is_empty(), "{code}");
+        assert!(comparison.unannotated.is_empty(), "{code}");
+        code
+    }
+
+    #[test]
+    fn updates_a_message_in_place() {
+        let diagnostics = [error(2, "mismatched types")];
+        let code = "fn main() {\n    let x: u32 = \"a\"; //~ ERROR wrong types\n}\n";
+        assert_eq!(
+            rewritten(code, &diagnostics, &diagnostics),
+            "fn main() {\n    let x: u32 = \"a\"; //~ ERROR mismatched types\n}\n"
+        );
+    }
+
+    #[test]
+    fn removes_a_stale_annotation() {
+        let code = "\
+fn main() {
+    foo(); //~ ERROR cannot find function
+    //~^ ERROR stale
+}
+";
+        let diagnostics = [error(2, "cannot find function `foo` in this scope")];
+        assert_eq!(
+            rewritten(code, &diagnostics, &diagnostics),
+            "fn main() {\n    foo(); //~ ERROR cannot find function\n}\n"
+        );
+    }
+
+    #[test]
+    fn appends_a_new_annotation() {
+        let code = "fn main() {\n    foo();\n}\n";
+        let diagnostics = [error(2, "cannot find function `foo` in this scope")];
+        assert_eq!(
+            rewritten(code, &diagnostics, &diagnostics),
+            "fn main() {\n    foo(); //~ ERROR cannot find function `foo` in this scope\n}\n"
+        );
+    }
+
+    #[test]
+    fn inserts_the_other_annotations_of_a_line() {
+        let code = "\
+fn main() {
+    let x: u32 = foo(); //~ ERROR cannot find function
+    bar();
+    //~^ ERROR cannot find function `bar`
+}
+";
+        let diagnostics = [
+            error(2, "cannot find function `foo` in this scope"),
+            error(2, "mismatched types"),
+            error(3, "cannot find function `bar` in this scope"),
+        ];
+        let moved = [
+            error(2, "cannot find function `foo` in this scope"),
+            error(2, "mismatched types"),
+            error(4, "cannot find function `bar` in this scope"),
+        ];
+        assert_eq!(
+            rewritten(code, &diagnostics, &moved),
+            "\
+fn main() {
+    let x: u32 = foo(); //~ ERROR cannot find function
+    //~| ERROR mismatched types
+    bar();
+    //~^ ERROR cannot find function `bar`
+}
+"
+        );
+    }
+
+    #[test]
+    fn keeps_the_relative_annotations_after_a_removed_line() {
+        let code = "\
+foo();
+//~^ ERROR stale
+//~^^ ERROR cannot find foo
+bar();
+//~v ERROR stale too
+//~| ERROR also stale
+//~v ERROR cannot find bar
+//~^^^^ ERROR cannot find bar
+baz();
+";
+        let diagnostics = [
+            error(1, "cannot find foo"),
+            error(4, "cannot find bar"),
+            error(4, "cannot find bar"),
+            error(9, "cannot find baz"),
+        ];
+        let moved = [
+            error(1, "cannot find foo"),
+            error(3, "cannot find bar"),
+            error(3, "cannot find bar"),
+            error(6, "cannot find baz"),
+        ];
+        assert_eq!(
+            rewritten(code, &diagnostics, &moved),
+            "\
+foo();
+//~^ ERROR cannot find foo
+bar();
+//~^ ERROR cannot find bar
+//~| ERROR cannot find bar
+baz(); //~ ERROR cannot find baz
+"
+        );
+    }
+
+    #[test]
+    fn keeps_the_target_of_a_continuation_whose_parent_is_removed() {
+        let code = "\
+foo();
+bar(); //~ ERROR stale
+//~^^ ERROR cannot find foo
+//~| ERROR mismatched types
+";
+        let diagnostics = [error(1, "cannot find foo"), error(1, "mismatched types")];
+        assert_eq!(
+            rewritten(code, &diagnostics, &diagnostics),
+            "\
+foo();
+bar();
+//~^^ ERROR cannot find foo
+//~| ERROR mismatched types
+"
+        );
+    }
+}
@@ -1,37 +1,58 @@
 use std::{
-    fs::read_to_string,
-    mem,
+    collections::HashMap,
+    io, mem,
     path::{Path, PathBuf},
-    process::exit,
+    process::{exit, Command, ExitCode},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
 use color_eyre::Result;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use ratatui::{
+    layout::{Constraint, Layout, Rect},
     style::Stylize,
     text::{Line, Span, Text},
-    widgets::{Block, Paragraph},
+    widgets::{Block, Clear, LineGauge, Paragraph, Wrap},
     DefaultTerminal, Frame,
 };
+use rayon::prelude::*;
 
 use crate::{
-    view::{DiffShow, FullLayout, ShowMode},
-    Stats, Stream,
+    annotations::{self, Comparison},
+    clipboard,
+    config::AfterBless,
+    diff::{line_diff, pane_rows, text_diff, too_big_for, DiffLine, DiffStats, LineTag},
+    diff_cache::{DiffCache, Summary},
+    directives,
+    editor::{self, Editor},
+    external::{self, ExternalDiff},
+    git,
+    history::History,
+    hooks, ice, keys,
+    loader::Loader,
+    metadata::Metadata,
+    outputs::{native_path, CachedData, CachedStreams, TestData},
+    picker::Picker,
+    preload::Preload,
+    recording::{Recorder, Replay},
+    remote, report,
+    rustc::{self, ColoredOutput},
+    search::{self, Search},
+    session::{FailOn, Session, Status},
+    sort::SortOrder,
+    theme::{Paint, Theme},
+    timings::{self, Phase},
+    tree::{self, Row, Tree},
+    view::{
+        expand_tabs, mark_cut_off, popup_area, render_scrollbar, render_stream_tabs,
+        resolved_paths_text, DiffShow, FullLayout, Pane, ShowMode,
+    },
+    watch::Watch,
+    FailureKind, Ignored, Stats, Stream,
 };
 
-#[derive(Debug, Clone)]
-pub struct TestData {
-    pub test_code: String,
-    pub actual: String,
-    pub expect: String,
-    pub stream: Stream,
-    pub test_name: String,
-    // TODO: I don't know how to extract it from stderr... probably need to modify compiletest
-    pub rustc_args: String,
-    // Used for blessing
-    pub expected_path: PathBuf,
-}
-
 #[derive(Debug, Default)]
 pub struct App {
     /// Is the application running?
@@ -42,167 +63,607 @@ pub struct App {
     pub current_stream: Stream,
     pub stats: Stats,
     pub paths: Vec<&'static str>,
+    /// The failing tests in the order they ran, `paths` may be sorted differently.
+    pub run_order: Vec<&'static str>,
+    pub sort_order: SortOrder,
+    /// Failed tests that aren't UI tests, only listed.
+    pub other_failed: Vec<&'static str>,
+    pub ignored: Vec<Ignored<'static>>,
+    pub exec_times: HashMap<&'static str, f64>,
+    pub failure_kinds: HashMap<&'static str, FailureKind>,
     pub cached_streams: CachedStreams, // This caches the loading of the test data
+    /// The diffs of `cached_streams`.
+    pub diffs: DiffCache,
+    /// Why the loaded streams may be stale, see [`TestData::staleness`].
+    pub stale: Vec<(Stream, &'static str)>,
+    /// How similar the expected and actual outputs of the tests are, from 0 to 1, as far as
+    /// they've been loaded.
+    pub similarities: HashMap<&'static str, f32>,
+    /// When the run started, from when the JSON output was last written and how long it took.
+    pub run_started: Option<SystemTime>,
     pub rust_path: PathBuf,
+    /// Branch and commit of the rust checkout, so that it's clear where the blesses go.
+    pub git_head: Option<String>,
+    /// Stage the blessed files, see `git_add` in the config.
+    pub git_add: bool,
+    /// See `pre_bless` in the config.
+    pub pre_bless: Option<String>,
+    /// See `post_bless` in the config.
+    pub post_bless: Option<String>,
+    /// Go from the last test to the first and back instead of stopping, see `wrap_around` in the
+    /// config.
+    pub wrap_around: bool,
+    /// See `after_bless` in the config.
+    pub after_bless: AfterBless,
+    /// See `confirm_bless` in the config.
+    pub confirm_bless: bool,
+    /// Commit the blessed files when the session ends, see `--commit`.
+    pub commit: bool,
+    /// See `--fail-on`.
+    pub fail_on: FailOn,
+    pub session: Session,
+    /// Where to save the session, if anywhere.
+    pub session_path: Option<PathBuf>,
+    /// The session as it was last saved, so that it's only written when it changes.
+    pub saved_session: Option<Session>,
+    /// Where to write the marks when the review ends, see `--export`.
+    pub export_path: Option<PathBuf>,
+    /// Where the keys are written, see `--record`.
+    pub recorder: Option<Recorder>,
+    /// The keys of `--replay` that are still to come.
+    pub replay: Option<Replay>,
 
     pub scroll_pos_diff: u16,
     pub scroll_pos_code: u16,
+    /// Inner heights of the diff and code panes as last drawn, for scrolling by pages.
+    pub diff_height: u16,
+    pub code_height: u16,
+    /// The lines of the focused pane cut off at its right edge as last drawn, shown by `L`.
+    pub cut_off: Vec<String>,
+    /// The pane that movement keys act on.
+    pub focus: Pane,
+    /// What `.` repeats.
+    pub last_action: Option<keys::Command>,
+    /// Set when a new stream is shown, so the diff starts at its first change.
+    pub scroll_to_first_hunk: bool,
+    /// 1-based line of the code pane to highlight.
+    pub highlighted_code_line: Option<usize>,
+    /// What the code pane shows, 0 for the test and then its auxiliary crates.
+    pub code_tab: usize,
+    pub search: Option<Search>,
+    pub picker: Option<Picker>,
+    pub tree: Tree,
+    pub show_tree: bool,
+    /// First key of a two key command like `ma`.
+    pub pending_key: Option<char>,
+    /// Tests jumped away from, for going back and forth like vim's jumplist.
+    pub history: Vec<usize>,
+    pub future: Vec<usize>,
+    pub bookmarks: HashMap<char, usize>,
+    /// The `:` prompt, currently only used to jump to a test by its number.
+    pub command: Option<String>,
+    /// Show the diff of an ICE instead of the ICE view.
+    pub show_ice_diff: bool,
+    /// A yes/no question, drawn on top of everything.
+    pub confirmation: Option<Confirmation>,
+    /// A program to run in the terminal, handled by the main loop, which owns the terminal.
+    pub suspend: Option<Suspend>,
+    /// Command for the external diff view, see `--diff-tool`.
+    pub diff_tool: Option<String>,
+    pub external_diff: Option<ExternalDiff>,
+    /// For the colored view, see [`rustc::colored_output`].
+    pub colored_output: Option<ColoredOutput>,
+    /// The last commit that touched an expected output, for the info view.
+    pub last_change: Option<(PathBuf, Option<String>)>,
+    /// Command to merge the expected and actual outputs with, see `--merge-tool`.
+    pub merge_tool: Option<String>,
+    /// Template of the command copied by `yx`, with `{path}` standing for the test.
+    pub x_command: String,
+    /// Editing the expected output in place of the diff.
+    pub editor: Option<Editor>,
+    /// Feedback shown in the bottom bar until the next key.
+    pub message: Option<String>,
+    /// Drawn on top of everything else and grabs the input while it's open.
+    pub popup: Option<Popup>,
+    /// Reload the current test when its outputs change, see `--watch`.
+    pub watch: Option<Watch>,
+    /// The recorded runs, see `history` in the config.
+    pub runs: Option<History>,
+    /// Of the current test, only gathered while the metadata panel is shown.
+    pub metadata: Option<Metadata>,
+    /// The tests loaded up front, see `--preload`.
+    pub preload: Option<Preload>,
+    /// The current test while it's being read.
+    pub loading: Option<Loader>,
+    /// The title and text of a popup, like the statistics, while it's being computed.
+    pub pending_popup: Option<(String, Receiver<String>)>,
+    /// The stream being blessed while its hooks run.
+    pub blessing: Option<Blessing>,
 }
 
-#[derive(Debug, Clone, Default)]
-enum CachedData {
-    #[default]
-    Unloaded,
-    Missing,
-    Present(TestData),
+/// Programs that take over the terminal.
+#[derive(Debug, Clone)]
+pub enum Suspend {
+    /// Open a file in the editor, at a line if given.
+    Edit(PathBuf, Option<usize>),
+    /// Run the merge tool on the current stream.
+    Merge,
+}
+
+/// Something that needs confirmation before it's done.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Bless,
+    /// Bless every failing test in the directory.
+    BlessDirectory(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Confirmation {
+    pub question: String,
+    pub action: Action,
 }
 
 #[derive(Debug, Clone, Default)]
-pub struct CachedStreams {
-    stderr: CachedData,
-    stdout: CachedData,
+pub struct Popup {
+    pub title: String,
+    pub text: String,
+    pub scroll: u16,
+}
+
+/// A stream blessed on a worker, as its hooks may take a while.
+#[derive(Debug)]
+pub struct Blessing {
+    test: String,
+    stream: Stream,
+    outcome: Receiver<BlessOutcome>,
+}
+
+/// How blessing a stream went.
+#[derive(Debug)]
+enum BlessOutcome {
+    /// Not blessed, with the title of the popup showing why.
+    Failed { title: &'static str, error: String },
+    /// Blessed, though staging it or the post-bless hook may have failed.
+    Blessed {
+        git_add: Option<String>,
+        post_bless: Option<String>,
+    },
 }
 
 impl App {
-    pub fn load_curr_data(&mut self) {
-        let path_str = self.paths[self.current_test];
-        let path = Path::new(path_str);
-        let test_code = self.rust_path.join(path);
-        let expected_stderr_path = test_code.with_extension("stderr");
-        let expected_stdout_path = test_code.with_extension("stdout");
-        let target_path = path
-            // In the build it has the path test instead of tests
-            .strip_prefix("tests/")
-            .expect("Path didn't start with tests/");
-        // FIXME: get the actual triplet
-        let actual_path = self
-            .rust_path
-            .join("build/x86_64-unknown-linux-gnu/test")
-            .join(target_path)
-            .with_extension("")
-            .join(path.file_stem().unwrap());
-        let actual_stderr = actual_path.with_extension("stderr");
-        let actual_stdout = actual_path.with_extension("stdout");
-
-        let Ok(test_code) = read_to_string(&test_code) else {
-            // TODO: Handle this
-            self.cached_streams.stderr = CachedData::Missing;
-            self.cached_streams.stdout = CachedData::Missing;
-            return;
+    /// Reads the current test on a worker if it isn't loaded yet, so that drawing doesn't wait on
+    /// slow filesystems, or waits for it if `wait`. Returns whether it's loaded.
+    pub fn poll_loading(&mut self, wait: bool) -> bool {
+        if !matches!(self.cached_streams.stderr, CachedData::Unloaded) {
+            return true;
+        }
+        let test = self.current_path();
+        if let Some(streams) = self.preload.as_mut().and_then(|p| p.take(test)) {
+            self.loading = None;
+            self.load_curr_data(streams);
+            return true;
+        }
+        let loader = match self.loading.take() {
+            Some(loader) if loader.path() == test => loader,
+            _ => Loader::start(self.rust_path.clone(), test),
         };
-        let expected_stderr = read_to_string(&expected_stderr_path).ok();
-        let expected_stdout = read_to_string(&expected_stdout_path).ok();
-        let actual_stderr = read_to_string(actual_stderr).ok();
-        let actual_stdout = read_to_string(actual_stdout).ok();
+        match loader.get(wait) {
+            Some(streams) => {
+                self.load_curr_data(streams);
+                true
+            }
+            None => {
+                self.loading = Some(loader);
+                false
+            }
+        }
+    }
 
-        if expected_stderr.is_some() || actual_stderr.is_some() && expected_stderr != actual_stderr
+    /// Takes the `streams` of the current test and derives what's shown about them.
+    fn load_curr_data(&mut self, streams: CachedStreams) {
+        let test = self.current_path();
+        self.cached_streams = streams;
+        self.diffs.clear();
+        // A test is shown from its first output that differs, which may not be the stderr.
+        let present = self.cached_streams.present();
+        if !present
+            .iter()
+            .any(|data| data.stream == self.current_stream)
         {
-            let actual = actual_stderr.unwrap_or_default();
-            let expect = expected_stderr.unwrap_or_default();
-            let stream = TestData {
-                test_code: test_code.clone(),
-                actual,
-                expect,
-                stream: Stream::Stderr,
-                test_name: path_str.to_owned(),
-                rustc_args: "TODO".to_owned(),
-                expected_path: expected_stderr_path,
-                // TODO: Where do I get this info
-                // number_of_errs: 1,
-            };
-            self.cached_streams.stderr = CachedData::Present(stream);
+            if let Some(data) = present.first() {
+                self.current_stream = data.stream;
+            }
+        }
+        if let Some(similarity) = self.cached_streams.similarity() {
+            self.similarities.insert(test, similarity);
+        }
+        self.stale = self
+            .cached_streams
+            .present()
+            .iter()
+            .filter_map(|data| {
+                let reason = data.staleness(&self.rust_path, self.run_started)?;
+                tracing::info!(test = data.test_name, stream = ?data.stream, reason, "stale");
+                Some((data.stream, reason))
+            })
+            .collect();
+        self.update_metadata();
+    }
+
+    /// Gathers the metadata of the current test if the panel is shown.
+    fn update_metadata(&mut self) {
+        self.metadata = if self.config.show_metadata {
+            Metadata::gather(&self.rust_path, &self.cached_streams)
+        } else {
+            None
+        };
+    }
+
+    /// Reads the current test again, dropping everything that was derived from it.
+    pub fn reload(&mut self) {
+        self.cached_streams = Default::default();
+        // A read that was started before the change may have missed it.
+        self.loading = None;
+        self.external_diff = None;
+        self.colored_output = None;
+        self.last_change = None;
+    }
+
+    /// The path of the current test. `paths` is never empty, but `current_test` is kept in it in
+    /// case the list shrinks.
+    pub fn current_path(&self) -> &'static str {
+        self.paths[self.current_test.min(self.paths.len() - 1)]
+    }
+
+    /// Goes to the next test, or ends the review after the last one unless `wrap_around`.
+    pub fn advance_test(&mut self) {
+        self.session.skip(self.current_path());
+        self.reset_scroll();
+        self.current_stream = Stream::Stderr;
+        self.cached_streams = Default::default();
+        if self.current_test + 1 < self.paths.len() {
+            self.current_test += 1;
+        } else if self.wrap_around {
+            self.current_test = 0;
         } else {
-            self.cached_streams.stderr = CachedData::Missing;
+            self.end_review();
         }
+    }
 
-        if expected_stdout.is_some() || actual_stdout.is_some() && expected_stdout != actual_stdout
-        {
-            let actual = actual_stdout.unwrap_or_default();
-            let expect = expected_stdout.unwrap_or_default();
-            let stream = TestData {
-                test_code: test_code.clone(),
-                actual,
-                expect,
-                stream: Stream::Stdout,
-                test_name: path_str.to_owned(),
-                rustc_args: "TODO".to_owned(),
-                expected_path: expected_stdout_path,
-                // TODO: Where do I get this info
-                // number_of_outs: 1,
-            };
-            self.cached_streams.stdout = CachedData::Present(stream);
+    /// Leaves the TUI when there's nothing more to review, from anywhere.
+    fn end_review(&mut self) -> ! {
+        self.current_test = self.current_test.min(self.paths.len() - 1);
+        let saved = self.save_session();
+        ratatui::restore();
+        if let Err(e) = saved {
+            eprintln!("Couldn't save the session: {e}");
+        }
+        self.finish();
+        exit(self.exit_code().into());
+    }
+
+    /// 1 if tests that `fail_on` cares about weren't resolved.
+    pub fn exit_code(&self) -> u8 {
+        let unresolved = self.session.unresolved(&self.paths, self.fail_on);
+        if unresolved > 0 {
+            eprintln!("{unresolved} tests weren't resolved");
+            1
         } else {
-            self.cached_streams.stdout = CachedData::Missing;
-            // if matches!(self.cached_streams.stderr, CachedData::Missing) {
-            //     unreachable!("what");
-            // }
+            0
         }
     }
 
-    pub fn advance_test(&mut self) {
+    /// Wraps up after the TUI is gone.
+    fn finish(&self) {
+        if let Some(path) = &self.export_path {
+            match self.session.export(path) {
+                Ok(()) => println!(
+                    "Exported {} marks to {}",
+                    self.session.statuses.len(),
+                    path.display()
+                ),
+                Err(e) => eprintln!("Couldn't export the marks to {}: {e}", path.display()),
+            }
+        }
+        if !self.commit {
+            return;
+        }
+        match self.commit_blessed() {
+            Ok(Some(summary)) => println!("{summary}"),
+            Ok(None) => println!("Nothing was blessed, not committing"),
+            Err(e) => eprintln!("Couldn't commit the blessed tests: {e}"),
+        }
+    }
+
+    /// Commits the expected outputs of the blessed tests with a message listing them.
+    fn commit_blessed(&self) -> io::Result<Option<String>> {
+        let blessed: Vec<_> = self
+            .session
+            .statuses
+            .iter()
+            .filter(|(_, &status)| status == Status::Blessed)
+            .map(|(test, _)| test.as_str())
+            .collect();
+        let paths: Vec<_> = blessed
+            .iter()
+            .flat_map(|test| {
+                let test = native_path(test);
+                [test.with_extension("stderr"), test.with_extension("stdout")]
+            })
+            .filter(|path| remote::exists(&self.rust_path.join(path)))
+            .collect();
+        if paths.is_empty() {
+            return Ok(None);
+        }
+
+        let mut message = match blessed.len() {
+            1 => "Bless 1 UI test\n".to_owned(),
+            n => format!("Bless {n} UI tests\n"),
+        };
+        for test in &blessed {
+            message.push_str(&format!("\n- {test}"));
+        }
+        let paths: Vec<_> = paths.iter().map(PathBuf::as_path).collect();
+        let hash = git::commit(&self.rust_path, &paths, &message)?;
+        let subject = message.lines().next().unwrap_or_default();
+        Ok(Some(format!("Committed {hash}: {subject}")))
+    }
+
+    pub fn save_session(&mut self) -> Result<()> {
+        let Some(path) = &self.session_path else {
+            return Ok(());
+        };
+        self.session.current = Some(self.current_path().to_owned());
+        if self.saved_session.as_ref() == Some(&self.session) {
+            return Ok(());
+        }
+        // Not tried again until it changes, even if saving fails.
+        self.saved_session = Some(self.session.clone());
+        self.session.save(path)
+    }
+
+    /// Jumps to the test at `index` in `paths`.
+    pub fn go_to_test(&mut self, index: usize) {
         self.reset_scroll();
         self.current_stream = Stream::Stderr;
-        self.current_test += 1;
+        self.current_test = index.min(self.paths.len() - 1);
         self.cached_streams = Default::default();
-        if self.current_test == self.paths.len() {
-            ratatui::restore();
-            exit(0);
+    }
+
+    /// Reorders the tests, keeping the current test, history and bookmarks pointing to the same
+    /// tests.
+    pub fn sort_tests(&mut self, order: SortOrder) {
+        let paths = self.paths.clone();
+        let current = self.current_path();
+
+        match order {
+            SortOrder::Run => {
+                let run_index: HashMap<_, _> = self
+                    .run_order
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| (*p, i))
+                    .collect();
+                self.paths.sort_by_key(|p| run_index.get(p).copied());
+            }
+            SortOrder::Path => self.paths.sort_unstable(),
+            SortOrder::Directory => self.paths.sort_by(|a, b| {
+                let (a, b) = (Path::new(a), Path::new(b));
+                (a.parent(), a.file_name()).cmp(&(b.parent(), b.file_name()))
+            }),
+            SortOrder::DiffSize => {
+                let rust_path = &self.rust_path;
+                let sizes: HashMap<_, _> = self
+                    .paths
+                    .par_iter()
+                    .map(|&p| (p, CachedStreams::load_or_missing(rust_path, p).diff_size()))
+                    .collect();
+                let failure_kinds = &self.failure_kinds;
+                self.paths
+                    .sort_by_key(|p| (failure_kinds.contains_key(p), sizes[p]));
+            }
+            SortOrder::Duration => {
+                let time = |p: &&str| self.exec_times.get(p).copied().unwrap_or_default();
+                let mut paths = mem::take(&mut self.paths);
+                paths.sort_by(|a, b| time(b).total_cmp(&time(a)));
+                self.paths = paths;
+            }
+        }
+        self.sort_order = order;
+
+        let new_index: HashMap<_, _> = self
+            .paths
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (*p, i))
+            .collect();
+        let remap = |i: &mut usize| {
+            if let Some(path) = paths.get(*i) {
+                *i = new_index[path];
+            }
+        };
+        self.current_test = new_index[current];
+        self.history.iter_mut().for_each(remap);
+        self.future.iter_mut().for_each(remap);
+        self.bookmarks.values_mut().for_each(remap);
+    }
+
+    /// Like [`App::go_to_test`], but remembers where we came from.
+    pub fn jump_to_test(&mut self, index: usize) {
+        if index == self.current_test {
+            return;
+        }
+        self.history.push(self.current_test);
+        self.future.clear();
+        self.go_to_test(index);
+    }
+
+    pub fn jump_back(&mut self) {
+        if let Some(index) = self.history.pop() {
+            self.future.push(self.current_test);
+            self.go_to_test(index);
+        }
+    }
+
+    pub fn jump_forward(&mut self) {
+        if let Some(index) = self.future.pop() {
+            self.history.push(self.current_test);
+            self.go_to_test(index);
         }
     }
 
     pub fn reset_scroll(&mut self) {
         self.scroll_pos_code = 0;
         self.scroll_pos_diff = 0;
+        self.highlighted_code_line = None;
+        self.code_tab = 0;
+        self.scroll_to_first_hunk = true;
+    }
+
+    /// Shows the next output of the test that differs, the first after the last.
+    pub fn next_stream(&mut self) {
+        self.step_stream(1);
+    }
+
+    /// Shows the previous output of the test that differs, the last before the first.
+    pub fn previous_stream(&mut self) {
+        self.step_stream(-1);
+    }
+
+    fn step_stream(&mut self, step: isize) {
+        let streams: Vec<_> = self
+            .cached_streams
+            .present()
+            .iter()
+            .map(|data| data.stream)
+            .collect();
+        let Some(i) = streams.iter().position(|&s| s == self.current_stream) else {
+            return;
+        };
+        let next = streams[(i as isize + step).rem_euclid(streams.len() as isize) as usize];
+        if next != self.current_stream {
+            self.reset_scroll();
+            self.current_stream = next;
+        }
+    }
+
+    /// Shows `stream` of the test, if it differs.
+    pub fn show_stream(&mut self, stream: Stream) {
+        if stream == self.current_stream {
+            return;
+        }
+        let present = self.cached_streams.present();
+        if present.iter().any(|data| data.stream == stream) {
+            self.reset_scroll();
+            self.current_stream = stream;
+        } else {
+            self.message = Some(format!("The {} of this test doesn't differ", stream.name()));
+        }
     }
 
+    /// Shows the next output of the test that differs, or the next test after the last one.
     pub fn advance_stream(&mut self) {
         self.reset_scroll();
-        match self.current_stream {
-            Stream::Stderr => self.current_stream = Stream::Stdout,
-            Stream::Stdout => self.advance_test(),
+        let next = self
+            .cached_streams
+            .present()
+            .iter()
+            .map(|data| data.stream)
+            .skip_while(|&stream| stream != self.current_stream)
+            .nth(1);
+        match next {
+            Some(stream) => self.current_stream = stream,
+            None => self.advance_test(),
         }
     }
 
+    /// Goes to the previous test, the last one from the first if `wrap_around`.
     pub fn previous_test(&mut self) {
-        self.reset_scroll();
-        self.current_stream = Stream::Stderr;
-        self.current_test = self.current_test.saturating_sub(1);
-        self.cached_streams = Default::default();
+        let index = match self.current_test.min(self.paths.len() - 1) {
+            0 if self.wrap_around => self.paths.len() - 1,
+            0 => {
+                self.message = Some("This is the first test".to_owned());
+                return;
+            }
+            i => i - 1,
+        };
+        self.go_to_test(index);
     }
 
+    /// The current stream of the current test, loading it if needed. Tests without a differing
+    /// output are skipped, and the review ends if none has one.
     pub fn request_curr_test(&mut self) -> &TestData {
-        match self.current_stream {
-            // We do this one first, then the other
-            Stream::Stderr => match self.cached_streams.stderr {
-                CachedData::Unloaded => {
-                    self.load_curr_data();
-                    self.request_curr_test()
-                }
-                CachedData::Missing => {
-                    self.current_stream = Stream::Stdout;
-                    self.request_curr_test()
-                }
-                CachedData::Present(ref test_data) => test_data,
-            },
-            Stream::Stdout => match self.cached_streams.stdout {
-                CachedData::Missing => {
-                    self.advance_test();
-                    self.request_curr_test()
-                }
-                CachedData::Present(ref test_data) => test_data,
-                CachedData::Unloaded => unreachable!(),
-            },
+        let mut skipped = 0;
+        loop {
+            self.poll_loading(true);
+            if let Some(data) = self.cached_streams.get(self.current_stream) {
+                tracing::trace!(test = data.test_name, "current test");
+                break;
+            }
+            skipped += 1;
+            if skipped > self.paths.len() {
+                self.end_review();
+            }
+            self.advance_test();
         }
+        self.cached_streams
+            .get(self.current_stream)
+            .expect("found in the loop")
+    }
+
+    /// The current test and the cache of its diffs.
+    fn request_curr_diffs(&mut self) -> (&TestData, &mut DiffCache) {
+        self.request_curr_test();
+        let data = self
+            .cached_streams
+            .get(self.current_stream)
+            .expect("just requested");
+        (data, &mut self.diffs)
     }
 
     /// Run the application's main loop.
-    pub fn run(mut self, mut terminal: DefaultTerminal) -> Result<()> {
+    pub fn run(mut self, mut terminal: DefaultTerminal) -> Result<ExitCode> {
         self.running = true;
+        self.scroll_to_first_hunk = true;
         while self.running {
-            terminal.draw(|frame| self.draw(frame))?;
+            if self.poll_loading(false) && mem::take(&mut self.scroll_to_first_hunk) {
+                if let Some(&row) = self.curr_hunk_rows().first() {
+                    self.scroll_pos_diff = row as u16;
+                }
+            }
+            let path = self.current_path();
+            timings::time(Phase::Draw, path, || {
+                terminal.draw(|frame| self.draw(frame))
+            })?;
             self.handle_crossterm_events()?;
+            self.reload_if_changed();
+            if let Some(preload) = &mut self.preload {
+                self.similarities.extend(preload.poll());
+            }
+            self.poll_pending_popup();
+            self.poll_blessing();
+            if let Some(suspend) = self.suspend.take() {
+                ratatui::restore();
+                match suspend {
+                    Suspend::Edit(path, line) => {
+                        if let Err(e) = edit(&path, line) {
+                            self.message = Some(format!("Couldn't run the editor: {e}"));
+                        }
+                    }
+                    Suspend::Merge => self.merge(),
+                }
+                terminal = ratatui::init();
+                terminal.clear()?;
+                self.reload();
+            }
+            if let Err(e) = self.save_session() {
+                self.message = Some(format!("Couldn't save the session: {e}"));
+            }
         }
-        Ok(())
+        ratatui::restore();
+        self.finish();
+        Ok(ExitCode::from(self.exit_code()))
     }
 
     /// Renders the user interface.
@@ -216,72 +677,558 @@ impl App {
             let block = Block::bordered().title_top(line);
             Paragraph::new(text).block(block)
         }
+        let theme = self.config.theme();
+        // Like `mk_paragraph`, with the border highlighted if the pane has the focus.
+        let mk_pane = |title, text, focused| {
+            let line = Line::from(title).bold().centered();
+            let border = if focused {
+                theme.focused_border
+            } else {
+                theme.border
+            };
+            Paragraph::new(text).block(Block::bordered().title_top(line).border_style(border))
+        };
+        fn wrap_if(paragraph: Paragraph<'_>, wrap: bool) -> Paragraph<'_> {
+            if wrap {
+                paragraph.wrap(Wrap { trim: false })
+            } else {
+                paragraph
+            }
+        }
+
+        if !self.poll_loading(false) {
+            self.draw_loading(frame);
+            return;
+        }
+        // Loading can move to another test, settle on one before looking at the session.
+        let diff_mode = self.config.diff_mode;
+        let data = self.request_curr_test();
+        let too_big = too_big_for(&data.expect, &data.actual, diff_mode);
+        let binary = data.is_binary();
+        match self.config.show_mode {
+            ShowMode::External => self.update_external_diff(),
+            ShowMode::Colored => self.update_colored_output(),
+            ShowMode::Info => self.update_last_change(),
+            _ => {}
+        }
+        let status = self
+            .session
+            .statuses
+            .get(self.current_path())
+            .map(|s| format!(" [{}]", s.name()))
+            .unwrap_or_default();
+        let exec_time = self
+            .exec_times
+            .get(self.current_path())
+            .map(|t| format!(" in {t:.2}s"))
+            .unwrap_or_default();
+        let other_failed = match self.other_failed.len() {
+            0 => String::new(),
+            n => format!(" ({n} non-UI)"),
+        };
+        let sort = match self.sort_order {
+            SortOrder::Run => String::new(),
+            order => format!(" (by {})", order.name()),
+        };
+        let git_head = self
+            .git_head
+            .as_deref()
+            .map(|head| format!("[{head}] "))
+            .unwrap_or_default();
+
+        let count = |status| self.session.count_among(&self.paths, status);
+        let (blessed, reviewed, skipped, deferred) = (
+            count(Status::Blessed),
+            count(Status::Reviewed),
+            count(Status::Skipped),
+            count(Status::Deferred),
+        );
+        // Deferred tests still need to be looked at.
+        let done = blessed + reviewed + skipped;
+        let progress = match self.preload.as_ref().and_then(Preload::progress) {
+            Some((loaded, total)) => LineGauge::default()
+                .ratio(loaded as f64 / total as f64)
+                .label(format!("loading {loaded}/{total}"))
+                .filled_style(theme.loading),
+            None => LineGauge::default()
+                .ratio(done as f64 / self.paths.len() as f64)
+                .label(format!(
+                    "reviewed {done}/{}, blessed {blessed}, skipped {skipped}, deferred {deferred}",
+                    self.paths.len()
+                ))
+                .filled_style(theme.progress),
+        }
+        .unfilled_style(theme.unfilled);
 
-        let layout = FullLayout::new(&self.config, frame.area());
-        let current_test = self.current_test;
-        let total_tests = self.stats.failed;
+        let banner = self
+            .failure_kinds
+            .get(self.current_path())
+            .map(|kind| match kind {
+                FailureKind::Timeout => {
+                    Line::styled(" timed out, blessing is disabled ", theme.warning)
+                }
+                FailureKind::Panic { message } => Line::styled(
+                    format!(
+                        " compiletest panicked{}, blessing is disabled ",
+                        message
+                            .as_deref()
+                            .map(|m| format!(": {m}"))
+                            .unwrap_or_default()
+                    ),
+                    theme.error,
+                ),
+            })
+            .or_else(|| {
+                let (_, reason) = self
+                    .stale
+                    .iter()
+                    .find(|(stream, _)| *stream == self.current_stream)?;
+                Some(Line::styled(
+                    format!(" possibly stale: {reason} "),
+                    theme.warning,
+                ))
+            })
+            .or_else(|| {
+                binary.then(|| {
+                    Line::from(" binary output, comparing hexdumps ")
+                        .black()
+                        .on_yellow()
+                })
+            })
+            .or_else(|| {
+                too_big.then(|| {
+                    Line::from(format!(
+                        " line diff: the outputs are too big for a {} diff ",
+                        self.config.diff_mode.name()
+                    ))
+                    .black()
+                    .on_yellow()
+                })
+            });
+        let layout = FullLayout::new(
+            &self.config,
+            frame.area(),
+            self.search.is_some()
+                || self.command.is_some()
+                || self.message.is_some()
+                || self.blessing.is_some(),
+            banner.is_some(),
+        );
+        let inner_height = |area: Rect| area.height.saturating_sub(2);
+        self.diff_height = layout.diff_show.diff_area().map_or(0, inner_height);
+        self.code_height = layout.diff_show.code_area().map_or(0, inner_height);
+        // 1-based so that it matches `:N`.
+        let current_test = self.current_test + 1;
+        let total_tests = self.paths.len();
+        let failed = self.stats.failed;
         let ok = self.stats.passed;
         let ignored = self.stats.ignored;
-        let diff_mode = self.config.diff_mode;
+        let diff_mode = if too_big { DiffMode::Line } else { diff_mode };
         let scroll_code = (self.scroll_pos_code, 0);
         let scroll_diff = (self.scroll_pos_diff, 0);
-        let diff_mode = self.config.diff_mode;
+        let highlighted_code_line = self.highlighted_code_line;
+        let code_tab = self.code_tab;
+        let last_change = self.last_change.as_ref().and_then(|(_, c)| c.clone());
+        let resolved_paths =
+            resolved_paths_text(&self.cached_streams.resolved, self.current_stream);
+        let show_ice_diff = self.show_ice_diff;
+        let wrap = self.config.wrap;
+        let tab_width = self.config.tab_width;
+        let side_by_side = self.config.show_mode.is_side_by_side();
+        let code_focused = self.focused_pane() == Pane::Code;
+        let query = self
+            .search
+            .as_ref()
+            .map(|s| s.query.clone())
+            .unwrap_or_default();
 
         if let Some(rect) = layout.help_bar {
-            frame.render_widget(Paragraph::new(self.config.help_string()).centered(), rect);
+            match (&self.search, &self.command, &self.message) {
+                (Some(search), _, _) => frame.render_widget(Paragraph::new(search.prompt()), rect),
+                (_, Some(command), _) => {
+                    frame.render_widget(Paragraph::new(format!(":{command}")), rect)
+                }
+                (_, _, Some(message)) => {
+                    frame.render_widget(Paragraph::new(message.as_str()).centered(), rect)
+                }
+                _ if self.blessing.is_some() => {
+                    let status = "running the bless hooks…".dim();
+                    frame.render_widget(Paragraph::new(status).centered(), rect)
+                }
+                _ => {
+                    let help = self.config.help_line(self.focused_pane());
+                    frame.render_widget(Paragraph::new(help).centered(), rect)
+                }
+            }
         }
 
+        let (data, diffs) = self.request_curr_diffs();
+        let (code_title, code) = data.code(code_tab);
         let TestData {
             actual,
-            expect,
+            expect: _,
             stream,
             test_name,
             rustc_args,
             test_code,
-            expected_path,
-        } = self.request_curr_test();
+            expected_path: _,
+            actual_path: _,
+            aux: _,
+            raw_actual: _,
+        } = data;
 
-        let top_bar_text = format!("Showing {test_name} {stream:?}. {current_test}/{total_tests}.  Ok: {ok}, Ignored: {ignored}");
+        let Summary {
+            similarity: percent_similar,
+            classification,
+            stats:
+                DiffStats {
+                    inserted,
+                    deleted,
+                    moved,
+                    hunks,
+                },
+            rows: diff_rows,
+            hunk_starts,
+        } = diffs.summary(data, side_by_side).clone();
+        let code_rows = code.lines().count();
+        let moved = if moved > 0 {
+            format!(" ~{moved}")
+        } else {
+            String::new()
+        };
+        // Tabs are expanded here as terminals don't agree on how wide they are.
+        let highlight = |text| search::highlight(expand_tabs(text, tab_width), &query, &theme);
+        let code_text = highlight(code_text(code, highlighted_code_line, &theme));
+        let editions = directives::editions(&directives::parse(test_code)).join("/");
+        let top_bar_text = format!("{git_head}Showing {test_name} (edition {editions}){status}{exec_time} {stream:?} (+{inserted}/-{deleted}{moved} in {hunks} hunks, {percent_similar:.0}% similar, {classification}). {current_test}/{total_tests}{sort}.  Failed: {failed}{other_failed}, Ok: {ok}, Ignored: {ignored}");
         frame.render_widget(Paragraph::new(top_bar_text).centered(), layout.top_bar);
+        frame.render_widget(progress, layout.progress);
+        if let (Some(banner), Some(area)) = (banner, layout.banner) {
+            frame.render_widget(Paragraph::new(banner.centered()).style(theme.banner), area);
+        }
+
+        // Marks the lines cut off in a pane, the code is never wrapped.
+        let mut cut_off = vec![];
+        let mut mark =
+            |frame: &mut Frame, pane, title: &str, area, text: &Text, scroll: (u16, u16)| {
+                if wrap && pane == Pane::Diff {
+                    return;
+                }
+                let lines = mark_cut_off(frame, area, text, scroll.0, &theme);
+                if (pane == Pane::Code) == code_focused {
+                    cut_off.extend(lines.into_iter().map(|line| format!("{title}: {line}")));
+                }
+            };
 
-        match layout.diff_show {
-            DiffShow::SideBySide { code, lhs, rhs } => {
-                let (expect, actual) = diff_vertical(expect, actual, diff_mode);
+        let ice = ice::detect(actual).filter(|_| !show_ice_diff);
+        let showing_ice = ice.is_some();
+        if let (Some(ice), Some(area)) = (ice, layout.diff_show.diff_area()) {
+            if let Some(code) = layout.diff_show.code_area() {
                 frame.render_widget(
-                    mk_paragraph("code", test_code.as_str()).scroll(scroll_code),
+                    mk_pane(code_title.as_str(), code_text.clone(), code_focused)
+                        .scroll(scroll_code),
                     code,
                 );
-                frame.render_widget(mk_paragraph("expected", expect).scroll(scroll_diff), lhs);
-                frame.render_widget(mk_paragraph("actual", actual).scroll(scroll_diff), rhs);
-            }
-            DiffShow::SideBySideOnly { rhs, lhs } => {
-                let (expect, actual) = diff_vertical(expect, actual, diff_mode);
-                frame.render_widget(mk_paragraph("expected", expect).scroll(scroll_diff), lhs);
-                frame.render_widget(mk_paragraph("actual", actual).scroll(scroll_diff), rhs);
-            }
-            DiffShow::Vertical { code, diff } => {
-                let tx_diff = diff_horizontal(expect, actual, diff_mode);
-                frame.render_widget(
-                    mk_paragraph("code", test_code.as_str()).scroll(scroll_code),
+                mark(
+                    frame,
+                    Pane::Code,
+                    &code_title,
                     code,
+                    &code_text,
+                    scroll_code,
                 );
-                frame.render_widget(mk_paragraph("diff", tx_diff).scroll(scroll_diff), diff);
             }
-            DiffShow::VerticalOnly { diff } => {
-                let tx_diff = diff_horizontal(expect, actual, diff_mode);
-                frame.render_widget(mk_paragraph("diff", tx_diff).scroll(scroll_diff), diff);
-            }
-            DiffShow::RustcArgs { args, oneline } => {
-                // This could be done inline but idc.
-                let text = if oneline {
-                    rustc_args.to_owned()
-                } else {
-                    rustc_args.replace(' ', "\n")
-                };
+            frame.render_widget(
+                mk_pane(
+                    "internal compiler error (i: show the diff)",
+                    ice.to_text(),
+                    !code_focused,
+                )
+                .scroll(scroll_diff),
+                area,
+            );
+        } else {
+            match layout.diff_show {
+                DiffShow::SideBySide { code, lhs, rhs } => {
+                    let (expect, actual) = diffs.vertical(data, diff_mode, &theme);
+                    let expect = highlight(expect);
+                    let actual = highlight(actual);
+                    frame.render_widget(
+                        mk_pane(code_title.as_str(), code_text.clone(), code_focused)
+                            .scroll(scroll_code),
+                        code,
+                    );
+                    mark(
+                        frame,
+                        Pane::Code,
+                        &code_title,
+                        code,
+                        &code_text,
+                        scroll_code,
+                    );
+                    frame.render_widget(
+                        wrap_if(
+                            mk_pane("expected", expect.clone(), !code_focused).scroll(scroll_diff),
+                            wrap,
+                        ),
+                        lhs,
+                    );
+                    mark(frame, Pane::Diff, "expected", lhs, &expect, scroll_diff);
+                    frame.render_widget(
+                        wrap_if(
+                            mk_pane("actual", actual.clone(), !code_focused).scroll(scroll_diff),
+                            wrap,
+                        ),
+                        rhs,
+                    );
+                    mark(frame, Pane::Diff, "actual", rhs, &actual, scroll_diff);
+                }
+                DiffShow::SideBySideOnly { rhs, lhs } => {
+                    let (expect, actual) = diffs.vertical(data, diff_mode, &theme);
+                    let expect = highlight(expect);
+                    let actual = highlight(actual);
+                    frame.render_widget(
+                        wrap_if(
+                            mk_pane("expected", expect.clone(), !code_focused).scroll(scroll_diff),
+                            wrap,
+                        ),
+                        lhs,
+                    );
+                    mark(frame, Pane::Diff, "expected", lhs, &expect, scroll_diff);
+                    frame.render_widget(
+                        wrap_if(
+                            mk_pane("actual", actual.clone(), !code_focused).scroll(scroll_diff),
+                            wrap,
+                        ),
+                        rhs,
+                    );
+                    mark(frame, Pane::Diff, "actual", rhs, &actual, scroll_diff);
+                }
+                DiffShow::Vertical { code, diff } => {
+                    let tx_diff = highlight(diffs.horizontal(data, diff_mode, &theme));
+                    frame.render_widget(
+                        mk_pane(code_title.as_str(), code_text.clone(), code_focused)
+                            .scroll(scroll_code),
+                        code,
+                    );
+                    mark(
+                        frame,
+                        Pane::Code,
+                        &code_title,
+                        code,
+                        &code_text,
+                        scroll_code,
+                    );
+                    frame.render_widget(
+                        wrap_if(
+                            mk_pane("diff", tx_diff.clone(), !code_focused).scroll(scroll_diff),
+                            wrap,
+                        ),
+                        diff,
+                    );
+                    mark(frame, Pane::Diff, "diff", diff, &tx_diff, scroll_diff);
+                }
+                DiffShow::VerticalOnly { diff } => {
+                    let tx_diff = highlight(diffs.horizontal(data, diff_mode, &theme));
+                    frame.render_widget(
+                        wrap_if(
+                            mk_pane("diff", tx_diff.clone(), !code_focused).scroll(scroll_diff),
+                            wrap,
+                        ),
+                        diff,
+                    );
+                    mark(frame, Pane::Diff, "diff", diff, &tx_diff, scroll_diff);
+                }
+                DiffShow::RustcArgs { args, oneline } => {
+                    // This could be done inline but idc.
+                    let text = if oneline {
+                        rustc_args.to_owned()
+                    } else {
+                        rustc_args.replace(' ', "\n")
+                    };
 
-                frame.render_widget(mk_paragraph("rustc arguments", text.as_str()), args);
-            }
-        };
+                    frame.render_widget(mk_paragraph("rustc arguments", text.as_str()), args);
+                }
+                DiffShow::External { area } => {
+                    let text = self
+                        .external_diff
+                        .as_ref()
+                        .map(|diff| diff.text.clone())
+                        .unwrap_or_default();
+                    let title = self.diff_tool.as_deref().unwrap_or_default();
+                    frame.render_widget(mk_paragraph(title, text).scroll(scroll_diff), area);
+                }
+                DiffShow::OtherFailures { area } => {
+                    let text = if self.other_failed.is_empty() {
+                        Text::from("Only UI tests failed")
+                    } else {
+                        self.other_failed
+                            .iter()
+                            .map(|&name| Line::from(name))
+                            .collect::<Vec<_>>()
+                            .into()
+                    };
+                    let title = format!("{} non-UI failures", self.other_failed.len());
+                    frame.render_widget(mk_paragraph(&title, text).scroll(scroll_diff), area);
+                }
+                DiffShow::Colored { area } => {
+                    let text = self
+                        .colored_output
+                        .as_ref()
+                        .map(|output| output.text.clone())
+                        .unwrap_or_default();
+                    frame.render_widget(
+                        mk_paragraph("rustc output", text).scroll(scroll_diff),
+                        area,
+                    );
+                }
+                DiffShow::Ignored { area } => {
+                    let text: Text = if self.ignored.is_empty() {
+                        Text::from("No tests were ignored")
+                    } else {
+                        self.ignored
+                            .iter()
+                            .map(|ignored| match &ignored.reason {
+                                Some(reason) => Line::from(vec![
+                                    ignored.name.into(),
+                                    format!(": {reason}").dim(),
+                                ]),
+                                None => Line::from(ignored.name),
+                            })
+                            .collect::<Vec<_>>()
+                            .into()
+                    };
+                    let title = format!("{} ignored tests", self.ignored.len());
+                    frame.render_widget(mk_paragraph(&title, text).scroll(scroll_diff), area);
+                }
+                DiffShow::Annotations { area } => {
+                    let text = diffs.annotations(data, &theme);
+                    frame
+                        .render_widget(mk_paragraph("annotations", text).scroll(scroll_diff), area);
+                }
+                DiffShow::Info { area } => {
+                    let last_change = match last_change {
+                        Some(commit) => Line::from(vec![
+                            "Expected output last changed in ".into(),
+                            commit.bold(),
+                        ]),
+                        None => Line::from("The expected output isn't committed").dim(),
+                    };
+                    let mut text = Text::from(vec![last_change, Line::default()]);
+                    text.extend(resolved_paths);
+                    text.push_line(Line::default());
+                    text.extend(directives::to_text(&directives::parse(test_code)));
+                    frame.render_widget(mk_paragraph("info", text).scroll(scroll_diff), area);
+                }
+            };
+        }
+        self.cut_off = cut_off;
+
+        if let Some(area) = layout.diff_show.diff_area().filter(|_| !showing_ice) {
+            render_scrollbar(
+                frame,
+                area,
+                diff_rows,
+                scroll_diff.0 as usize,
+                &hunk_starts,
+                &theme,
+            );
+        }
+        if let Some(area) = layout.diff_show.code_area() {
+            render_scrollbar(frame, area, code_rows, scroll_code.0 as usize, &[], &theme);
+        }
+        if let Some(area) = layout.diff_show.diff_area().filter(|_| !showing_ice) {
+            let streams: Vec<_> = self
+                .cached_streams
+                .present()
+                .iter()
+                .map(|data| data.stream)
+                .collect();
+            render_stream_tabs(frame, area, &streams, self.current_stream);
+        }
+        if let Some(area) = layout.metadata {
+            let text = match &self.metadata {
+                Some(metadata) => metadata.to_text(),
+                None => Text::from("No differing output").dim(),
+            };
+            frame.render_widget(mk_paragraph("metadata", text), area);
+        }
+
+        if let Some(editor) = &self.editor {
+            let area = layout
+                .diff_show
+                .diff_area()
+                .unwrap_or_else(|| popup_area(frame.area(), 80, 80));
+            editor.render(frame, area, &theme);
+        }
+
+        if let Some(picker) = &self.picker {
+            let area = popup_area(frame.area(), 80, 80);
+            picker.render(frame, area, &self.paths, self.current_test);
+        }
+
+        if self.show_tree {
+            let area = popup_area(frame.area(), 80, 80);
+            self.tree.loading = self
+                .preload
+                .as_ref()
+                .is_some_and(|p| p.progress().is_some());
+            self.tree.render(
+                frame,
+                area,
+                &self.paths,
+                self.current_test,
+                &self.session,
+                &self.similarities,
+            );
+        }
+
+        if let Some(popup) = &self.popup {
+            let area = popup_area(frame.area(), 80, 80);
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                mk_paragraph(&popup.title, popup.text.as_str())
+                    .wrap(Wrap { trim: false })
+                    .scroll((popup.scroll, 0)),
+                area,
+            );
+        }
+
+        if let Some(confirmation) = &self.confirmation {
+            let area = popup_area(frame.area(), 50, 20);
+            frame.render_widget(Clear, area);
+            let text = Text::from(vec![
+                Line::from(confirmation.question.as_str()),
+                Line::default(),
+                Line::from("[y/n]").bold(),
+            ]);
+            frame.render_widget(
+                mk_paragraph("confirm", text).wrap(Wrap { trim: false }),
+                area,
+            );
+        }
+    }
+
+    /// The top bar and a placeholder for the diff while the current test is read.
+    fn draw_loading(&self, frame: &mut Frame) {
+        let [top_bar, main] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(frame.area());
+        frame.render_widget(
+            Line::from(format!(
+                "Loading {}. {}/{}",
+                self.current_path(),
+                self.current_test + 1,
+                self.paths.len()
+            ))
+            .centered(),
+            top_bar,
+        );
+        frame.render_widget(
+            Paragraph::new("loading…".dim())
+                .centered()
+                .block(Block::bordered()),
+            main,
+        );
     }
 
     /// Reads the crossterm events and updates the state of [`App`].
@@ -289,9 +1236,42 @@ impl App {
     /// If your application needs to perform work in between handling events, you can use the
     /// [`event::poll`] function to check if there are any events available with a timeout.
     fn handle_crossterm_events(&mut self) -> Result<()> {
+        // Wake up now and then to look at the watched files or show the loading progress, and
+        // soon to show the test being loaded.
+        let preloading = self
+            .preload
+            .as_ref()
+            .is_some_and(|preload| preload.progress().is_some());
+        let mut timeout =
+            if self.loading.is_some() || self.pending_popup.is_some() || self.blessing.is_some() {
+                Some(Duration::from_millis(20))
+            } else if self.watch.is_some() || preloading {
+                Some(Duration::from_millis(200))
+            } else {
+                None
+            };
+        // The keys of `--replay` come in between the ones of the user.
+        if let Some(replay) = &mut self.replay {
+            if let Some(key) = replay.next_key() {
+                self.press(key);
+                return Ok(());
+            }
+            if let Some(wait) = replay.wait() {
+                timeout = Some(timeout.map_or(wait, |timeout| timeout.min(wait)));
+            } else {
+                self.replay = None;
+                self.message = Some("The replay is over".to_owned());
+                return Ok(());
+            }
+        }
+        if let Some(timeout) = timeout {
+            if !event::poll(timeout)? {
+                return Ok(());
+            }
+        }
         match event::read()? {
             // it's important to check KeyEventKind::Press to avoid handling key release events
-            Event::Key(key) if key.kind == KeyEventKind::Press => self.on_key_event(key),
+            Event::Key(key) if key.kind == KeyEventKind::Press => self.press(key),
             Event::Mouse(_) => {}
             Event::Resize(_, _) => {}
             _ => {}
@@ -299,71 +1279,564 @@ impl App {
         Ok(())
     }
 
+    fn press(&mut self, key: KeyEvent) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(key);
+        }
+        self.on_key_event(key);
+    }
+
+    /// Opens a popup titled `title` that shows what `compute` returns once it's done on a worker.
+    fn open_pending_popup(
+        &mut self,
+        title: String,
+        compute: impl FnOnce() -> String + Send + 'static,
+    ) {
+        let (sender, text) = mpsc::channel();
+        thread::spawn(move || {
+            // The receiver is gone if another popup was asked for in the meantime.
+            let _ = sender.send(compute());
+        });
+        self.pending_popup = Some((title.clone(), text));
+        self.popup = Some(Popup {
+            title,
+            text: "loading…".to_owned(),
+            scroll: 0,
+        });
+    }
+
+    /// Shows the text of the pending popup once it's computed, unless the popup was closed.
+    fn poll_pending_popup(&mut self) {
+        let Some((title, receiver)) = &self.pending_popup else {
+            return;
+        };
+        let text = match receiver.try_recv() {
+            Ok(text) => text,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => format!("Couldn't compute the {title}"),
+        };
+        if let Some(popup) = &mut self.popup {
+            if popup.title == *title {
+                popup.text = text;
+            }
+        }
+        self.pending_popup = None;
+    }
+
+    /// Finishes blessing a stream once its hooks ran, going on to the next stream or test if
+    /// it's still the one shown.
+    fn poll_blessing(&mut self) {
+        let Some(blessing) = self.blessing.take() else {
+            return;
+        };
+        let outcome = match blessing.outcome.try_recv() {
+            Ok(outcome) => outcome,
+            Err(TryRecvError::Empty) => {
+                self.blessing = Some(blessing);
+                return;
+            }
+            Err(TryRecvError::Disconnected) => BlessOutcome::Failed {
+                title: "bless",
+                error: "The bless hooks panicked".to_owned(),
+            },
+        };
+        let shown = blessing.test == self.current_path() && blessing.stream == self.current_stream;
+        if self.finish_bless(blessing.test, outcome) && shown {
+            self.after_bless();
+        }
+    }
+
+    /// Reloads the current test if `--watch` saw its outputs or the JSON output change.
+    fn reload_if_changed(&mut self) {
+        let Some(watch) = &self.watch else {
+            return;
+        };
+        let changes = watch.changes();
+        let test = native_path(self.current_path());
+        // The actual outputs are `{test}/{test}.stderr` under the build directory.
+        let touches_test =
+            |path: &PathBuf| path.file_stem() == test.file_stem() || path == watch.input();
+        if changes.iter().any(touches_test) {
+            tracing::info!(?changes, "reloading");
+            self.reload();
+            self.message = Some("Reloaded, the outputs changed".to_owned());
+        }
+    }
+
     /// Handles the key events and updates the state of [`App`].
     fn on_key_event(&mut self, key: KeyEvent) {
-        match key.code {
-            KeyCode::Esc | KeyCode::Char('q') => self.quit(),
-            KeyCode::Char('d') => {
-                self.config.diff_mode.rotate_next();
+        self.message = None;
+        if let Some(confirmation) = self.confirmation.take() {
+            if key.code == KeyCode::Char('y') {
+                self.run_action(confirmation.action);
             }
-            KeyCode::Char('r') => match self.config.show_mode {
-                ShowMode::RustcArgs { oneline } => {
-                    self.config.show_mode = self.prev_view;
-                    self.prev_view = ShowMode::RustcArgs { oneline }
+            return;
+        }
+        if let Some(popup) = &mut self.popup {
+            match key.code {
+                KeyCode::Char('j') => popup.scroll += 1,
+                KeyCode::Char('k') => popup.scroll = popup.scroll.saturating_sub(1),
+                _ => self.popup = None,
+            }
+            return;
+        }
+        if let Some(editor) = &mut self.editor {
+            match editor.on_key(key) {
+                editor::Outcome::Continue => {}
+                editor::Outcome::Save => self.save_editor(),
+                editor::Outcome::Quit => {
+                    if editor.dirty {
+                        self.message = Some("Discarded the edits".to_owned());
+                    }
+                    self.editor = None;
                 }
-                _ => {
-                    self.prev_view = self.config.show_mode;
-                    self.config.show_mode = ShowMode::RustcArgs { oneline: false }
+            }
+            return;
+        }
+        if let Some(picker) = &mut self.picker {
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+            match key.code {
+                KeyCode::Esc => self.picker = None,
+                KeyCode::Enter => {
+                    let matches = picker.matches(&self.paths);
+                    if let Some(&test) = matches.get(picker.selected) {
+                        self.jump_to_test(test);
+                    }
+                    self.picker = None;
                 }
-            },
-            KeyCode::Char('o') => {
-                if let ShowMode::RustcArgs { oneline } = self.config.show_mode {
-                    self.config.show_mode = ShowMode::RustcArgs { oneline: !oneline }
+                KeyCode::Down => picker.select_next(picker.matches(&self.paths).len()),
+                KeyCode::Char('n') if ctrl => picker.select_next(picker.matches(&self.paths).len()),
+                KeyCode::Up => picker.select_previous(),
+                KeyCode::Char('p') if ctrl => picker.select_previous(),
+                KeyCode::Backspace => {
+                    picker.query.pop();
+                    picker.selected = 0;
                 }
+                KeyCode::Char(c) => {
+                    picker.query.push(c);
+                    picker.selected = 0;
+                }
+                _ => {}
             }
-            KeyCode::Char('p') => {
-                mem::swap(&mut self.config.show_mode, &mut self.prev_view);
-            }
-            KeyCode::Char('h') => {
-                self.config.hide_help = !self.config.hide_help;
-            }
-            KeyCode::Char('b') => {
-                self.bless();
+            return;
+        }
+        if self.show_tree {
+            let rows = self.tree.rows(&self.paths);
+            let selected = rows.get(self.tree.selected).copied();
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q' | 't') => self.show_tree = false,
+                KeyCode::Down | KeyCode::Char('j') => self.tree.select_next(rows.len()),
+                KeyCode::Up | KeyCode::Char('k') => self.tree.select_previous(),
+                KeyCode::Enter | KeyCode::Char(' ') => match selected {
+                    Some(Row::Dir { dir, .. }) => self.tree.toggle(dir),
+                    Some(Row::Test(index)) => {
+                        self.jump_to_test(index);
+                        self.show_tree = false;
+                    }
+                    None => {}
+                },
+                KeyCode::Char('B') => {
+                    let dir = match selected {
+                        Some(Row::Dir { dir, .. }) => dir,
+                        Some(Row::Test(index)) => tree::dir_of(self.paths[index]),
+                        None => return,
+                    };
+                    if self.confirm_bless {
+                        self.confirmation = Some(Confirmation {
+                            question: format!("Bless every failing test in {dir}/?"),
+                            action: Action::BlessDirectory(dir.to_owned()),
+                        });
+                    } else {
+                        self.bless_directory(dir);
+                    }
+                }
+                _ => {}
             }
-            KeyCode::Char('n') => {
-                self.advance_stream();
+            return;
+        }
+        if let Some(command) = &mut self.command {
+            match key.code {
+                KeyCode::Esc => self.command = None,
+                KeyCode::Enter => {
+                    if let Ok(n) = command.trim().parse::<usize>() {
+                        self.jump_to_test(n.clamp(1, self.paths.len()) - 1);
+                    }
+                    self.command = None;
+                }
+                KeyCode::Backspace => {
+                    command.pop();
+                }
+                KeyCode::Char(c) => command.push(c),
+                _ => {}
             }
-            KeyCode::Char('N') => {
-                self.previous_test();
+            return;
+        }
+        if let Some(pending) = self.pending_key.take() {
+            match (pending, key.code) {
+                ('m', KeyCode::Char(c)) => {
+                    self.bookmarks.insert(c, self.current_test);
+                }
+                ('\'', KeyCode::Char(c)) => {
+                    if let Some(&index) = self.bookmarks.get(&c) {
+                        self.jump_to_test(index);
+                    }
+                }
+                ('g', KeyCode::Char('g')) => *self.focused_scroll() = 0,
+                ('y', KeyCode::Char(c)) => self.copy(c),
+                _ => {}
             }
-            KeyCode::Char('j') => {
-                self.scroll_pos_diff += 1;
+            return;
+        }
+        if let Some(search) = self.search.as_mut().filter(|s| s.editing) {
+            match key.code {
+                KeyCode::Esc => {
+                    self.scroll_pos_diff = search.origin;
+                    self.search = None;
+                    return;
+                }
+                KeyCode::Enter => search.editing = false,
+                KeyCode::Backspace => {
+                    search.query.pop();
+                }
+                KeyCode::Char(c) => search.query.push(c),
+                _ => {}
             }
-            KeyCode::Char('k') => {
-                self.scroll_pos_diff = self.scroll_pos_diff.saturating_sub(1);
+            let origin = search.origin as usize;
+            self.jump_to_match(|row| row >= origin, false);
+            return;
+        }
+        match key.code {
+            KeyCode::Esc if self.search.is_some() => self.search = None,
+            KeyCode::Char('n') if self.search.is_some() => {
+                let scroll = self.scroll_pos_diff as usize;
+                self.jump_to_match(|row| row > scroll, false);
             }
-            KeyCode::Char('J') => {
-                self.scroll_pos_code += 1;
+            KeyCode::Char('N') if self.search.is_some() => {
+                let scroll = self.scroll_pos_diff as usize;
+                self.jump_to_match(|row| row < scroll, true);
             }
-            KeyCode::Char('K') => {
-                self.scroll_pos_code = self.scroll_pos_code.saturating_sub(1);
+            _ => {
+                if let Some(command) = keys::lookup(key) {
+                    self.execute(command);
+                }
             }
-            KeyCode::Char('c') => match self.config.show_mode {
-                ShowMode::SideBySide => self.config.show_mode = ShowMode::SideBySideOnly,
-                ShowMode::SideBySideOnly => self.config.show_mode = ShowMode::SideBySide,
-                ShowMode::Vertical => self.config.show_mode = ShowMode::VerticalOnly,
-                ShowMode::VerticalOnly => self.config.show_mode = ShowMode::Vertical,
-                _ => {}
+        }
+    }
+
+    fn execute(&mut self, command: keys::Command) {
+        if command.is_repeatable() {
+            self.last_action = Some(command);
+        }
+        match command {
+            keys::Command::Quit => self.quit(),
+            keys::Command::Bless => self.request_bless(),
+            keys::Command::MarkReviewed => self.mark(Status::Reviewed),
+            keys::Command::MarkSkipped => self.mark(Status::Skipped),
+            keys::Command::MarkDeferred => self.mark(Status::Deferred),
+            keys::Command::Explain => self.explain_error_code(),
+            keys::Command::Edit => self.request_edit(),
+            keys::Command::Merge => {
+                if self.request_curr_test().is_binary() {
+                    self.message = Some("Can't merge a binary output".to_owned());
+                } else {
+                    self.suspend = Some(Suspend::Merge);
+                }
+            }
+            keys::Command::EditExpected => self.open_editor(),
+            keys::Command::UpdateAnnotations => {
+                if let ShowMode::Annotations = self.config.show_mode {
+                    self.rewrite_annotations();
+                }
+            }
+            keys::Command::Repeat => match self.last_action {
+                Some(action) => self.execute(action),
+                None => self.message = Some("Nothing to repeat yet".to_owned()),
             },
-            KeyCode::Char('s') => match self.config.show_mode {
+            keys::Command::NextTest => self.advance_test(),
+            keys::Command::NextStream => self.next_stream(),
+            keys::Command::PreviousStream => self.previous_stream(),
+            keys::Command::ShowStderr => self.show_stream(Stream::Stderr),
+            keys::Command::ShowStdout => self.show_stream(Stream::Stdout),
+            keys::Command::PreviousTest => self.previous_test(),
+            keys::Command::Reload => {
+                self.reload();
+                self.message = Some("Reloaded".to_owned());
+            }
+            keys::Command::Rerun => self.rerun_with_rustc(),
+            keys::Command::GoTo => self.command = Some(String::new()),
+            keys::Command::FindTest => self.picker = Some(Picker::default()),
+            keys::Command::Tree => {
+                // With `--preload` the similarities come in as the tests are loaded, otherwise
+                // they're computed the same way without keeping the outputs.
+                if self.preload.is_none() {
+                    let missing: Vec<_> = self
+                        .paths
+                        .iter()
+                        .copied()
+                        .filter(|test| !self.similarities.contains_key(test))
+                        .collect();
+                    if !missing.is_empty() {
+                        self.preload = Some(Preload::similarities(self.rust_path.clone(), missing));
+                    }
+                }
+                self.tree.reveal(&self.paths, self.current_test);
+                self.show_tree = true;
+            }
+            keys::Command::Back => self.jump_back(),
+            keys::Command::Forward => self.jump_forward(),
+            keys::Command::SetBookmark => self.pending_key = Some('m'),
+            keys::Command::GoToBookmark => self.pending_key = Some('\''),
+            keys::Command::Top => self.pending_key = Some('g'),
+            keys::Command::Copy => self.pending_key = Some('y'),
+            keys::Command::RotateSort => {
+                let mut order = self.sort_order;
+                order.rotate_next();
+                self.sort_tests(order);
+            }
+            keys::Command::JumpToLine => self.jump_to_referenced_line(),
+            keys::Command::NextHunk => self.next_hunk(),
+            keys::Command::PreviousHunk => self.previous_hunk(),
+            keys::Command::Search => {
+                self.search = Some(Search {
+                    editing: true,
+                    origin: self.scroll_pos_diff,
+                    ..Default::default()
+                });
+            }
+            keys::Command::ScrollDown => self.scroll_by(1),
+            keys::Command::ScrollUp => self.scroll_by(-1),
+            keys::Command::HalfPageDown => self.scroll_by(self.focused_height() as i16 / 2),
+            keys::Command::HalfPageUp => self.scroll_by(-(self.focused_height() as i16 / 2)),
+            keys::Command::PageDown => self.scroll_by(self.focused_height() as i16),
+            keys::Command::PageUp => self.scroll_by(-(self.focused_height() as i16)),
+            keys::Command::Bottom => self.scroll_to_bottom(),
+            keys::Command::CycleFocus => self.focus.rotate_next(),
+            keys::Command::NextCodeTab => {
+                let tabs = self.request_curr_test().aux.len() + 1;
+                if tabs == 1 {
+                    self.message = Some("The test has no auxiliary crates".to_owned());
+                } else {
+                    self.code_tab = (self.code_tab + 1) % tabs;
+                    self.scroll_pos_code = 0;
+                    self.highlighted_code_line = None;
+                }
+            }
+            keys::Command::RotateDiffMode => self.config.diff_mode.rotate_next(),
+            keys::Command::ToggleLayout => match self.config.show_mode {
                 ShowMode::SideBySide => self.config.show_mode = ShowMode::Vertical,
                 ShowMode::SideBySideOnly => self.config.show_mode = ShowMode::VerticalOnly,
                 ShowMode::Vertical => self.config.show_mode = ShowMode::SideBySide,
                 ShowMode::VerticalOnly => self.config.show_mode = ShowMode::SideBySideOnly,
                 _ => {}
             },
-            _ => {}
+            keys::Command::ToggleCode => match self.config.show_mode {
+                ShowMode::SideBySide => self.config.show_mode = ShowMode::SideBySideOnly,
+                ShowMode::SideBySideOnly => self.config.show_mode = ShowMode::SideBySide,
+                ShowMode::Vertical => self.config.show_mode = ShowMode::VerticalOnly,
+                ShowMode::VerticalOnly => self.config.show_mode = ShowMode::Vertical,
+                _ => {}
+            },
+            keys::Command::ToggleWrap => self.config.wrap = !self.config.wrap,
+            keys::Command::CutOff => {
+                if self.cut_off.is_empty() {
+                    self.message = Some("No line is cut off".to_owned());
+                } else {
+                    self.popup = Some(Popup {
+                        title: "cut off lines".to_owned(),
+                        text: self.cut_off.join("\n\n"),
+                        scroll: 0,
+                    });
+                }
+            }
+            keys::Command::ToggleMetadata => {
+                self.config.show_metadata = !self.config.show_metadata;
+                self.update_metadata();
+            }
+            keys::Command::ToggleColorblind => {
+                self.config.colorblind = !self.config.colorblind;
+                self.diffs.clear();
+            }
+            keys::Command::ToggleIce => self.show_ice_diff = !self.show_ice_diff,
+            keys::Command::RustcArgs => match self.config.show_mode {
+                ShowMode::RustcArgs { oneline } => {
+                    self.config.show_mode = self.prev_view;
+                    self.prev_view = ShowMode::RustcArgs { oneline }
+                }
+                _ => {
+                    self.prev_view = self.config.show_mode;
+                    self.config.show_mode = ShowMode::RustcArgs { oneline: false }
+                }
+            },
+            keys::Command::Oneline => {
+                if let ShowMode::RustcArgs { oneline } = self.config.show_mode {
+                    self.config.show_mode = ShowMode::RustcArgs { oneline: !oneline }
+                }
+            }
+            keys::Command::Annotations => self.toggle_show_mode(ShowMode::Annotations),
+            keys::Command::Info => self.toggle_show_mode(ShowMode::Info),
+            keys::Command::OtherFailures => self.toggle_show_mode(ShowMode::OtherFailures),
+            keys::Command::ExternalDiff => {
+                if self.diff_tool.is_some() {
+                    self.toggle_show_mode(ShowMode::External);
+                } else {
+                    self.message = Some("No diff tool, set one with --diff-tool".to_owned());
+                }
+            }
+            keys::Command::Colored => self.toggle_show_mode(ShowMode::Colored),
+            keys::Command::Ignored => self.toggle_show_mode(ShowMode::Ignored),
+            keys::Command::History => {
+                let Some(runs) = &self.runs else {
+                    self.message = Some("No history, set `history: true` in the config".to_owned());
+                    return;
+                };
+                let test = self.current_path();
+                match runs.describe(test) {
+                    Ok(text) => {
+                        self.popup = Some(Popup {
+                            title: "history".to_owned(),
+                            text,
+                            scroll: 0,
+                        });
+                    }
+                    Err(e) => self.message = Some(format!("Couldn't read the history: {e}")),
+                }
+            }
+            keys::Command::Statistics => {
+                // Every test is read and diffed, which takes a while on large runs.
+                let rust_path = self.rust_path.clone();
+                let paths = self.paths.clone();
+                let failure_kinds = self.failure_kinds.clone();
+                let stats = self.stats;
+                let other_failed = self.other_failed.clone();
+                self.open_pending_popup("statistics".to_owned(), move || {
+                    let tests = report::collect(&rust_path, &paths, &failure_kinds, None);
+                    report::statistics(&tests, stats, &other_failed)
+                });
+            }
+            keys::Command::PreviousMode => {
+                mem::swap(&mut self.config.show_mode, &mut self.prev_view);
+            }
+            keys::Command::ToggleHelpBar => self.config.hide_help = !self.config.hide_help,
+            keys::Command::Help => {
+                self.popup = Some(Popup {
+                    title: "keys".to_owned(),
+                    text: keys::help_text(),
+                    scroll: 0,
+                });
+            }
+        }
+    }
+
+    /// Runs the diff tool on the current stream unless its output is already there.
+    fn update_external_diff(&mut self) {
+        let (test, stream) = (self.current_test, self.current_stream);
+        if self
+            .external_diff
+            .as_ref()
+            .is_some_and(|diff| diff.test == test && diff.stream == stream)
+        {
+            return;
+        }
+        let Some(tool) = self.diff_tool.clone() else {
+            return;
         };
+        let TestData {
+            expect,
+            actual,
+            expected_path,
+            ..
+        } = self.request_curr_test();
+        let file_name = expected_path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .unwrap_or("output");
+        let text = external::run(&tool, file_name, expect, actual)
+            .unwrap_or_else(|e| Text::from(e.to_string()).red());
+        self.external_diff = Some(ExternalDiff { test, stream, text });
+    }
+
+    /// Writes the actual stderr of the current test from rustc alone, see [`rustc::rerun`].
+    fn rerun_with_rustc(&mut self) {
+        let rust_path = self.rust_path.clone();
+        let TestData {
+            test_name,
+            test_code,
+            ..
+        } = self.request_curr_test();
+        let (test_name, test_code) = (test_name.clone(), test_code.clone());
+        if directives::parse(&test_code)
+            .iter()
+            .any(|d| d.name == "revisions")
+        {
+            self.message = Some("Can't rerun a test with revisions with rustc alone".to_owned());
+            return;
+        }
+        let actual_path = self
+            .cached_streams
+            .resolved
+            .iter()
+            .find(|r| r.stream == Stream::Stderr)
+            .and_then(|r| r.actual_path.clone());
+        let Some(actual_path) = actual_path else {
+            self.message = Some("Can't rerun the tests of --artifacts".to_owned());
+            return;
+        };
+
+        let started = Instant::now();
+        self.message = Some(match rustc::rerun(&rust_path, &test_name, &test_code) {
+            Ok(stderr) => match remote::write(&actual_path, stderr) {
+                Ok(()) => format!("Reran with rustc in {:.1?}", started.elapsed()),
+                Err(e) => format!("Couldn't write {}: {e}", actual_path.display()),
+            },
+            Err(e) => format!("Couldn't rerun the test: {e}"),
+        });
+        self.reload();
+    }
+
+    /// Compiles the current test for the colored view unless it already was.
+    fn update_colored_output(&mut self) {
+        let test = self.current_test;
+        if self
+            .colored_output
+            .as_ref()
+            .is_some_and(|output| output.test == test)
+        {
+            return;
+        }
+        let rust_path = self.rust_path.clone();
+        let TestData {
+            test_name,
+            test_code,
+            ..
+        } = self.request_curr_test();
+        let text = rustc::colored_output(&rust_path, test_name, test_code)
+            .unwrap_or_else(|e| Text::from(e.to_string()).red());
+        self.colored_output = Some(ColoredOutput { test, text });
+    }
+
+    /// Looks up the last commit of the current expected output unless it's already known.
+    fn update_last_change(&mut self) {
+        let path = self.request_curr_test().expected_path.clone();
+        if self
+            .last_change
+            .as_ref()
+            .is_some_and(|(known, _)| *known == path)
+        {
+            return;
+        }
+        let commit = git::last_commit(&self.rust_path, &path);
+        self.last_change = Some((path, commit));
+    }
+
+    /// Switches to `mode`, or back to the previous one if already there.
+    fn toggle_show_mode(&mut self, mode: ShowMode) {
+        if mem::discriminant(&self.config.show_mode) == mem::discriminant(&mode) {
+            self.config.show_mode = self.prev_view;
+            self.prev_view = mode;
+        } else {
+            self.prev_view = self.config.show_mode;
+            self.config.show_mode = mode;
+        }
     }
 
     /// Set running to false to quit the application.
@@ -371,71 +1844,840 @@ impl App {
         self.running = false;
     }
 
+    fn mark(&mut self, status: Status) {
+        self.session.toggle(self.current_path(), status);
+    }
+
+    /// Opens a popup with `rustc --explain` for the first error code at or below the diff
+    /// scroll position, from the rustc of the checkout.
+    fn explain_error_code(&mut self) {
+        let scroll = self.scroll_pos_diff as usize;
+        let TestData { expect, actual, .. } = self.request_curr_test();
+        let diff = line_diff(expect, actual);
+        let code = diff
+            .iter()
+            .skip(scroll)
+            .chain(&diff)
+            .find_map(|line| error_code(line.value));
+        let Some(code) = code.map(str::to_owned) else {
+            return;
+        };
+        let rust_path = self.rust_path.clone();
+        self.open_pending_popup(format!("rustc --explain {code}"), move || {
+            rustc::explain(&rust_path, &code).unwrap_or_else(|e| e.to_string())
+        });
+    }
+
+    fn curr_hunk_rows(&mut self) -> Vec<usize> {
+        let side_by_side = self.config.show_mode.is_side_by_side();
+        let (data, diffs) = self.request_curr_diffs();
+        diffs.summary(data, side_by_side).hunk_starts.clone()
+    }
+
+    /// Scrolls the diff to the first (or last if `backwards`) search match whose row satisfies
+    /// `accept`.
+    fn jump_to_match(&mut self, accept: impl Fn(usize) -> bool, backwards: bool) {
+        let Some(query) = self.search.as_ref().map(|s| s.query.clone()) else {
+            return;
+        };
+        let side_by_side = self.config.show_mode.is_side_by_side();
+        let TestData { expect, actual, .. } = self.request_curr_test();
+        let rows = search::match_rows(pane_rows(&line_diff(expect, actual), side_by_side), &query);
+        let row = if backwards {
+            rows.into_iter().rev().find(|&row| accept(row))
+        } else {
+            rows.into_iter().find(|&row| accept(row))
+        };
+        if let Some(row) = row {
+            self.scroll_pos_diff = row as u16;
+        }
+    }
+
+    fn next_hunk(&mut self) {
+        let scroll = self.scroll_pos_diff as usize;
+        if let Some(&row) = self.curr_hunk_rows().iter().find(|&&row| row > scroll) {
+            self.scroll_pos_diff = row as u16;
+        }
+    }
+
+    fn previous_hunk(&mut self) {
+        let scroll = self.scroll_pos_diff as usize;
+        if let Some(&row) = self
+            .curr_hunk_rows()
+            .iter()
+            .rev()
+            .find(|&&row| row < scroll)
+        {
+            self.scroll_pos_diff = row as u16;
+        }
+    }
+
+    /// Scrolls the code pane to the line of the `--> $DIR/test.rs:L:C` closest to the top of the
+    /// diff pane, looking upwards first since that's the location of the snippet being shown.
+    /// Locations in auxiliary crates switch to their tab.
+    fn jump_to_referenced_line(&mut self) {
+        let scroll = self.scroll_pos_diff as usize;
+        let TestData {
+            expect,
+            actual,
+            test_name,
+            aux,
+            ..
+        } = self.request_curr_test();
+        let file_name = Path::new(test_name.as_str())
+            .file_name()
+            .and_then(|f| f.to_str())
+            .unwrap_or_default();
+        let files: Vec<_> = [file_name]
+            .into_iter()
+            .chain(aux.iter().map(|aux| aux.path.as_str()))
+            .collect();
+        let diff = line_diff(expect, actual);
+        let location = |line: &DiffLine<'_>| {
+            files
+                .iter()
+                .enumerate()
+                .find_map(|(tab, file)| Some((tab, diff_location(line, file)?)))
+        };
+        let cursor = scroll.min(diff.len());
+        let location = diff[..cursor.saturating_add(1).min(diff.len())]
+            .iter()
+            .rev()
+            .chain(&diff[cursor..])
+            .find_map(location);
+
+        if let Some((tab, line)) = location {
+            self.code_tab = tab;
+            self.highlighted_code_line = Some(line);
+            // Leave some context above the line.
+            self.scroll_pos_code = line.saturating_sub(4) as u16;
+        }
+    }
+
+    /// Updates the `//~` annotations of the test source to match the actual stderr.
+    fn rewrite_annotations(&mut self) {
+        let TestData {
+            test_code,
+            actual,
+            test_name,
+            ..
+        } = self.request_curr_test().clone();
+        let path = self.rust_path.join(native_path(&test_name));
+        let file_name = path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .unwrap_or_default();
+        let comparison = Comparison::from_sources(&test_code, &actual, file_name);
+        let text = if comparison.unmatched.is_empty() && comparison.unannotated.is_empty() {
+            "Annotations already match the diagnostics".to_owned()
+        } else {
+            let new_code = annotations::rewrite(&test_code, &comparison);
+            match remote::write(&path, new_code) {
+                Ok(()) => format!(
+                    "Rewrote {} annotations and added {} in {}.\nRerun the test to update the line numbers of the output.",
+                    comparison.unmatched.len(),
+                    comparison.unannotated.len(),
+                    path.display()
+                ),
+                Err(e) => format!("Couldn't write {}: {e}", path.display()),
+            }
+        };
+        self.cached_streams = Default::default();
+        self.popup = Some(Popup {
+            title: "annotations".to_owned(),
+            text,
+            scroll: 0,
+        });
+    }
+
+    /// Blesses every failing test in `dir`, not including subdirectories.
+    fn bless_directory(&mut self, dir: &str) {
+        let mut blessed = 0;
+        let mut errors = vec![];
+        let mut not_mismatches = 0;
+        // Asked about when blessing a single test, so left to be blessed one by one.
+        let mut ices = vec![];
+        let mut modified = vec![];
+        for &path in self.paths.iter().filter(|p| tree::dir_of(p) == dir) {
+            if self.failure_kinds.contains_key(path) {
+                not_mismatches += 1;
+                continue;
+            }
+            let streams = CachedStreams::load_or_missing(&self.rust_path, path);
+            if streams.present().iter().any(|data| data.is_ice()) {
+                ices.push(path);
+                continue;
+            }
+            let blessed_here = self.session.statuses.get(path) == Some(&Status::Blessed);
+            let is_modified =
+                |data: &&TestData| git::is_modified(&self.rust_path, &data.expected_path);
+            if !blessed_here && streams.present().iter().any(is_modified) {
+                modified.push(path);
+                continue;
+            }
+            let checked = match &self.pre_bless {
+                Some(hook) => streams.present().iter().try_for_each(|data| {
+                    hooks::pre_bless(
+                        hook,
+                        &self.rust_path,
+                        path,
+                        &data.expected_path,
+                        &data.actual,
+                    )
+                }),
+                None => Ok(()),
+            };
+            let result = checked.and_then(|()| streams.bless()).and_then(|()| {
+                if self.git_add {
+                    git::add(&self.rust_path, &streams.expected_paths())?;
+                }
+                if let Some(hook) = &self.post_bless {
+                    hooks::post_bless(hook, &self.rust_path, path, &streams.expected_paths())?;
+                }
+                Ok(())
+            });
+            match result {
+                Ok(()) => {
+                    blessed += 1;
+                    self.session
+                        .statuses
+                        .insert(path.to_owned(), Status::Blessed);
+                }
+                Err(e) => errors.push(format!("{path}: {e}")),
+            }
+        }
+        self.cached_streams = Default::default();
+
+        let mut text = format!("Blessed {blessed} tests in {dir}/");
+        if not_mismatches > 0 {
+            text.push_str(&format!(
+                "\nSkipped {not_mismatches} tests that timed out or panicked"
+            ));
+        }
+        if !ices.is_empty() {
+            text.push_str("\nSkipped the internal compiler errors of:");
+            for path in ices {
+                text.push_str(&format!("\n  {path}"));
+            }
+        }
+        if !modified.is_empty() {
+            text.push_str(
+                "\nSkipped the tests with uncommitted changes to their expected outputs:",
+            );
+            for path in modified {
+                text.push_str(&format!("\n  {path}"));
+            }
+        }
+        for error in errors {
+            text.push('\n');
+            text.push_str(&error);
+        }
+        self.popup = Some(Popup {
+            title: "bless directory".to_owned(),
+            text,
+            scroll: 0,
+        });
+    }
+
+    /// The pane that scrolls, the diff unless the code is focused and shown.
+    fn focused_pane(&self) -> Pane {
+        if self.config.show_mode.shows_code() {
+            self.focus
+        } else {
+            Pane::Diff
+        }
+    }
+
+    fn focused_scroll(&mut self) -> &mut u16 {
+        match self.focused_pane() {
+            Pane::Diff => &mut self.scroll_pos_diff,
+            Pane::Code => &mut self.scroll_pos_code,
+        }
+    }
+
+    fn focused_height(&self) -> u16 {
+        match self.focused_pane() {
+            Pane::Diff => self.diff_height,
+            Pane::Code => self.code_height,
+        }
+    }
+
+    /// Scrolls the focused pane so that its last line is at the bottom.
+    fn scroll_to_bottom(&mut self) {
+        let side_by_side = self.config.show_mode.is_side_by_side();
+        let height = self.focused_height();
+        let pane = self.focused_pane();
+        let code_tab = self.code_tab;
+        let data = self.request_curr_test();
+        let rows = match pane {
+            Pane::Diff => pane_rows(&line_diff(&data.expect, &data.actual), side_by_side)
+                .iter()
+                .map(Vec::len)
+                .max()
+                .unwrap_or_default(),
+            Pane::Code => data.code(code_tab).1.lines().count(),
+        };
+        *self.focused_scroll() = (rows as u16).saturating_sub(height);
+    }
+
+    fn scroll_by(&mut self, rows: i16) {
+        let scroll = self.focused_scroll();
+        *scroll = scroll.saturating_add_signed(rows);
+    }
+
+    /// Copies the test path (`p`), the path that blessing writes to (`b`), the diff (`d`) or the
+    /// command to rerun the test (`x`).
+    fn copy(&mut self, what: char) {
+        let x_command = self.x_command.clone();
+        let TestData {
+            expect,
+            actual,
+            test_name,
+            expected_path,
+            ..
+        } = self.request_curr_test();
+        let (name, text) = match what {
+            'p' => ("test path", test_name.clone()),
+            'b' => ("bless target", expected_path.display().to_string()),
+            'x' => ("command", x_command.replace("{path}", test_name)),
+            'd' => (
+                "diff",
+                text_diff(expect, actual)
+                    .unified_diff()
+                    .header("expected", "actual")
+                    .to_string(),
+            ),
+            _ => return,
+        };
+        self.message = Some(match clipboard::copy(&text) {
+            Ok(()) => format!("Copied the {name}"),
+            Err(e) => format!("Couldn't copy the {name}: {e}"),
+        });
+    }
+
+    /// Opens the test in the editor, at the highlighted code line or else at the line of the
+    /// first diagnostic that changed. On the tab of an auxiliary crate, opens that instead.
+    fn request_edit(&mut self) {
+        let highlighted = self.highlighted_code_line;
+        let code_tab = self.code_tab;
+        let path = self.rust_path.join(native_path(self.current_path()));
+        let TestData {
+            expect,
+            actual,
+            test_name,
+            aux,
+            ..
+        } = self.request_curr_test();
+        if let Some(aux) = code_tab.checked_sub(1).and_then(|i| aux.get(i)) {
+            let path = path.parent().unwrap_or(&path).join(native_path(&aux.path));
+            self.suspend = Some(Suspend::Edit(path, highlighted));
+            return;
+        }
+        let file_name = Path::new(test_name.as_str())
+            .file_name()
+            .and_then(|f| f.to_str())
+            .unwrap_or_default();
+        let first_changed = first_changed_location(&line_diff(expect, actual), file_name);
+        self.suspend = Some(Suspend::Edit(path, highlighted.or(first_changed)));
+    }
+
+    fn open_editor(&mut self) {
+        let TestData {
+            expect,
+            actual,
+            expected_path,
+            raw_actual,
+            ..
+        } = self.request_curr_test();
+        if raw_actual.is_some() {
+            self.message = Some("Can't edit a binary output".to_owned());
+            return;
+        }
+        self.editor = Some(Editor::new(
+            expected_path.clone(),
+            &line_diff(expect, actual),
+        ));
+    }
+
+    fn save_editor(&mut self) {
+        let Some(editor) = self.editor.take() else {
+            return;
+        };
+        self.message = Some(match remote::write(&editor.path, editor.expected()) {
+            Ok(()) => format!("Wrote {}", editor.path.display()),
+            Err(e) => format!("Couldn't write {}: {e}", editor.path.display()),
+        });
+        self.reload();
+    }
+
+    fn run_action(&mut self, action: Action) {
+        match action {
+            Action::Bless => self.bless(),
+            Action::BlessDirectory(dir) => self.bless_directory(&dir),
+        }
+    }
+
+    /// Blesses the current stream, asking first if that doesn't look like a good idea.
+    fn request_bless(&mut self) {
+        if self.failure_kinds.contains_key(self.current_path()) {
+            return;
+        }
+        let TestData {
+            actual,
+            expected_path,
+            test_name,
+            ..
+        } = self.request_curr_test();
+        let is_ice = ice::detect(actual).is_some();
+        let (expected_path, test_name) = (expected_path.clone(), test_name.clone());
+
+        let mut warnings = vec![];
+        if is_ice {
+            warnings.push("The actual output is an internal compiler error.".to_owned());
+        }
+        // Blessing shows up as a modification too, only ask about changes made outside.
+        let blessed_here = self.session.statuses.get(&test_name) == Some(&Status::Blessed);
+        if !blessed_here && git::is_modified(&self.rust_path, &expected_path) {
+            let path = expected_path
+                .strip_prefix(&self.rust_path)
+                .unwrap_or(&expected_path);
+            warnings.push(format!(
+                "{} has uncommitted changes that blessing would overwrite.",
+                path.display()
+            ));
+        }
+        let question = if !warnings.is_empty() {
+            warnings.push("Bless it anyway?".to_owned());
+            warnings.join(" ")
+        } else if self.confirm_bless {
+            let path = expected_path
+                .strip_prefix(&self.rust_path)
+                .unwrap_or(&expected_path);
+            format!("Overwrite {} with the actual output?", path.display())
+        } else {
+            self.bless();
+            return;
+        };
+        self.confirmation = Some(Confirmation {
+            question,
+            action: Action::Bless,
+        });
+    }
+
+    /// Runs the merge tool on the current stream and writes the result as the expected output.
+    fn merge(&mut self) {
+        let tool = self
+            .merge_tool
+            .clone()
+            .or_else(|| std::env::var("MERGETOOL").ok())
+            .unwrap_or_else(|| "vimdiff".to_owned());
+        let TestData {
+            expect,
+            actual,
+            expected_path,
+            test_name,
+            ..
+        } = self.request_curr_test();
+        let file_name = expected_path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .unwrap_or("output");
+        let result = external::merge(&tool, file_name, expect, actual).and_then(|merged| {
+            let Some(merged) = merged else {
+                return Ok(false);
+            };
+            remote::write(expected_path, merged)?;
+            Ok(true)
+        });
+        let test_name = test_name.clone();
+        match result {
+            Ok(true) => {
+                self.session.statuses.insert(test_name, Status::Blessed);
+                self.message = Some("Wrote the merged output".to_owned());
+            }
+            Ok(false) => self.message = Some("Nothing was merged".to_owned()),
+            Err(e) => self.message = Some(format!("Couldn't merge: {e}")),
+        }
+    }
+
     fn bless(&mut self) {
+        if self.blessing.is_some() {
+            self.message = Some("The bless hooks are still running".to_owned());
+            return;
+        }
         let data = match self.current_stream {
             Stream::Stderr => &self.cached_streams.stderr,
             Stream::Stdout => &self.cached_streams.stdout,
         };
         if let CachedData::Present(data) = data {
-            std::fs::write(&data.expected_path, &data.actual).unwrap();
+            if self.pre_bless.is_none() && self.post_bless.is_none() {
+                let outcome = bless_stream(data, &self.rust_path, None, None, self.git_add);
+                if !self.finish_bless(data.test_name.clone(), outcome) {
+                    return;
+                }
+            } else {
+                let (sender, outcome) = mpsc::channel();
+                let (data, rust_path) = (data.clone(), self.rust_path.clone());
+                let (pre_bless, post_bless) = (self.pre_bless.clone(), self.post_bless.clone());
+                let git_add = self.git_add;
+                self.blessing = Some(Blessing {
+                    test: data.test_name.clone(),
+                    stream: self.current_stream,
+                    outcome,
+                });
+                thread::spawn(move || {
+                    let (pre_bless, post_bless) = (pre_bless.as_deref(), post_bless.as_deref());
+                    let _ = sender.send(bless_stream(
+                        &data, &rust_path, pre_bless, post_bless, git_add,
+                    ));
+                });
+                return;
+            }
         }
-        self.advance_stream();
+        self.after_bless();
+    }
+
+    /// Shows how blessing `test` went, returns whether it's blessed.
+    fn finish_bless(&mut self, test: String, outcome: BlessOutcome) -> bool {
+        match outcome {
+            BlessOutcome::Failed { title, error } => {
+                // Not blessed, the test stays so that the output can be looked at.
+                self.popup = Some(Popup {
+                    title: title.to_owned(),
+                    text: error,
+                    scroll: 0,
+                });
+                false
+            }
+            BlessOutcome::Blessed {
+                git_add,
+                post_bless,
+            } => {
+                self.message = git_add;
+                if let Some(error) = post_bless {
+                    self.popup = Some(Popup {
+                        title: "post-bless hook".to_owned(),
+                        text: error,
+                        scroll: 0,
+                    });
+                }
+                self.session.statuses.insert(test, Status::Blessed);
+                true
+            }
+        }
+    }
+
+    fn after_bless(&mut self) {
+        match self.after_bless {
+            AfterBless::Stay => {
+                self.message = Some(format!("Blessed the {}", self.current_stream.name()));
+            }
+            AfterBless::NextStream => self.advance_stream(),
+            AfterBless::NextTest => self.advance_test(),
+        }
+    }
+}
+
+/// Writes the actual output of `data` as the expected one, with the hooks around it.
+fn bless_stream(
+    data: &TestData,
+    rust_path: &Path,
+    pre_bless: Option<&str>,
+    post_bless: Option<&str>,
+    git_add: bool,
+) -> BlessOutcome {
+    let files = [data.expected_path.as_path()];
+    if let Some(hook) = pre_bless {
+        let checked = hooks::pre_bless(
+            hook,
+            rust_path,
+            &data.test_name,
+            &data.expected_path,
+            &data.actual,
+        );
+        if let Err(e) = checked {
+            return BlessOutcome::Failed {
+                title: "pre-bless hook",
+                error: e.to_string(),
+            };
+        }
+    }
+    if let Err(e) = remote::write(&data.expected_path, data.actual_bytes()) {
+        // Not blessed either, like when the pre-bless hook fails.
+        return BlessOutcome::Failed {
+            title: "bless",
+            error: format!("Couldn't write {}: {e}", data.expected_path.display()),
+        };
+    }
+    tracing::info!(path = %data.expected_path.display(), "blessed");
+    let git_add = git_add
+        .then(|| git::add(rust_path, &files).err())
+        .flatten()
+        .map(|e| e.to_string());
+    let post_bless = post_bless
+        .and_then(|hook| hooks::post_bless(hook, rust_path, &data.test_name, &files).err())
+        .map(|e| e.to_string());
+    BlessOutcome::Blessed {
+        git_add,
+        post_bless,
     }
 }
 
+fn code_text<'a>(code: &'a str, highlighted: Option<usize>, theme: &Theme) -> Text<'a> {
+    code.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if Some(i + 1) == highlighted {
+                Line::styled(line, theme.highlight)
+            } else {
+                Line::from(line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// The line of the test that a ` --> $DIR/file.rs:line:col` line of the diff points to.
+fn diff_location(line: &DiffLine<'_>, file_name: &str) -> Option<usize> {
+    let location = line.value.trim_start().strip_prefix("--> ")?;
+    annotations::location_line(location.trim_end(), file_name)
+}
+
+/// The line of the test that the first diagnostic that changed points to.
+pub fn first_changed_location(diff: &[DiffLine<'_>], file_name: &str) -> Option<usize> {
+    let first = diff.iter().position(|line| line.tag != LineTag::Equal)?;
+    diff[first..]
+        .iter()
+        .find_map(|line| diff_location(line, file_name))
+}
+
+/// Opens `path` in `$VISUAL` or `$EDITOR`, at `line` if given, and waits for it to exit. Remote
+/// files are edited in a local copy that's written back.
+fn edit(path: &Path, line: Option<usize>) -> io::Result<()> {
+    if remote::host().is_none() {
+        return edit_local(path, line);
+    }
+    let file_name = path.file_name().unwrap_or_default();
+    let copy = std::env::temp_dir()
+        .join("compiletest-differ")
+        .join("remote")
+        .join(file_name);
+    std::fs::create_dir_all(copy.parent().unwrap())?;
+    let text = remote::read_to_string(path)?;
+    std::fs::write(&copy, &text)?;
+    edit_local(&copy, line)?;
+    let edited = std::fs::read_to_string(&copy)?;
+    if edited != text {
+        remote::write(path, edited)?;
+    }
+    Ok(())
+}
+
+fn edit_local(path: &Path, line: Option<usize>) -> io::Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+    // The variable can have arguments, like `code --wait`.
+    let mut words = editor.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or("vi"));
+    command.args(words);
+    if let Some(line) = line {
+        command.arg(format!("+{line}"));
+    }
+    let status = command.arg(path).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("{editor} exited with {status}")));
+    }
+    Ok(())
+}
+
+/// Finds an error code like `E0308` in a line like `error[E0308]: mismatched types`.
+fn error_code(line: &str) -> Option<&str> {
+    line.match_indices("[E").find_map(|(i, _)| {
+        let code = line.get(i + 1..i + 6)?;
+        let is_code =
+            code[1..].bytes().all(|b| b.is_ascii_digit()) && line[i + 6..].starts_with(']');
+        is_code.then_some(code)
+    })
+}
+
+/// The default of `tab_width` in the config, rustc renders tabs as 4 spaces.
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Config {
     pub diff_mode: DiffMode,
     pub show_mode: ShowMode,
     pub hide_help: bool,
+    /// Wrap long lines of the diff instead of cutting them off.
+    pub wrap: bool,
+    pub theme: Theme,
+    /// Use the colorblind theme instead of `theme`.
+    pub colorblind: bool,
+    /// The panel with the sizes and ages of the files of the test, its edition and revisions.
+    pub show_metadata: bool,
+    /// Columns between tab stops in the code and the diff, see `tab_width` in the config.
+    pub tab_width: usize,
 }
 
 impl Config {
-    pub fn help_string(&self) -> String {
-        // FIXME: the colors don't work!
-        // Need to use proper ratatui spans to fix this..
-        let bless = format!("{}less", "b".blue().bold());
-        let help = format!("{}elp toggle", "h".green().bold());
-        let previous_mode = format!("{}revious mode", "p".yellow().bold());
-        let rustc_args = format!("{}ustc args", "r".green().bold());
-        let next_diff = format!(
-            "next {}iff mode: {}",
-            "d".red().bold(),
-            self.diff_mode.next_text()
-        );
+    pub const fn theme(&self) -> Theme {
+        if self.colorblind {
+            Theme::COLORBLIND
+        } else {
+            self.theme
+        }
+    }
 
-        let vertical = format!("vertical {}how mode", "s".red().bold());
-        let horizontal = format!("horizontal {}how mode", "s".red().bold());
-        let show_code = format!("show {}ode", "c".magenta().bold());
-        let hide_code = format!("hide {}ode", "c".magenta().bold());
+    /// The help bar: the keys for the current view and the focused pane.
+    pub fn help_line(&self, focus: Pane) -> Line<'static> {
+        let theme = self.theme();
+        let item = |before: &'static str, key: &'static str, after: String, paint: Paint| {
+            vec![
+                Span::raw(before),
+                Span::styled(key, paint),
+                Span::raw(after),
+            ]
+        };
+        let move_item =
+            |before, key, after: &str| item(before, key, after.to_owned(), theme.move_key);
 
-        let show_mode_specific = match self.show_mode {
-            ShowMode::SideBySide => {
-                format!("{vertical} | {hide_code} | {next_diff} | {rustc_args}")
-            }
-            ShowMode::SideBySideOnly => {
-                format!("{vertical} | {show_code} | {next_diff} | {rustc_args}")
-            }
-            ShowMode::Vertical => {
-                format!("{horizontal} | {hide_code} | {next_diff} | {rustc_args}")
-            }
-            ShowMode::VerticalOnly => {
-                format!("{horizontal} | {show_code} | {next_diff} | {rustc_args}")
+        let bless = item("", "b", "less".to_owned(), theme.bless_key);
+        let marks = [
+            item("mark re", "v", "iewed/".to_owned(), theme.mark_key),
+            item("", "S", "kipped/".to_owned(), theme.mark_key),
+            item("", "D", "eferred".to_owned(), theme.mark_key),
+        ]
+        .concat();
+        let explain = move_item("e", "x", "plain");
+        let find = move_item("", "f", "ind test");
+        let tree = move_item("", "t", "ree");
+        let goto = move_item("", ":", "N go to test");
+        let history = [move_item("", "<", "/"), move_item("", ">", " back/forward")].concat();
+        let bookmarks = [
+            move_item("", "m", "ark/"),
+            move_item("", "'", " go to mark"),
+        ]
+        .concat();
+        let sort = move_item("s", "O", "rt order");
+        let focus_item = match focus {
+            Pane::Diff => move_item("", "^w", " focus code"),
+            Pane::Code => move_item("", "^w", " focus diff"),
+        };
+        let pages = move_item("", "PgUp/PgDn ^u/^d", " pages");
+        let ends = move_item("", "gg/G", " top/bottom");
+
+        let rustc_args = item("", "r", "ustc args".to_owned(), theme.view_key);
+        let annotations = item("", "a", "nnotations".to_owned(), theme.view_key);
+        let info = item("", "@", " info".to_owned(), theme.view_key);
+        let other_failures = item("other ", "F", "ailures".to_owned(), theme.view_key);
+        let ignored = item("", "I", "gnored".to_owned(), theme.view_key);
+        let views = [
+            rustc_args.clone(),
+            annotations,
+            info.clone(),
+            other_failures.clone(),
+            ignored.clone(),
+        ];
+        let previous_mode = item("", "p", "revious mode".to_owned(), theme.mark_key);
+        let help = [
+            item("", "h", "elp toggle | ".to_owned(), theme.view_key),
+            item("", "?", " all keys".to_owned(), theme.view_key),
+        ]
+        .concat();
+
+        let mut items = vec![bless, marks, explain];
+        match self.show_mode {
+            ShowMode::SideBySide
+            | ShowMode::SideBySideOnly
+            | ShowMode::Vertical
+            | ShowMode::VerticalOnly => {
+                let layout = if self.show_mode.is_side_by_side() {
+                    "vertical "
+                } else {
+                    "horizontal "
+                };
+                let code = if self.show_mode.shows_code() {
+                    "hide "
+                } else {
+                    "show "
+                };
+                if focus == Pane::Diff || !self.show_mode.shows_code() {
+                    items.extend([
+                        move_item("", "enter", " jump to line"),
+                        move_item("", "[ ]", " hunks"),
+                        move_item("", "/", " search"),
+                        move_item("", "w", "rap"),
+                        move_item("", "i", " ICE/diff"),
+                    ]);
+                }
+                items.push(move_item("", "tab/⇧tab", " next/previous output"));
+                if self.show_mode.shows_code() {
+                    items.push(focus_item);
+                }
+                items.extend([pages, ends]);
+                items.extend([
+                    item(layout, "s", "how mode".to_owned(), theme.mode_key),
+                    item(code, "c", "ode".to_owned(), theme.mode_key),
+                    item(
+                        "next ",
+                        "d",
+                        format!("iff mode: {}", self.diff_mode.next_text()),
+                        theme.mode_key,
+                    ),
+                ]);
+                items.extend(views);
             }
             ShowMode::RustcArgs { oneline } => {
-                format!(
-                    "{} {}neline",
-                    "o".red(),
-                    if oneline { "disable" } else { "enable" }
-                )
+                let toggle = if oneline { " disable" } else { " enable" };
+                items.push(item("", "o", format!("{toggle} oneline"), theme.mode_key));
+                items.push(rustc_args);
             }
-        };
+            ShowMode::Annotations => {
+                items.push(item(
+                    "",
+                    "u",
+                    "pdate annotations".to_owned(),
+                    theme.mode_key,
+                ));
+                items.push(pages);
+                items.extend(views);
+            }
+            ShowMode::Info => items.extend([pages, info]),
+            ShowMode::OtherFailures => items.extend([pages, other_failures]),
+            ShowMode::External => items.extend([
+                pages,
+                item("e", "X", "ternal diff".to_owned(), theme.view_key),
+            ]),
+            ShowMode::Ignored => items.extend([pages, ignored]),
+            ShowMode::Colored => items.extend([
+                pages,
+                item("", "K", " colored output".to_owned(), theme.view_key),
+            ]),
+        }
+        items.extend([
+            find,
+            tree,
+            goto,
+            history,
+            bookmarks,
+            sort,
+            previous_mode,
+            help,
+        ]);
 
-        format!("{bless} | {show_mode_specific} | {previous_mode} | {help}")
+        let mut spans = vec![];
+        for item in items {
+            if !spans.is_empty() {
+                spans.push(Span::raw(" | "));
+            }
+            spans.extend(item);
+        }
+        Line::from(spans)
     }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
 pub enum DiffMode {
     #[default]
     Line,
@@ -452,6 +2694,14 @@ impl DiffMode {
         }
     }
 
+    pub const fn name(self) -> &'static str {
+        match self {
+            DiffMode::Char => "char",
+            DiffMode::Word => "word",
+            DiffMode::Line => "line",
+        }
+    }
+
     pub const fn next_text(&self) -> &'static str {
         match self {
             DiffMode::Char => "word",
@@ -460,99 +2710,3 @@ impl DiffMode {
         }
     }
 }
-
-fn diff_vertical_linewise<'a>(lhs: &'a str, rhs: &'a str) -> (Text<'a>, Text<'a>) {
-    let diff = similar::TextDiff::from_lines(lhs, rhs);
-
-    let mut lhs: Vec<Line<'_>> = vec![];
-    let mut rhs: Vec<Line<'_>> = vec![];
-    for hunk in diff.iter_all_changes() {
-        match hunk.tag() {
-            similar::ChangeTag::Equal => {
-                lhs.push(hunk.value().into());
-                rhs.push(hunk.value().into());
-            }
-            similar::ChangeTag::Delete => {
-                lhs.push(hunk.value().red().into());
-            }
-            similar::ChangeTag::Insert => {
-                rhs.push(hunk.value().green().bold().into());
-            }
-        }
-    }
-
-    (lhs.into(), rhs.into())
-}
-
-fn diff_vertical<'a>(lhs: &'a str, rhs: &'a str, diffmode: DiffMode) -> (Text<'a>, Text<'a>) {
-    let diff = match diffmode {
-        DiffMode::Char => similar::TextDiff::from_chars(lhs, rhs),
-        DiffMode::Word => similar::TextDiff::from_words(lhs, rhs),
-        DiffMode::Line => return diff_vertical_linewise(lhs, rhs),
-    };
-
-    let diff = similar::TextDiffConfig::default()
-        .newline_terminated(true)
-        .diff_words(lhs, rhs);
-
-    let mut lhs: Vec<Span<'_>> = vec![];
-    let mut rhs: Vec<Span<'_>> = vec![];
-    for hunk in diff.iter_all_changes() {
-        match hunk.tag() {
-            similar::ChangeTag::Equal => {
-                lhs.push(hunk.value().into());
-                rhs.push(hunk.value().into());
-            }
-            similar::ChangeTag::Delete => {
-                lhs.push(hunk.value().red());
-            }
-            similar::ChangeTag::Insert => {
-                rhs.push(hunk.value().green());
-            }
-        }
-    }
-    (Line::from(rhs).into(), Line::from(lhs).into())
-}
-
-fn diff_horizontal_linewise<'a>(lhs: &'a str, rhs: &'a str) -> Text<'a> {
-    let diff = similar::TextDiff::from_lines(lhs, rhs);
-
-    let mut text: Vec<Line<'_>> = vec![];
-    for hunk in diff.iter_all_changes() {
-        match hunk.tag() {
-            similar::ChangeTag::Equal => {
-                text.push(hunk.value().into());
-            }
-            similar::ChangeTag::Delete => {
-                text.push(hunk.value().red().into());
-            }
-            similar::ChangeTag::Insert => {
-                text.push(hunk.value().green().into());
-            }
-        }
-    }
-    text.into()
-}
-
-fn diff_horizontal<'a>(lhs: &'a str, rhs: &'a str, diffmode: DiffMode) -> Text<'a> {
-    let diff = match diffmode {
-        DiffMode::Char => similar::TextDiff::from_chars(lhs, rhs),
-        DiffMode::Word => similar::TextDiff::from_words(lhs, rhs),
-        DiffMode::Line => return diff_horizontal_linewise(lhs, rhs),
-    };
-    let mut text: Vec<Span<'_>> = vec![];
-    for hunk in diff.iter_all_changes() {
-        match hunk.tag() {
-            similar::ChangeTag::Equal => {
-                text.push(hunk.value().into());
-            }
-            similar::ChangeTag::Delete => {
-                text.push(hunk.value().red());
-            }
-            similar::ChangeTag::Insert => {
-                text.push(hunk.value().green());
-            }
-        }
-    }
-    Line::from(text).into()
-}
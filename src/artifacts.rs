@@ -0,0 +1,109 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Cursor, Read},
+    path::Path,
+    sync::OnceLock,
+};
+
+use color_eyre::{eyre::eyre, Result};
+
+use crate::compressed;
+
+/// The outputs in the archive of `--artifacts` or rebuilt from a `ui_test` log, set once at
+/// startup.
+static ARTIFACTS: OnceLock<Artifacts> = OnceLock::new();
+
+/// The `.stderr` and `.stdout` files of an archive of the build directory, by their path in it.
+/// Kept as bytes, as the outputs that aren't text are shown as hexdumps.
+#[derive(Debug, Default)]
+pub struct Artifacts {
+    outputs: HashMap<String, Vec<u8>>,
+}
+
+/// Reads the archive at `path`, a tarball (possibly compressed) or a zip, for [`get`].
+pub fn load(path: &Path) -> Result<()> {
+    let read = || -> io::Result<Artifacts> {
+        let bytes = compressed::decompress(fs::read(path)?)?;
+        if bytes.starts_with(b"PK\x03\x04") {
+            Artifacts::from_zip(bytes)
+        } else {
+            Artifacts::from_tar(&bytes)
+        }
+    };
+    let artifacts = read().map_err(|e| eyre!("Can't read {}: {e}", path.display()))?;
+    tracing::info!(path = %path.display(), outputs = artifacts.outputs.len(), "read the artifacts");
+    let _ = ARTIFACTS.set(artifacts);
+    Ok(())
+}
+
+/// Reads the actual outputs from `artifacts`, unless `--artifacts` was given.
+pub fn set(artifacts: Artifacts) {
+    let _ = ARTIFACTS.set(artifacts);
+}
+
+/// The artifacts, if the actual outputs are read from them instead of the build directory.
+pub fn get() -> Option<&'static Artifacts> {
+    ARTIFACTS.get()
+}
+
+fn is_output(path: &str) -> bool {
+    path.ends_with(".stderr") || path.ends_with(".stdout")
+}
+
+impl Artifacts {
+    fn from_tar(bytes: &[u8]) -> io::Result<Self> {
+        let mut artifacts = Self::default();
+        for entry in tar::Archive::new(bytes).entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.to_string_lossy().into_owned();
+            if entry.header().entry_type().is_file() && is_output(&path) {
+                let mut bytes = vec![];
+                entry.read_to_end(&mut bytes)?;
+                artifacts.outputs.insert(path, bytes);
+            }
+        }
+        Ok(artifacts)
+    }
+
+    fn from_zip(bytes: Vec<u8>) -> io::Result<Self> {
+        let mut artifacts = Self::default();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            let path = file.name().to_owned();
+            if file.is_file() && is_output(&path) {
+                let mut bytes = vec![];
+                file.read_to_end(&mut bytes)?;
+                artifacts.outputs.insert(path, bytes);
+            }
+        }
+        Ok(artifacts)
+    }
+
+    /// The actual output with `extension` of the test at `test`, like `tests/ui/foo/bar.rs`.
+    /// Found anywhere in the archive, as `ui/foo/bar/bar.stderr` under some directory.
+    pub fn actual(&self, test: &str, extension: &str) -> Option<Vec<u8>> {
+        let wanted = key(test, extension)?;
+        let found = self.outputs.iter().find(|(path, _)| {
+            path.strip_suffix(&wanted)
+                .is_some_and(|dir| dir.is_empty() || dir.ends_with('/'))
+        });
+        tracing::debug!(wanted, found = ?found.map(|(path, _)| path), "looked up in the artifacts");
+        found.map(|(_, bytes)| bytes.clone())
+    }
+
+    /// Adds `bytes` as the actual output with `extension` of the test at `test`.
+    pub fn insert(&mut self, test: &str, extension: &str, bytes: Vec<u8>) {
+        if let Some(key) = key(test, extension) {
+            self.outputs.insert(key, bytes);
+        }
+    }
+}
+
+/// Where the build directory has the output with `extension` of `test`, relative to the suites.
+fn key(test: &str, extension: &str) -> Option<String> {
+    let test = test.strip_prefix("tests/")?.strip_suffix(".rs")?;
+    let stem = test.rsplit('/').next()?;
+    Some(format!("{test}/{stem}.{extension}"))
+}
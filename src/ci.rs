@@ -0,0 +1,168 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    process::Command,
+};
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+
+use crate::{compressed, remote};
+
+/// What was downloaded from a CI run.
+#[derive(Debug)]
+pub struct Fetched {
+    /// The libtest JSON output of the run.
+    pub input: PathBuf,
+    /// A `--build-dir` for the test outputs, if they were among the artifacts.
+    pub build_dir: Option<String>,
+}
+
+/// Runs `gh` and returns what it printed.
+fn gh(args: &[&str]) -> Result<String> {
+    let mut command = Command::new("gh");
+    command.args(args);
+    let output = command
+        .output()
+        .map_err(|e| eyre!("Couldn't run gh, the GitHub CLI: {e}"))?;
+    tracing::debug!(?command, status = %output.status, "ran gh");
+    if !output.status.success() {
+        bail!(
+            "gh {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// The id of the run `id` refers to: itself if it's a run, else the latest run on the head commit
+/// of the PR.
+fn run_id(id: &str, repo: &str) -> Result<String> {
+    let number: u64 = id
+        .trim_start_matches('#')
+        .parse()
+        .map_err(|_| eyre!("{id} isn't a PR number or a run id"))?;
+    // PR numbers are far from the run ids, which are in the billions.
+    if number >= 100_000_000 {
+        return Ok(number.to_string());
+    }
+    let number = number.to_string();
+    let commit = gh(&[
+        "pr",
+        "view",
+        &number,
+        "--repo",
+        repo,
+        "--json",
+        "headRefOid",
+        "--jq",
+        ".headRefOid",
+    ])?;
+    let run = gh(&[
+        "run",
+        "list",
+        "--repo",
+        repo,
+        "--commit",
+        &commit,
+        "--limit",
+        "1",
+        "--json",
+        "databaseId",
+        "--jq",
+        ".[].databaseId",
+    ])?;
+    if run.is_empty() {
+        bail!("No CI runs for the head commit {commit} of #{number}");
+    }
+    Ok(run)
+}
+
+/// Downloads the artifacts of the CI run `id` (a PR or a run) that match `patterns`, or all of
+/// them, into `dir`, and finds the test output among them.
+pub fn fetch(id: &str, repo: &str, patterns: &[String], dir: Option<&Path>) -> Result<Fetched> {
+    let run = run_id(id, repo)?;
+    let dir = match dir {
+        Some(dir) => dir.to_owned(),
+        None => dirs::cache_dir()
+            .ok_or_else(|| eyre!("No cache directory to download to, pass --dir"))?
+            .join("compiletest-differ")
+            .join("ci")
+            .join(&run),
+    };
+    if dir.exists() {
+        println!("Using the artifacts of run {run} in {}", dir.display());
+    } else {
+        println!(
+            "Downloading the artifacts of run {run} to {}",
+            dir.display()
+        );
+        let dir_arg = dir.to_string_lossy();
+        let mut args = vec!["run", "download", &run, "--repo", repo, "--dir", &dir_arg];
+        for pattern in patterns {
+            args.extend(["--pattern", pattern]);
+        }
+        if let Err(e) = gh(&args) {
+            // Don't leave a partial download that would be used next time.
+            let _ = fs::remove_dir_all(&dir);
+            return Err(e);
+        }
+    }
+
+    let files = remote::files(&dir)?;
+    let inputs: Vec<_> = files.iter().filter(|path| is_libtest_json(path)).collect();
+    let input = match inputs[..] {
+        [input] => input.clone(),
+        [] => bail!(
+            "No libtest JSON output among the artifacts in {}",
+            dir.display()
+        ),
+        _ => bail!(
+            "The artifacts have several test outputs, review one of them with `view`:\n{}",
+            inputs
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+    };
+    Ok(Fetched {
+        input,
+        build_dir: files.iter().find_map(|path| ui_build_dir(path)),
+    })
+}
+
+/// Whether `path` is the JSON output of libtest, which starts with a `suite` event. Possibly
+/// compressed, like `foo.json.gz`.
+fn is_libtest_json(path: &Path) -> bool {
+    let is_json = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().contains(".json"));
+    if !is_json {
+        return false;
+    }
+    let Ok(bytes) = fs::read(path).and_then(compressed::decompress) else {
+        return false;
+    };
+    String::from_utf8_lossy(&bytes)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .any(|event| event["type"] == "suite")
+}
+
+/// A `--build-dir` for the outputs in the artifacts, from a file under `test/ui` like
+/// `build/x86_64-unknown-linux-gnu/test/ui/foo/bar/bar.stderr`.
+fn ui_build_dir(path: &Path) -> Option<String> {
+    let components: Vec<_> = path.components().collect();
+    let test = components.windows(2).position(|pair| {
+        pair == [
+            Component::Normal("test".as_ref()),
+            Component::Normal("ui".as_ref()),
+        ]
+    })?;
+    let test_dir: PathBuf = components[..=test].iter().collect();
+    Some(format!("{}/{{suite}}", test_dir.display()))
+}
@@ -0,0 +1,240 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Serialize;
+
+/// What kind of change a diff is, so that similar failures can be handled together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Classification {
+    /// Only the lines and columns of `--> $DIR/file.rs:L:C` changed, like after adding a line to
+    /// the test.
+    LineNumbersOnly,
+    /// The same diagnostics, but the spans they underline moved or grew within their lines.
+    SpanShift,
+    /// All the expected diagnostics are still there, with new ones.
+    NewDiagnostics,
+    /// Some of the expected diagnostics are gone, and there are no new ones.
+    RemovedDiagnostics,
+    /// The same kinds of diagnostics in the same order, with different messages or notes.
+    WordingChange,
+    /// There's output but none was expected.
+    NewOutput,
+    /// Output was expected but there's none.
+    OutputGone,
+    Other,
+}
+
+impl Classification {
+    pub fn new(expect: &str, actual: &str) -> Self {
+        if actual.is_empty() {
+            return Classification::OutputGone;
+        }
+        if expect.is_empty() {
+            return Classification::NewOutput;
+        }
+        let (expect, actual) = (without_line_numbers(expect), without_line_numbers(actual));
+        if expect == actual {
+            return Classification::LineNumbersOnly;
+        }
+        if without_span_positions(&expect) == without_span_positions(&actual) {
+            return Classification::SpanShift;
+        }
+        let (expected, actual) = (diagnostics(&expect), diagnostics(&actual));
+        let contains = |all: &[&str], some: &[&str]| {
+            let mut all = all.to_vec();
+            some.iter().all(|diagnostic| {
+                let found = all.iter().position(|d| d == diagnostic);
+                found.map(|i| all.swap_remove(i)).is_some()
+            })
+        };
+        if actual.len() > expected.len() && contains(&actual, &expected) {
+            Classification::NewDiagnostics
+        } else if actual.len() < expected.len() && contains(&expected, &actual) {
+            Classification::RemovedDiagnostics
+        } else if !expected.is_empty()
+            && expected
+                .iter()
+                .map(|d| header(d))
+                .eq(actual.iter().map(|d| header(d)))
+        {
+            Classification::WordingChange
+        } else {
+            Classification::Other
+        }
+    }
+
+    /// Whether the change is only in where things are, not in what the diagnostics say.
+    pub const fn is_trivial(self) -> bool {
+        matches!(
+            self,
+            Classification::LineNumbersOnly | Classification::SpanShift
+        )
+    }
+
+    /// As in the JSON report.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Classification::LineNumbersOnly => "line-numbers-only",
+            Classification::SpanShift => "span-shift",
+            Classification::NewDiagnostics => "new-diagnostics",
+            Classification::RemovedDiagnostics => "removed-diagnostics",
+            Classification::WordingChange => "wording-change",
+            Classification::NewOutput => "new-output",
+            Classification::OutputGone => "output-gone",
+            Classification::Other => "other",
+        }
+    }
+}
+
+fn without_line_numbers(output: &str) -> String {
+    static LOCATION: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(\.rs):\d+:\d+").expect("valid regex"));
+    LOCATION.replace_all(output, "$1:L:C").into_owned()
+}
+
+/// `output` with the whitespace and the lengths of the underlines like
+/// `   |     ---   ^^^ expected `u32`` collapsed, so that where they point doesn't matter.
+fn without_span_positions(output: &str) -> String {
+    static UNDERLINE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^\s*(LL)?\s*\|\s*[-^~+|]").expect("valid regex"));
+    output
+        .lines()
+        .map(|line| {
+            if UNDERLINE.is_match(line) {
+                let words = line.split_whitespace().map(|word| {
+                    if word.chars().all(|c| "-^~+".contains(c)) {
+                        let mut marks: Vec<_> = word.chars().collect();
+                        marks.dedup();
+                        marks.into_iter().collect()
+                    } else {
+                        word.to_owned()
+                    }
+                });
+                words.collect::<Vec<_>>().join(" ")
+            } else {
+                line.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The first line of a diagnostic, like `error[E0308]: mismatched types`.
+static HEADER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?m)^(error|warning|note|help)(\[\w+\])?: ").expect("valid regex")
+});
+
+/// Whether `line` starts a diagnostic, other than the summaries like
+/// `error: aborting due to 2 previous errors`.
+pub fn is_diagnostic_header(line: &str) -> bool {
+    let line = line.trim_end();
+    HEADER.is_match(line) && !is_summary(line)
+}
+
+fn is_summary(diagnostic: &str) -> bool {
+    diagnostic.starts_with("error: aborting due to")
+        || diagnostic.starts_with("warning: ") && diagnostic.ends_with(" emitted")
+}
+
+/// The diagnostics of `output`, each from its `error[E0308]: ...` line to the next one. The
+/// summaries are left out.
+fn diagnostics(output: &str) -> Vec<&str> {
+    let starts: Vec<_> = HEADER.find_iter(output).map(|m| m.start()).collect();
+    starts
+        .iter()
+        .zip(starts.iter().skip(1).chain([&output.len()]))
+        .map(|(&start, &end)| output[start..end].trim_end())
+        .filter(|diagnostic| !is_summary(diagnostic))
+        .collect()
+}
+
+/// The level and code of `diagnostic`, like `error[E0308]`.
+fn header(diagnostic: &str) -> &str {
+    diagnostic
+        .split_once(": ")
+        .map_or(diagnostic, |(header, _)| header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MISMATCH: &str = "\
+error[E0308]: mismatched types
+  --> $DIR/mismatch.rs:2:18
+   |
+LL |     let x: u32 = \"a\";
+   |            ---   ^^^ expected `u32`, found `&str`
+   |            |
+   |            expected due to this
+
+error: aborting due to 1 previous error
+
+For more information about this error, try `rustc --explain E0308`.
+";
+
+    const UNRESOLVED: &str = "\
+error[E0425]: cannot find function `foo` in this scope
+  --> $DIR/mismatch.rs:3:5
+   |
+LL |     foo();
+   |     ^^^ not found in this scope
+
+";
+
+    fn classify(expect: &str, actual: &str) -> &'static str {
+        Classification::new(expect, actual).name()
+    }
+
+    #[test]
+    fn line_numbers_only() {
+        let moved = MISMATCH.replace("mismatch.rs:2:18", "mismatch.rs:3:18");
+        assert_eq!(classify(MISMATCH, &moved), "line-numbers-only");
+        assert!(Classification::new(MISMATCH, &moved).is_trivial());
+    }
+
+    #[test]
+    fn span_shift() {
+        let shifted = MISMATCH.replace(
+            "   |            ---   ^^^ expected",
+            "   |            ---   ^^^^^ expected",
+        );
+        assert_eq!(classify(MISMATCH, &shifted), "span-shift");
+    }
+
+    #[test]
+    fn new_and_removed_diagnostics() {
+        let both =
+            format!("{UNRESOLVED}{MISMATCH}").replace("1 previous error", "2 previous errors");
+        assert_eq!(classify(MISMATCH, &both), "new-diagnostics");
+        assert_eq!(classify(&both, MISMATCH), "removed-diagnostics");
+    }
+
+    #[test]
+    fn wording_change() {
+        let reworded = MISMATCH.replace("expected due to this", "expected because of this");
+        assert_eq!(classify(MISMATCH, &reworded), "wording-change");
+        assert!(!Classification::new(MISMATCH, &reworded).is_trivial());
+    }
+
+    #[test]
+    fn output_appears_or_goes() {
+        assert_eq!(classify("", MISMATCH), "new-output");
+        assert_eq!(classify(MISMATCH, ""), "output-gone");
+        assert_eq!(classify(MISMATCH, UNRESOLVED), "other");
+    }
+
+    #[test]
+    fn summaries_are_not_diagnostics() {
+        assert!(is_diagnostic_header("error[E0308]: mismatched types"));
+        assert!(is_diagnostic_header("warning: unused variable: `x`"));
+        assert!(!is_diagnostic_header(
+            "error: aborting due to 2 previous errors"
+        ));
+        assert!(!is_diagnostic_header("warning: 1 warning emitted"));
+        assert!(!is_diagnostic_header(
+            "   = note: `#[warn(unused_variables)]` on by default"
+        ));
+    }
+}
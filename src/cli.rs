@@ -0,0 +1,231 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use compiletest_differ::{
+    report::ListFormat,
+    session::{FailOn, Status},
+    sort::SortOrder,
+    theme::Background,
+};
+
+/// Review the failing UI tests of a compiletest run.
+#[derive(Debug, Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Without a subcommand, the arguments of `view`.
+    #[command(flatten)]
+    pub view: ViewArgs,
+    /// Write what's being read, written and run to this file.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub log: Option<PathBuf>,
+    /// When done, print how long reading and parsing the input, loading each test, diffing and
+    /// drawing took, with the slowest tests.
+    #[arg(long, global = true)]
+    pub timings: bool,
+    /// Where the outputs of a test suite are, relative to the rust checkout unless absolute.
+    /// `{triple}` is replaced with the host triple, `{suite}` with the suite, like `ui`, and
+    /// `{stage}` with `--stage`. [default: build/{triple}/test/{suite}]
+    #[arg(long, global = true, value_name = "TEMPLATE")]
+    pub build_dir: Option<String>,
+    /// The stage of the compiler that ran the tests, for build directories with a directory per
+    /// stage. Also passed to `./x test` by `run`. [default: 1]
+    #[arg(long, global = true, value_name = "N")]
+    pub stage: Option<u32>,
+    /// A rust checkout on another machine, reached with `ssh`: the tests, their outputs and the
+    /// input are read from it, blesses are written to it and `git` and `./x` run on it.
+    #[arg(long, global = true, value_name = "USER@HOST:/PATH/TO/RUST")]
+    pub remote: Option<String>,
+    /// Read the actual outputs from this archive of the build directory, a tarball (possibly
+    /// compressed) or a zip, instead of from the build directory.
+    #[arg(long, global = true, value_name = "ARCHIVE")]
+    pub artifacts: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Review the failures in the TUI, what runs without a subcommand.
+    View(ViewArgs),
+    /// Bless every failed test without reviewing them, except the ones that timed out or made
+    /// compiletest panic.
+    BlessAll {
+        #[command(flatten)]
+        input: Input,
+    },
+    /// Print a summary of the failures.
+    #[command(group(clap::ArgGroup::new("format").required(true)))]
+    Report {
+        #[command(flatten)]
+        input: Input,
+        /// As markdown with the diffs folded, for GitHub comments.
+        #[arg(long, group = "format")]
+        markdown: bool,
+        /// As JSON, with the diff stats and a classification of every stream.
+        #[arg(long, group = "format")]
+        json: bool,
+        /// As GitHub Actions annotations on the tests, at the first diagnostic that changed.
+        #[arg(long, group = "format")]
+        github: bool,
+        /// As the whole diffs in color, for reading in a terminal or with `less -R`.
+        #[arg(long, group = "format")]
+        text: bool,
+        /// As a standalone HTML page with the diffs folded.
+        #[arg(long, group = "format")]
+        html: bool,
+    },
+    /// Compare the failures of two runs: the tests that started failing, that were fixed and that
+    /// still fail.
+    CompareRuns {
+        /// The libtest JSON output of the earlier run.
+        old: PathBuf,
+        /// The libtest JSON output of the later run.
+        new: PathBuf,
+        #[command(flatten)]
+        filter: Filter,
+    },
+    /// Print the UI tests whose actual output in the build directory differs from the expected
+    /// one, for when there's no JSON output of the run.
+    Scan {
+        #[command(flatten)]
+        filter: Filter,
+    },
+    /// Print the results of a test in the runs recorded with `history` in the config, and since
+    /// when it's been failing.
+    History {
+        /// The path of the test, like `tests/ui/foo/bar.rs`.
+        test: String,
+    },
+    /// Download the test output of a CI run with the GitHub CLI, `gh`, and review it.
+    FetchCi {
+        /// The number of a PR, for the latest run on its head commit, or the id of a run.
+        id: String,
+        /// The repository of the run.
+        #[arg(long, default_value = "rust-lang/rust")]
+        repo: String,
+        /// Only download the artifacts whose name matches this glob, like the job that failed.
+        /// Can be repeated. Include the one with the test outputs of the build directory to see
+        /// the actual outputs.
+        #[arg(long, value_name = "GLOB")]
+        artifact: Vec<String>,
+        /// Where to download to, the cache directory by default. Reused if it exists.
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        #[command(flatten)]
+        filter: Filter,
+    },
+    /// Run UI tests with `./x test` and review the ones that fail.
+    Run {
+        /// Tests or directories to run, all the UI tests if none.
+        #[arg(default_value = "tests/ui")]
+        tests: Vec<String>,
+        /// Where to keep the JSON output of the run, relative to the rust checkout.
+        #[arg(long, default_value = "build/compiletest-differ.json")]
+        output: PathBuf,
+    },
+}
+
+/// Which tests of a run to look at.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct Input {
+    /// The libtest JSON output of the test run, possibly compressed with gzip, zstd or xz. The
+    /// `libtest-json` output of cargo-nextest and the output of `ui_test` work too.
+    #[arg(default_value = "/home/ardi/repos/rust/blah.json")]
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub filter: Filter,
+}
+
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct Filter {
+    /// Only take the tests whose path matches. Globs match the whole path
+    /// (`tests/ui/async-await/**`), plain strings match anywhere in it. Can be repeated.
+    #[arg(long, value_name = "PATTERN")]
+    pub filter: Vec<String>,
+    /// Skip the tests whose path matches, with the same syntax as `--filter`. Can be repeated.
+    #[arg(long, value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+    /// Interpret the patterns as regular expressions instead of globs.
+    #[arg(long)]
+    pub regex: bool,
+    /// Only take the failed tests compiled with this edition, as set by their `//@ edition`
+    /// directive, 2015 without one. Can be repeated.
+    #[arg(long, value_name = "EDITION")]
+    pub edition: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct ViewArgs {
+    #[command(flatten)]
+    pub input: Input,
+    /// Start at this test, given as its number in the review (as shown in the top bar) or as
+    /// (part of) its path.
+    #[arg(long, value_name = "PATH_OR_INDEX")]
+    pub start_at: Option<String>,
+    /// Don't offer to resume the previous session over the same input, start a new one.
+    #[arg(long)]
+    pub fresh: bool,
+    /// Only review the tests that were given this status in the previous session. Can be
+    /// repeated.
+    #[arg(long, value_enum, conflicts_with = "fresh")]
+    pub status: Vec<Status>,
+    /// Order in which to review the tests.
+    #[arg(long, value_enum, default_value_t)]
+    pub sort: SortOrder,
+    /// Color theme, built-in (`default`, `light`, `high-contrast`, `colorblind`) or defined in the config file.
+    #[arg(long, value_name = "NAME")]
+    pub theme: Option<String>,
+    /// Show diffs with this shell command too (`X`), like `difft --color=always` or
+    /// `delta {expected} {actual}`. The files are appended if there's no placeholder.
+    #[arg(long, value_name = "COMMAND")]
+    pub diff_tool: Option<String>,
+    /// Merge tool for `M`, with the same syntax as `--diff-tool`. The edits to the expected side
+    /// are written as the new expected output. Defaults to `$MERGETOOL`, then `vimdiff`.
+    #[arg(long, value_name = "COMMAND")]
+    pub merge_tool: Option<String>,
+    /// The terminal background, for picking the default theme.
+    #[arg(long, value_enum)]
+    pub background: Option<Background>,
+    /// When done, commit the blessed expected outputs to the rust repo, with a message listing
+    /// the tests. Other changes, staged or not, are left out of the commit.
+    #[arg(long)]
+    pub commit: bool,
+    /// Print the tests that would be reviewed, one per line, instead of opening the TUI. `long`
+    /// adds a line per stream with the stream, the diff size and its classification,
+    /// separated by tabs.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        default_missing_value = "paths"
+    )]
+    pub list: Option<ListFormat>,
+    /// Exit with an error if these tests remain when the review ends.
+    #[arg(long, value_enum, default_value_t)]
+    pub fail_on: FailOn,
+    /// Reload the current test when its actual outputs or the JSON output are rewritten, like
+    /// when rerunning the tests in another terminal.
+    #[arg(long)]
+    pub watch: bool,
+    /// Load and diff all the tests in the background when starting, so that moving between them
+    /// and the stats of the tree are instant afterwards.
+    #[arg(long)]
+    pub preload: bool,
+    /// When done, write the marks (blessed, reviewed, skipped, deferred) to this file as JSON, to
+    /// merge them into another review of the run with `--import`.
+    #[arg(long, value_name = "FILE")]
+    pub export: Option<PathBuf>,
+    /// Add the marks of another review written by `--export` to this one. The tests marked here
+    /// keep their mark. Can be repeated.
+    #[arg(long, value_name = "FILE")]
+    pub import: Vec<PathBuf>,
+    /// Write the keys pressed and when to this file, to show a bug or how a review goes with
+    /// `--replay`.
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+    /// Press the keys written by `--record` at the same pace. Start from the same input and flags,
+    /// like `--fresh`, for the keys to do the same. The keyboard keeps working.
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+}
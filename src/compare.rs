@@ -0,0 +1,30 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use crate::Events;
+
+/// What changed between the failures of two runs.
+pub fn compare(old: &Events<'_>, new: &Events<'_>) -> String {
+    let failures = |events: &Events<'_>| -> BTreeSet<String> {
+        events
+            .failed
+            .iter()
+            .chain(&events.other_failed)
+            .map(|&name| name.to_owned())
+            .collect()
+    };
+    let (old, new) = (failures(old), failures(new));
+
+    let mut out = String::new();
+    for (title, tests) in [
+        ("Started failing", new.difference(&old).collect::<Vec<_>>()),
+        ("Fixed", old.difference(&new).collect()),
+    ] {
+        // Writing to a `String` can't fail.
+        let _ = writeln!(out, "{title} ({}):", tests.len());
+        for test in tests {
+            let _ = writeln!(out, "    {test}");
+        }
+    }
+    let _ = writeln!(out, "Still failing: {}", old.intersection(&new).count());
+    out
+}
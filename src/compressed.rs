@@ -0,0 +1,21 @@
+use std::io::{self, Read};
+
+/// `bytes`, decompressed if they're gzip, zstd or xz, recognized by their magic number.
+pub fn decompress(bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut out = vec![];
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        tracing::debug!("decompressing gzip");
+        flate2::read::MultiGzDecoder::new(&bytes[..]).read_to_end(&mut out)?;
+    } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        tracing::debug!("decompressing zstd");
+        ruzstd::decoding::StreamingDecoder::new(&bytes[..])
+            .map_err(io::Error::other)?
+            .read_to_end(&mut out)?;
+    } else if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0]) {
+        tracing::debug!("decompressing xz");
+        lzma_rs::xz_decompress(&mut &bytes[..], &mut out).map_err(io::Error::other)?;
+    } else {
+        return Ok(bytes);
+    }
+    Ok(out)
+}
@@ -0,0 +1,111 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use color_eyre::{eyre::eyre, Result};
+use ron::extensions::Extensions;
+use serde::Deserialize;
+
+use crate::theme::{Background, Theme};
+
+/// `config.ron` in the config directory.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FileConfig {
+    /// Name of the theme to use, built-in or from `themes`.
+    pub theme: Option<String>,
+    pub themes: BTreeMap<String, Theme>,
+    pub background: Background,
+    /// See `--diff-tool`.
+    pub diff_tool: Option<String>,
+    /// See `--merge-tool`.
+    pub merge_tool: Option<String>,
+    /// The command that `yx` copies, `{path}` is replaced with the path of the test.
+    pub x_command: Option<String>,
+    /// `git add` every blessed file, so that what's staged is what was reviewed.
+    pub git_add: bool,
+    /// See `--build-dir`.
+    pub build_dir: Option<String>,
+    /// See `--stage`.
+    pub stage: Option<u32>,
+    /// Shell command run after blessing a file, in the rust checkout, like `./x fmt {file}`.
+    /// `{test}` and `{file}` are replaced with the paths of the test and of the written file, and
+    /// appended if there's no placeholder.
+    pub post_bless: Option<String>,
+    /// Shell command run before blessing a file, with the same placeholders as `post_bless` and
+    /// the new expected output on stdin, like a lint. If it fails the file isn't blessed.
+    pub pre_bless: Option<String>,
+    /// Record the results of every run that's reviewed, for `history`.
+    pub history: bool,
+    /// Go to the first test after the last one and to the last one before the first, instead of
+    /// ending the review after the last test.
+    pub wrap_around: bool,
+    /// How many columns a tab takes in the code and the diff, 4 by default.
+    pub tab_width: Option<usize>,
+    /// Where blessing goes: `stay`, `next_stream` (the default) or `next_test`.
+    pub after_bless: AfterBless,
+    /// Ask before every bless, not only when the output is an ICE or the expected file has
+    /// uncommitted changes.
+    pub confirm_bless: bool,
+}
+
+impl FileConfig {
+    pub fn path() -> Option<PathBuf> {
+        Some(
+            dirs::config_dir()?
+                .join("compiletest-differ")
+                .join("config.ron"),
+        )
+    }
+
+    /// The config file, or the defaults if there's none.
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path().filter(|p| p.exists()) else {
+            return Ok(Self::default());
+        };
+        let text = fs::read_to_string(&path)?;
+        // So that optional fields can be written without `Some(..)`.
+        ron::Options::default()
+            .with_default_extension(Extensions::IMPLICIT_SOME)
+            .from_str(&text)
+            .map_err(|e| eyre!("Invalid config at {}: {e}", path.display()))
+    }
+
+    /// Looks up `name` (or the configured theme) among the user's and the built-in themes. If
+    /// there's none, picks the default for the `background` (or the configured one).
+    pub fn theme(&self, name: Option<&str>, background: Option<Background>) -> Result<Theme> {
+        let Some(name) = name.or(self.theme.as_deref()) else {
+            let light = background.unwrap_or(self.background).is_light();
+            return Ok(if light { Theme::LIGHT } else { Theme::DEFAULT });
+        };
+        self.themes
+            .get(name)
+            .copied()
+            .or_else(|| {
+                Theme::BUILT_IN
+                    .iter()
+                    .find(|(built_in, _)| *built_in == name)
+                    .map(|(_, theme)| *theme)
+            })
+            .ok_or_else(|| {
+                let names = Theme::BUILT_IN
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .chain(self.themes.keys().map(String::as_str))
+                    .collect::<Vec<_>>();
+                eyre!("Unknown theme {name}, the themes are: {}", names.join(", "))
+            })
+    }
+}
+
+/// What's shown after blessing an output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AfterBless {
+    /// The blessed output, to look at it again before moving on.
+    Stay,
+    /// The next differing output of the test, or the next test after the last one. Only the
+    /// outputs that differ are stepped through, so a test with only its stderr differing moves on.
+    #[default]
+    NextStream,
+    /// The next test, even if another output of this one differs.
+    NextTest,
+}
@@ -0,0 +1,493 @@
+use std::time::Duration;
+
+use ratatui::{
+    style::Style,
+    text::{Line, Span, Text},
+};
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+
+use crate::{app::DiffMode, theme::Theme};
+
+/// The kind of change a line went through, as far as the differ is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineTag {
+    Equal,
+    Delete,
+    Insert,
+    /// Old position of a diagnostic that is present on both sides but at a different position.
+    MovedFrom,
+    /// New position of a moved diagnostic.
+    MovedTo,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DiffLine<'a> {
+    pub tag: LineTag,
+    pub value: &'a str,
+}
+
+impl<'a> DiffLine<'a> {
+    fn styled(self, theme: &Theme) -> Line<'a> {
+        let (marker, style) = match self.tag {
+            LineTag::Equal => ("  ", Style::new()),
+            LineTag::Delete => ("- ", theme.delete.into()),
+            LineTag::Insert => ("+ ", theme.insert.into()),
+            LineTag::MovedFrom | LineTag::MovedTo => ("~ ", theme.moved.into()),
+        };
+        if theme.markers {
+            Line::from(vec![
+                Span::styled(marker, style),
+                Span::styled(self.value, style),
+            ])
+        } else {
+            Line::styled(self.value, style)
+        }
+    }
+}
+
+/// How long diffing may take before settling for a diff that isn't minimal. Some outputs, like
+/// thousands of repeated lines, take seconds otherwise.
+const DIFF_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Diffs `lhs` and `rhs` by lines, within [`DIFF_TIMEOUT`].
+pub fn text_diff<'a>(lhs: &'a str, rhs: &'a str) -> TextDiff<'a, 'a, 'a, str> {
+    TextDiff::configure()
+        .timeout(DIFF_TIMEOUT)
+        .diff_lines(lhs, rhs)
+}
+
+/// Linewise diff of `lhs` and `rhs` with moved diagnostics detected.
+pub fn line_diff<'a>(lhs: &'a str, rhs: &'a str) -> Vec<DiffLine<'a>> {
+    let diff = text_diff(lhs, rhs);
+    let mut lines: Vec<_> = diff
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            tag: match change.tag() {
+                ChangeTag::Equal => LineTag::Equal,
+                ChangeTag::Delete => LineTag::Delete,
+                ChangeTag::Insert => LineTag::Insert,
+            },
+            value: change.value(),
+        })
+        .collect();
+    mark_moved(&mut lines);
+    lines
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct DiffStats {
+    pub inserted: usize,
+    pub deleted: usize,
+    /// Lines of moved diagnostics, counted once.
+    pub moved: usize,
+    pub hunks: usize,
+}
+
+impl DiffStats {
+    pub fn new(lines: &[DiffLine<'_>]) -> Self {
+        let count = |tag| lines.iter().filter(|line| line.tag == tag).count();
+        Self {
+            inserted: count(LineTag::Insert),
+            deleted: count(LineTag::Delete),
+            moved: count(LineTag::MovedTo),
+            hunks: hunk_rows(lines, false).len(),
+        }
+    }
+}
+
+/// Number of hunks, and of inserted and deleted lines. Moved diagnostics don't count.
+pub fn diff_size(lhs: &str, rhs: &str) -> (usize, usize) {
+    let stats = DiffStats::new(&line_diff(lhs, rhs));
+    (stats.hunks, stats.inserted + stats.deleted)
+}
+
+/// How much of `lhs` and `rhs` is the same, from 0 to 1, by lines. Also the number of lines, for
+/// weighing it against others.
+pub fn similarity(lhs: &str, rhs: &str) -> (f32, usize) {
+    let diff = text_diff(lhs, rhs);
+    let lines = diff.old_slices().len() + diff.new_slices().len();
+    (diff.ratio(), lines)
+}
+
+/// The text of every row of the diff panes: a single pane in the unified view, expected and
+/// actual side by side.
+pub fn pane_rows<'a>(lines: &[DiffLine<'a>], side_by_side: bool) -> Vec<Vec<&'a str>> {
+    let rows = |keep: fn(LineTag) -> bool| {
+        lines
+            .iter()
+            .filter(|line| keep(line.tag))
+            .map(|line| line.value)
+            .collect()
+    };
+    if side_by_side {
+        vec![
+            rows(|tag| matches!(tag, LineTag::Equal | LineTag::Delete | LineTag::MovedFrom)),
+            rows(|tag| matches!(tag, LineTag::Equal | LineTag::Insert | LineTag::MovedTo)),
+        ]
+    } else {
+        vec![rows(|_| true)]
+    }
+}
+
+/// Display rows at which each changed hunk starts. In side by side mode the rows are counted on
+/// the expected side.
+pub fn hunk_rows(lines: &[DiffLine<'_>], side_by_side: bool) -> Vec<usize> {
+    let mut rows = vec![];
+    let mut row = 0;
+    let mut in_hunk = false;
+    for line in lines {
+        let changed = line.tag != LineTag::Equal;
+        if changed && !in_hunk {
+            rows.push(row);
+        }
+        in_hunk = changed;
+        let shown = !side_by_side
+            || matches!(
+                line.tag,
+                LineTag::Equal | LineTag::Delete | LineTag::MovedFrom
+            );
+        if shown {
+            row += 1;
+        }
+    }
+    rows.dedup();
+    rows
+}
+
+/// Splits the runs of `tag` lines into blocks separated by blank lines. rustc separates every
+/// diagnostic with an empty line, so each block is (roughly) one diagnostic.
+fn blocks(lines: &[DiffLine<'_>], tag: LineTag) -> Vec<(usize, usize)> {
+    let mut blocks = vec![];
+    let mut start = None;
+    for (i, line) in lines.iter().enumerate() {
+        let in_block = line.tag == tag && !line.value.trim().is_empty();
+        match (start, in_block) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                blocks.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        blocks.push((s, lines.len()));
+    }
+    blocks
+}
+
+/// Turns a deleted block that is reinserted verbatim somewhere else into a pair of moved blocks.
+fn mark_moved(lines: &mut [DiffLine<'_>]) {
+    let deleted = blocks(lines, LineTag::Delete);
+    let mut inserted = blocks(lines, LineTag::Insert);
+
+    let same = |lines: &[DiffLine<'_>], a: (usize, usize), b: (usize, usize)| {
+        a.1 - a.0 == b.1 - b.0
+            && lines[a.0..a.1]
+                .iter()
+                .zip(&lines[b.0..b.1])
+                .all(|(a, b)| a.value.trim_end() == b.value.trim_end())
+    };
+
+    for del in deleted {
+        let Some(pos) = inserted.iter().position(|&ins| same(lines, del, ins)) else {
+            continue;
+        };
+        let ins = inserted.swap_remove(pos);
+        for line in &mut lines[del.0..del.1] {
+            line.tag = LineTag::MovedFrom;
+        }
+        for line in &mut lines[ins.0..ins.1] {
+            line.tag = LineTag::MovedTo;
+        }
+    }
+}
+
+/// Receives a diff piece by piece from [`feed`], for frontends that show diffs their own way.
+pub trait DiffSink<'a> {
+    /// A line of a linewise diff, with its newline.
+    fn line(&mut self, line: DiffLine<'a>);
+    /// A run of chars or words of a finer diff, which can span lines. Never tagged as moved.
+    fn fragment(&mut self, tag: LineTag, value: &'a str);
+}
+
+/// Above this many bytes of both sides in total, char and word diffs are too slow to compute and
+/// lines are diffed instead.
+pub const FINE_DIFF_LIMIT: usize = 256 * 1024;
+
+/// Whether diffing `lhs` and `rhs` by `mode` would take too long, see [`FINE_DIFF_LIMIT`].
+pub fn too_big_for(lhs: &str, rhs: &str, mode: DiffMode) -> bool {
+    !matches!(mode, DiffMode::Line) && lhs.len() + rhs.len() > FINE_DIFF_LIMIT
+}
+
+/// Diffs `lhs` and `rhs` into `sink`, by lines (with moved diagnostics detected) or finer.
+pub fn feed<'a>(lhs: &'a str, rhs: &'a str, mode: DiffMode, sink: &mut impl DiffSink<'a>) {
+    let mut config = similar::TextDiffConfig::default();
+    config.newline_terminated(true).timeout(DIFF_TIMEOUT);
+    let diff = match mode {
+        // By graphemes and unicode words, so that a combining mark or a wide character made of
+        // several chars isn't split between fragments.
+        DiffMode::Char => config.diff_graphemes(lhs, rhs),
+        DiffMode::Word => config.diff_unicode_words(lhs, rhs),
+        DiffMode::Line => {
+            for line in line_diff(lhs, rhs) {
+                sink.line(line);
+            }
+            return;
+        }
+    };
+    for change in diff.iter_all_changes() {
+        let tag = match change.tag() {
+            ChangeTag::Equal => LineTag::Equal,
+            ChangeTag::Delete => LineTag::Delete,
+            ChangeTag::Insert => LineTag::Insert,
+        };
+        sink.fragment(tag, change.value());
+    }
+}
+
+fn fragment_style(tag: LineTag, theme: &Theme) -> Style {
+    match tag {
+        LineTag::Equal => Style::new(),
+        LineTag::Delete | LineTag::MovedFrom => theme.delete.into(),
+        LineTag::Insert | LineTag::MovedTo => theme.insert.into(),
+    }
+}
+
+/// Both sides in one pane, the TUI's [`DiffSink`].
+struct Unified<'a, 't> {
+    theme: &'t Theme,
+    lines: Vec<Line<'a>>,
+    fragments: Vec<Line<'a>>,
+}
+
+impl<'a> DiffSink<'a> for Unified<'a, '_> {
+    fn line(&mut self, line: DiffLine<'a>) {
+        self.lines.push(line.styled(self.theme));
+    }
+
+    fn fragment(&mut self, tag: LineTag, value: &'a str) {
+        push_fragment(&mut self.fragments, value, fragment_style(tag, self.theme));
+    }
+}
+
+/// Each side in its own pane, the TUI's other [`DiffSink`].
+struct SideBySide<'a, 't> {
+    theme: &'t Theme,
+    left: Vec<Line<'a>>,
+    right: Vec<Line<'a>>,
+    left_fragments: Vec<Line<'a>>,
+    right_fragments: Vec<Line<'a>>,
+}
+
+impl<'a> DiffSink<'a> for SideBySide<'a, '_> {
+    fn line(&mut self, line: DiffLine<'a>) {
+        match line.tag {
+            LineTag::Equal => {
+                self.left.push(line.styled(self.theme));
+                self.right.push(line.styled(self.theme));
+            }
+            LineTag::Delete | LineTag::MovedFrom => self.left.push(line.styled(self.theme)),
+            LineTag::Insert | LineTag::MovedTo => self.right.push(line.styled(self.theme)),
+        }
+    }
+
+    fn fragment(&mut self, tag: LineTag, value: &'a str) {
+        let style = fragment_style(tag, self.theme);
+        match tag {
+            LineTag::Equal => {
+                push_fragment(&mut self.left_fragments, value, style);
+                push_fragment(&mut self.right_fragments, value, style);
+            }
+            LineTag::Delete | LineTag::MovedFrom => {
+                push_fragment(&mut self.left_fragments, value, style)
+            }
+            LineTag::Insert | LineTag::MovedTo => {
+                push_fragment(&mut self.right_fragments, value, style)
+            }
+        }
+    }
+}
+
+/// Appends a fragment of a fine diff to `lines`, which can span lines, so that the rows of the
+/// fine diffs line up with those of the line diff.
+fn push_fragment<'a>(lines: &mut Vec<Line<'a>>, value: &'a str, style: Style) {
+    if lines.is_empty() {
+        lines.push(Line::default());
+    }
+    for (i, piece) in value.split('\n').enumerate() {
+        if i > 0 {
+            lines.push(Line::default());
+        }
+        if !piece.is_empty() {
+            let line = lines.last_mut().expect("pushed above");
+            line.push_span(Span::styled(piece, style));
+        }
+    }
+}
+
+fn into_text<'a>(lines: Vec<Line<'a>>, mut fragments: Vec<Line<'a>>) -> Text<'a> {
+    if fragments.is_empty() {
+        return lines.into();
+    }
+    // After the last newline.
+    if fragments.last().is_some_and(|line| line.spans.is_empty()) {
+        fragments.pop();
+    }
+    fragments.into()
+}
+
+pub fn diff_vertical<'a>(
+    lhs: &'a str,
+    rhs: &'a str,
+    diffmode: DiffMode,
+    theme: &Theme,
+) -> (Text<'a>, Text<'a>) {
+    let mut sink = SideBySide {
+        theme,
+        left: vec![],
+        right: vec![],
+        left_fragments: vec![],
+        right_fragments: vec![],
+    };
+    feed(lhs, rhs, diffmode, &mut sink);
+    (
+        into_text(sink.left, sink.left_fragments),
+        into_text(sink.right, sink.right_fragments),
+    )
+}
+
+pub fn diff_horizontal<'a>(
+    lhs: &'a str,
+    rhs: &'a str,
+    diffmode: DiffMode,
+    theme: &Theme,
+) -> Text<'a> {
+    let mut sink = Unified {
+        theme,
+        lines: vec![],
+        fragments: vec![],
+    };
+    feed(lhs, rhs, diffmode, &mut sink);
+    into_text(sink.lines, sink.fragments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOT_FOUND: &str = "\
+error[E0425]: cannot find function `foo` in this scope
+  --> $DIR/moved.rs:3:5
+   |
+LL |     foo();
+   |     ^^^ not found in this scope
+";
+
+    const MISMATCHED: &str = "\
+error[E0308]: mismatched types
+  --> $DIR/moved.rs:2:18
+   |
+LL |     let x: u32 = \"a\";
+   |            ---   ^^^ expected `u32`, found `&str`
+   |            |
+   |            expected due to this
+";
+
+    const SUMMARY: &str = "\
+error: aborting due to 2 previous errors
+
+Some errors have detailed explanations: E0308, E0425.
+For more information about an error, try `rustc --explain E0308`.
+";
+
+    fn stderr(diagnostics: &[&str]) -> String {
+        let mut stderr = diagnostics.join("\n");
+        stderr.push('\n');
+        stderr.push_str(SUMMARY);
+        stderr
+    }
+
+    /// The first line of each run of lines tagged `tag`.
+    fn runs(lines: &[DiffLine<'_>], tag: LineTag) -> Vec<usize> {
+        (0..lines.len())
+            .filter(|&i| lines[i].tag == tag && (i == 0 || lines[i - 1].tag != tag))
+            .collect()
+    }
+
+    /// The lines tagged `tag`, joined.
+    fn tagged(lines: &[DiffLine<'_>], tag: LineTag) -> String {
+        lines
+            .iter()
+            .filter(|line| line.tag == tag)
+            .map(|line| line.value)
+            .collect()
+    }
+
+    #[test]
+    fn marks_a_block_moved_up() {
+        let (expect, actual) = (
+            stderr(&[MISMATCHED, NOT_FOUND]),
+            stderr(&[NOT_FOUND, MISMATCHED]),
+        );
+        let lines = line_diff(&expect, &actual);
+        // Only the empty line separating it from the next diagnostic isn't moved.
+        assert_eq!(tagged(&lines, LineTag::Insert), "\n");
+        assert_eq!(tagged(&lines, LineTag::Delete), "\n");
+        assert_eq!(DiffStats::new(&lines).moved, NOT_FOUND.lines().count());
+        assert_eq!(tagged(&lines, LineTag::MovedTo), NOT_FOUND);
+        assert_eq!(tagged(&lines, LineTag::MovedFrom), NOT_FOUND);
+        // It's shown where it's now, above where it was.
+        assert_eq!(runs(&lines, LineTag::MovedTo), [0]);
+        assert!(runs(&lines, LineTag::MovedFrom)[0] > NOT_FOUND.lines().count());
+    }
+
+    #[test]
+    fn marks_a_block_moved_down() {
+        let (expect, actual) = (
+            stderr(&[NOT_FOUND, MISMATCHED]),
+            stderr(&[MISMATCHED, NOT_FOUND]),
+        );
+        let lines = line_diff(&expect, &actual);
+        assert_eq!(tagged(&lines, LineTag::MovedFrom), NOT_FOUND);
+        assert_eq!(tagged(&lines, LineTag::MovedTo), NOT_FOUND);
+        assert_eq!(runs(&lines, LineTag::MovedFrom), [0]);
+        assert!(runs(&lines, LineTag::MovedTo)[0] > NOT_FOUND.lines().count());
+    }
+
+    #[test]
+    fn keeps_a_block_that_moved_and_changed_as_a_change() {
+        let changed = NOT_FOUND.replace("3:5", "4:5");
+        let (expect, actual) = (
+            stderr(&[MISMATCHED, NOT_FOUND]),
+            stderr(&[&changed, MISMATCHED]),
+        );
+        let lines = line_diff(&expect, &actual);
+        assert_eq!(DiffStats::new(&lines).moved, 0);
+        assert!(tagged(&lines, LineTag::Delete).contains(NOT_FOUND));
+        assert!(tagged(&lines, LineTag::Insert).contains(&changed));
+    }
+
+    #[test]
+    fn finds_the_rows_of_the_hunks() {
+        let expect = "a\nb\nc\nd\ne\nf\n";
+        let actual = "a\nB\nc\nd\nE\nF\nG\n";
+        let lines = line_diff(expect, actual);
+        // a, -b, +B, c, d, -e, -f, +E, +F, +G
+        assert_eq!(hunk_rows(&lines, false), [1, 5]);
+        // a, b, c, d, e, f on the expected side.
+        assert_eq!(hunk_rows(&lines, true), [1, 4]);
+        let rows = pane_rows(&lines, true);
+        assert_eq!((rows[0].len(), rows[1].len()), (6, 7));
+        assert!(hunk_rows(&line_diff(expect, expect), false).is_empty());
+    }
+
+    #[test]
+    fn counts_the_rows_of_a_hunk_that_only_inserts_on_the_expected_side() {
+        let lines = line_diff("a\nb\n", "a\nnew\nb\n");
+        assert_eq!(hunk_rows(&lines, false), [1]);
+        // The insertion isn't shown on the expected side, it starts where `b` is.
+        assert_eq!(hunk_rows(&lines, true), [1]);
+    }
+}
@@ -0,0 +1,128 @@
+//! The diffs of the test shown and the comparison of its annotations, computed once rather than
+//! for every frame, as diffing big outputs takes a while. They're dropped whenever the test is
+//! read again, like after reloading, blessing or rerunning it, and when the colors change.
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::Path,
+};
+
+use ratatui::text::{Line, Span, Text};
+
+use crate::{
+    annotations::Comparison,
+    app::DiffMode,
+    classify::Classification,
+    diff::{
+        diff_horizontal, diff_vertical, hunk_rows, line_diff, pane_rows, similarity, DiffStats,
+    },
+    outputs::TestData,
+    theme::Theme,
+    timings::{self, Phase},
+    Stream,
+};
+
+/// What the top bar and the hunk navigation show of a diff.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    /// In percent.
+    pub similarity: f32,
+    pub classification: &'static str,
+    pub stats: DiffStats,
+    /// The rows of the longest diff pane.
+    pub rows: usize,
+    pub hunk_starts: Vec<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct DiffCache {
+    /// By stream and whether the diff is side by side.
+    summaries: HashMap<(Stream, bool), Summary>,
+    vertical: HashMap<(Stream, DiffMode), (Text<'static>, Text<'static>)>,
+    horizontal: HashMap<(Stream, DiffMode), Text<'static>>,
+    annotations: HashMap<Stream, Text<'static>>,
+}
+
+impl DiffCache {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn summary(&mut self, data: &TestData, side_by_side: bool) -> &Summary {
+        self.summaries
+            .entry((data.stream, side_by_side))
+            .or_insert_with(|| {
+                timings::time(Phase::Diff, &data.test_name, || {
+                    let (expect, actual) = (&data.expect, &data.actual);
+                    let lines = line_diff(expect, actual);
+                    Summary {
+                        similarity: similarity(expect, actual).0 * 100.0,
+                        classification: Classification::new(expect, actual).name(),
+                        stats: DiffStats::new(&lines),
+                        rows: pane_rows(&lines, side_by_side)
+                            .iter()
+                            .map(Vec::len)
+                            .max()
+                            .unwrap_or_default(),
+                        hunk_starts: hunk_rows(&lines, side_by_side),
+                    }
+                })
+            })
+    }
+
+    /// The expected and actual sides of [`diff_vertical`].
+    pub fn vertical(
+        &mut self,
+        data: &TestData,
+        mode: DiffMode,
+        theme: &Theme,
+    ) -> (Text<'static>, Text<'static>) {
+        match self.vertical.entry((data.stream, mode)) {
+            Entry::Occupied(sides) => sides.get().clone(),
+            Entry::Vacant(entry) => {
+                let (expect, actual) = diff_vertical(&data.expect, &data.actual, mode, theme);
+                entry.insert((owned(expect), owned(actual))).clone()
+            }
+        }
+    }
+
+    /// The unified diff of [`diff_horizontal`].
+    pub fn horizontal(&mut self, data: &TestData, mode: DiffMode, theme: &Theme) -> Text<'static> {
+        self.horizontal
+            .entry((data.stream, mode))
+            .or_insert_with(|| owned(diff_horizontal(&data.expect, &data.actual, mode, theme)))
+            .clone()
+    }
+
+    /// The [`Comparison`] of the annotations of the test with the diagnostics of the stream.
+    pub fn annotations(&mut self, data: &TestData, theme: &Theme) -> Text<'static> {
+        self.annotations
+            .entry(data.stream)
+            .or_insert_with(|| {
+                let file_name = Path::new(&data.test_name)
+                    .file_name()
+                    .and_then(|f| f.to_str())
+                    .unwrap_or_default();
+                Comparison::from_sources(&data.test_code, &data.actual, file_name).to_text(theme)
+            })
+            .clone()
+    }
+}
+
+/// `text` without borrowing the outputs it was made from.
+fn owned(text: Text<'_>) -> Text<'static> {
+    let lines = text.lines.into_iter().map(|line| Line {
+        spans: line
+            .spans
+            .into_iter()
+            .map(|span| Span::styled(span.content.into_owned(), span.style))
+            .collect(),
+        style: line.style,
+        alignment: line.alignment,
+    });
+    Text {
+        lines: lines.collect(),
+        style: text.style,
+        alignment: text.alignment,
+    }
+}
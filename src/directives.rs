@@ -0,0 +1,223 @@
+use ratatui::{
+    style::Stylize,
+    text::{Line, Text},
+};
+
+/// A `//@ name: value` header directive of a test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive<'a> {
+    /// 1-based.
+    pub line: usize,
+    /// From `//@[revision] name`.
+    pub revision: Option<&'a str>,
+    pub name: &'a str,
+    pub value: Option<&'a str>,
+}
+
+pub fn parse(code: &str) -> Vec<Directive<'_>> {
+    code.lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let rest = line.trim_start().strip_prefix("//@")?;
+            let (revision, rest) = match rest.strip_prefix('[') {
+                Some(rest) => {
+                    let (revision, rest) = rest.split_once(']')?;
+                    (Some(revision.trim()), rest)
+                }
+                None => (None, rest),
+            };
+            let rest = rest.trim();
+            // Some directives take a value without a colon, like `//@ normalize-stderr-test "a" -> "b"`.
+            let (name, value) = match rest.split_once(|c: char| c == ':' || c.is_whitespace()) {
+                Some((name, value)) => (name, Some(value.trim_start_matches(':').trim())),
+                None => (rest, None),
+            };
+            Some(Directive {
+                line: i + 1,
+                revision,
+                name,
+                value,
+            })
+        })
+        .collect()
+}
+
+/// What the directives that matter when deciding whether to bless do.
+fn explanation(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "check-pass" | "build-pass" | "run-pass" => "expected to succeed",
+        "check-fail" | "build-fail" | "run-fail" => "expected to fail",
+        "edition" => "compiled with this edition",
+        "revisions" => "compiled once per revision, each with its own output",
+        "compile-flags" => "extra flags to rustc",
+        "aux-build" | "aux-crate" => "auxiliary crate built first",
+        "ignore-test" => "never run",
+        "known-bug" => "the output documents a bug, it's fine that it's wrong",
+        "dont-check-compiler-stderr" | "dont-check-compiler-stdout" => "output isn't compared",
+        "run-rustfix" => "the suggestions are applied and compared with the .fixed file",
+        name if name.starts_with("normalize-") => "the output is rewritten before comparing",
+        name if name.starts_with("ignore-") || name.starts_with("only-") => {
+            "only runs on some targets"
+        }
+        name if name.starts_with("needs-") => "needs some support to run",
+        _ => return None,
+    })
+}
+
+pub fn to_text(directives: &[Directive<'_>]) -> Text<'static> {
+    if directives.is_empty() {
+        return Text::from("No //@ directives");
+    }
+    directives
+        .iter()
+        .map(|directive| {
+            let mut line = Line::from(format!("{:>4}: ", directive.line).dim());
+            if let Some(revision) = directive.revision {
+                line.push_span(format!("[{revision}] ").magenta());
+            }
+            line.push_span(directive.name.to_owned().bold());
+            if let Some(value) = directive.value {
+                line.push_span(format!(": {value}"));
+            }
+            if let Some(explanation) = explanation(directive.name) {
+                line.push_span(format!("  ({explanation})").dim());
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// The files of the auxiliary crates the test builds, relative to the `auxiliary` directory next
+/// to it.
+pub fn aux_files<'a>(directives: &[Directive<'a>]) -> Vec<&'a str> {
+    directives
+        .iter()
+        .filter(|directive| {
+            matches!(
+                directive.name,
+                "aux-build" | "aux-crate" | "aux-bin" | "aux-codegen-backend" | "proc-macro"
+            )
+        })
+        .filter_map(|directive| {
+            let value = directive.value?;
+            // `aux-crate: name=file.rs`, possibly with options like `priv:name=file.rs`.
+            let file = value.rsplit_once('=').map_or(value, |(_, file)| file);
+            Some(file.trim())
+        })
+        .filter(|file| !file.is_empty())
+        .collect()
+}
+
+/// The editions the test is compiled with: the one it sets, or 2015 like compiletest, unless each
+/// of its revisions sets another one.
+pub fn editions<'a>(directives: &[Directive<'a>]) -> Vec<&'a str> {
+    let edition = |revision| {
+        directives
+            .iter()
+            .find(|d| d.revision == revision && d.name == "edition")
+            .and_then(|d| d.value)
+    };
+    let default = edition(None).unwrap_or("2015");
+    let revisions = directives
+        .iter()
+        .find(|d| d.revision.is_none() && d.name == "revisions")
+        .and_then(|d| d.value)
+        .unwrap_or_default();
+    let mut editions = vec![];
+    for revision in revisions.split_whitespace() {
+        let edition = edition(Some(revision)).unwrap_or(default);
+        if !editions.contains(&edition) {
+            editions.push(edition);
+        }
+    }
+    if editions.is_empty() {
+        editions.push(default);
+    }
+    editions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = r#"//@ revisions: current next
+//@[next] compile-flags: -Znext-solver
+//@ check-pass
+//@ normalize-stderr-test "\d+ bits" -> "N bits"
+//   @ not a directive
+
+fn main() {}
+"#;
+
+    #[test]
+    fn parses_the_directives() {
+        let directive = |line, revision, name, value| Directive {
+            line,
+            revision,
+            name,
+            value,
+        };
+        assert_eq!(
+            parse(CODE),
+            [
+                directive(1, None, "revisions", Some("current next")),
+                directive(2, Some("next"), "compile-flags", Some("-Znext-solver")),
+                directive(3, None, "check-pass", None),
+                directive(
+                    4,
+                    None,
+                    "normalize-stderr-test",
+                    Some(r#""\d+ bits" -> "N bits""#)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn explains_what_matters() {
+        assert_eq!(explanation("check-pass"), Some("expected to succeed"));
+        assert_eq!(
+            explanation("normalize-stdout-test"),
+            Some("the output is rewritten before comparing")
+        );
+        assert_eq!(
+            explanation("ignore-windows"),
+            Some("only runs on some targets")
+        );
+        assert_eq!(explanation("dont-require-annotations"), None);
+    }
+
+    #[test]
+    fn finds_the_auxiliary_crates() {
+        let code = "\
+//@ aux-build: two_macros.rs
+//@ aux-crate: priv:priv_dep=priv_dep.rs
+//@ proc-macro: test-macros.rs
+//@ aux-build:
+//@ edition: 2021
+";
+        assert_eq!(
+            aux_files(&parse(code)),
+            ["two_macros.rs", "priv_dep.rs", "test-macros.rs"]
+        );
+    }
+
+    #[test]
+    fn finds_the_editions() {
+        assert_eq!(editions(&parse("fn main() {}")), ["2015"]);
+        assert_eq!(editions(&parse("//@ edition: 2021")), ["2021"]);
+        let code = "\
+//@ revisions: e2015 e2018 e2024 other
+//@[e2018] edition: 2018
+//@[e2024] edition: 2024
+";
+        assert_eq!(editions(&parse(code)), ["2015", "2018", "2024"]);
+        let code = "\
+//@ revisions: current next
+//@ edition: 2021
+//@[next] compile-flags: -Znext-solver
+";
+        assert_eq!(editions(&parse(code)), ["2021"]);
+    }
+}
@@ -0,0 +1,216 @@
+use std::path::PathBuf;
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    layout::Rect,
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, List, ListState},
+    Frame,
+};
+
+use crate::{
+    diff::{DiffLine, LineTag},
+    theme::Theme,
+};
+
+/// A line of the diff, in the expected output, the actual one or both.
+#[derive(Debug, Clone)]
+struct Row {
+    text: String,
+    in_expected: bool,
+    in_actual: bool,
+}
+
+/// Line editing of the expected output on top of the diff, for small fixes without leaving the
+/// TUI.
+#[derive(Debug, Clone)]
+pub struct Editor {
+    pub path: PathBuf,
+    rows: Vec<Row>,
+    cursor: usize,
+    /// Typing into the line under the cursor, at this byte offset.
+    insert_at: Option<usize>,
+    pub dirty: bool,
+}
+
+pub enum Outcome {
+    Continue,
+    Save,
+    Quit,
+}
+
+impl Editor {
+    pub fn new(path: PathBuf, diff: &[DiffLine<'_>]) -> Self {
+        let rows = diff
+            .iter()
+            .map(|line| Row {
+                text: line.value.trim_end_matches('\n').to_owned(),
+                in_expected: matches!(
+                    line.tag,
+                    LineTag::Equal | LineTag::Delete | LineTag::MovedFrom
+                ),
+                in_actual: matches!(
+                    line.tag,
+                    LineTag::Equal | LineTag::Insert | LineTag::MovedTo
+                ),
+            })
+            .collect();
+        Editor {
+            path,
+            rows,
+            cursor: 0,
+            insert_at: None,
+            dirty: false,
+        }
+    }
+
+    /// The edited expected output.
+    pub fn expected(&self) -> String {
+        self.rows
+            .iter()
+            .filter(|row| row.in_expected)
+            .map(|row| format!("{}\n", row.text))
+            .collect()
+    }
+
+    pub fn on_key(&mut self, key: KeyEvent) -> Outcome {
+        if let Some(at) = self.insert_at {
+            let text = &mut self.rows[self.cursor].text;
+            match key.code {
+                KeyCode::Esc | KeyCode::Enter => self.insert_at = None,
+                KeyCode::Char(c) => {
+                    text.insert(at, c);
+                    self.insert_at = Some(at + c.len_utf8());
+                }
+                KeyCode::Backspace => {
+                    if let Some((i, _)) = text[..at].char_indices().next_back() {
+                        text.remove(i);
+                        self.insert_at = Some(i);
+                    }
+                }
+                _ => {}
+            }
+            return Outcome::Continue;
+        }
+
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.cursor = (self.cursor + 1).min(self.rows.len().saturating_sub(1));
+            }
+            KeyCode::Char('k') | KeyCode::Up => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Char('a') => self.accept(),
+            KeyCode::Char('d') => self.delete(),
+            KeyCode::Char('o') => self.open_line(),
+            KeyCode::Char('i') => self.edit_line(),
+            KeyCode::Char('w') => return Outcome::Save,
+            KeyCode::Esc | KeyCode::Char('q') => return Outcome::Quit,
+            _ => {}
+        }
+        Outcome::Continue
+    }
+
+    /// Makes the expected output agree with the actual one on the line under the cursor.
+    fn accept(&mut self) {
+        let Some(row) = self.rows.get_mut(self.cursor) else {
+            return;
+        };
+        if row.in_expected != row.in_actual {
+            row.in_expected = row.in_actual;
+            if !row.in_actual {
+                self.rows.remove(self.cursor);
+                self.cursor = self.cursor.min(self.rows.len().saturating_sub(1));
+            }
+            self.dirty = true;
+        }
+    }
+
+    /// Removes the line under the cursor from the expected output.
+    fn delete(&mut self) {
+        let Some(row) = self.rows.get_mut(self.cursor) else {
+            return;
+        };
+        if !row.in_expected {
+            return;
+        }
+        row.in_expected = false;
+        if !row.in_actual {
+            self.rows.remove(self.cursor);
+            self.cursor = self.cursor.min(self.rows.len().saturating_sub(1));
+        }
+        self.dirty = true;
+    }
+
+    /// Adds an empty line to the expected output below the cursor and starts typing into it.
+    fn open_line(&mut self) {
+        let at = (self.cursor + 1).min(self.rows.len());
+        self.rows.insert(
+            at,
+            Row {
+                text: String::new(),
+                in_expected: true,
+                in_actual: false,
+            },
+        );
+        self.cursor = at;
+        self.insert_at = Some(0);
+        self.dirty = true;
+    }
+
+    /// Starts typing at the end of the expected line under the cursor.
+    fn edit_line(&mut self) {
+        let Some(row) = self.rows.get(self.cursor) else {
+            return;
+        };
+        if !row.in_expected {
+            return;
+        }
+        // The actual line stays as it is, the edit only goes to the expected side.
+        if row.in_actual {
+            let copy = Row {
+                in_actual: false,
+                ..row.clone()
+            };
+            self.rows[self.cursor].in_expected = false;
+            self.rows.insert(self.cursor, copy);
+        }
+        self.insert_at = Some(self.rows[self.cursor].text.len());
+        self.dirty = true;
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect, theme: &Theme) {
+        let items = self.rows.iter().enumerate().map(|(i, row)| {
+            let (marker, style) = match (row.in_expected, row.in_actual) {
+                (true, true) => ("  ", Style::new()),
+                (true, false) => ("- ", theme.delete.into()),
+                (false, _) => ("+ ", theme.insert.into()),
+            };
+            let mut spans = vec![Span::styled(marker, style)];
+            match self.insert_at.filter(|_| i == self.cursor) {
+                Some(at) => spans.extend([
+                    Span::styled(row.text[..at].to_owned(), style),
+                    Span::raw("▏").slow_blink(),
+                    Span::styled(row.text[at..].to_owned(), style),
+                ]),
+                None => spans.push(Span::styled(row.text.as_str(), style)),
+            }
+            Line::from(spans)
+        });
+
+        let dirty = if self.dirty { " [modified]" } else { "" };
+        let title = Line::from(format!(
+            " editing {}{dirty} (a: accept actual, d: delete, o: new line, i: edit, w: save, q: close) ",
+            self.path.display()
+        ))
+        .bold();
+        let mut state = ListState::default().with_selected(Some(self.cursor));
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title_top(title))
+                .highlight_style(Modifier::REVERSED),
+            area,
+            &mut state,
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! The events of a test run, from the JSON output of libtest or of cargo-nextest.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+/// An output of a test, each compared with its own expected file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    #[default]
+    Stderr,
+    Stdout,
+}
+
+impl Stream {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Stream::Stderr => "stderr",
+            Stream::Stdout => "stdout",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum Item<'a> {
+    #[serde(rename = "test")]
+    Test {
+        name: &'a str,
+        event: &'a str,
+        /// The ignore reason, newer libtest versions call it `reason`.
+        #[serde(alias = "reason")]
+        message: Option<String>,
+        /// Seconds, only present with `--report-time`.
+        exec_time: Option<f64>,
+        /// What the harness printed for a failed test.
+        stdout: Option<String>,
+    },
+    #[serde(rename = "suite")]
+    Suite {
+        failed: u32,
+        passed: u32,
+        ignored: u32,
+        /// Seconds, of the whole run.
+        exec_time: Option<f64>,
+    },
+}
+
+/// The totals of the run, over all the tests.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Stats {
+    pub failed: u32,
+    pub passed: u32,
+    pub ignored: u32,
+    /// How long the run took, in seconds.
+    #[serde(skip)]
+    pub exec_time: Option<f64>,
+}
+
+/// What we care about from a test run.
+#[derive(Debug, Clone, Default)]
+pub struct Events<'a> {
+    /// Paths of the failed UI tests.
+    pub failed: Vec<&'a str>,
+    /// Paths of the UI tests that passed.
+    pub passed: Vec<&'a str>,
+    /// Names of the failed tests that aren't UI tests, these can't be reviewed here.
+    pub other_failed: Vec<&'a str>,
+    pub ignored: Vec<Ignored<'a>>,
+    /// Seconds each failed UI test took, if reported.
+    pub exec_times: HashMap<&'a str, f64>,
+    /// Failed UI tests that didn't fail because of an output mismatch.
+    pub failure_kinds: HashMap<&'a str, FailureKind>,
+    pub stats: Stats,
+}
+
+/// Why a UI test failed, when it's not something that blessing can fix.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum FailureKind {
+    /// Ran over the time limit, or was reported as running for too long.
+    Timeout,
+    /// compiletest itself panicked instead of reporting a mismatch.
+    Panic { message: Option<String> },
+}
+
+impl FailureKind {
+    /// Classifies a failure from its ignore/failure reason and the harness output.
+    fn new(timed_out: bool, reason: Option<&str>, stdout: Option<&str>) -> Option<Self> {
+        if timed_out || reason == Some("time limit exceeded") {
+            return Some(FailureKind::Timeout);
+        }
+        let stdout = stdout?;
+        if stdout.contains("differed from the expected") {
+            return None;
+        }
+        let mut lines = stdout.lines();
+        lines.find(|line| line.starts_with("thread '") && line.contains("panicked at"))?;
+        Some(FailureKind::Panic {
+            message: lines.next().map(str::to_owned),
+        })
+    }
+}
+
+/// A test that didn't run, and why if libtest says.
+#[derive(Debug, Clone)]
+pub struct Ignored<'a> {
+    pub name: &'a str,
+    pub reason: Option<String>,
+}
+
+/// Reads the JSON output of libtest, as printed by `./x test -- -Zunstable-options
+/// --format=json`. Lines that aren't events, like the output of the build, are skipped.
+///
+/// The same format printed by `cargo nextest run --message-format libtest-json` (with
+/// `NEXTEST_EXPERIMENTAL_LIBTEST_JSON=1`) works too: its test names are prefixed with the test
+/// binary, like `tests::ui$[ui] tests/ui/foo.rs`, and there's a suite per binary.
+pub fn parse_events(events: &str) -> Events<'_> {
+    let lines = events
+        .lines()
+        .filter_map(|line| match serde_json::from_str::<Item>(line) {
+            Ok(item) => Some(item),
+            Err(e) => {
+                // Also the output of the build, which gets mixed in.
+                tracing::trace!(line, error = %e, "not an event");
+                None
+            }
+        })
+        .skip(1);
+
+    let mut failed = vec![];
+    let mut passed = vec![];
+    let mut other_failed = vec![];
+    let mut ignored = vec![];
+    let mut exec_times = HashMap::new();
+    let mut failure_kinds = HashMap::new();
+    let mut timed_out = HashSet::new();
+    let mut stats = None;
+    let mut ok_count = 0;
+
+    for event in lines {
+        match event {
+            Item::Test {
+                name,
+                event,
+                message,
+                exec_time,
+                stdout,
+            } => {
+                if event == "timeout" {
+                    // Only a warning that it's running long, the result comes later.
+                    timed_out.insert(name);
+                    continue;
+                }
+                if event == "ignored" {
+                    ignored.push(Ignored {
+                        name,
+                        reason: message,
+                    });
+                    continue;
+                }
+                if event != "failed" {
+                    ok_count += 1;
+                    if let ("ok", Some((_, path))) = (event, name.split_once("[ui] ")) {
+                        passed.push(path);
+                    }
+                    continue;
+                }
+                let Some((_, path)) = name.split_once("[ui] ") else {
+                    other_failed.push(name);
+                    continue;
+                };
+                if let Some(exec_time) = exec_time {
+                    exec_times.insert(path, exec_time);
+                }
+                if let Some(kind) = FailureKind::new(
+                    timed_out.contains(name),
+                    message.as_deref(),
+                    stdout.as_deref(),
+                ) {
+                    failure_kinds.insert(path, kind);
+                }
+                failed.push(path);
+            }
+            Item::Suite {
+                failed,
+                passed,
+                ignored,
+                exec_time,
+            } => {
+                // nextest reports a suite per test binary, run in parallel.
+                let stats = stats.get_or_insert_with(Stats::default);
+                stats.failed += failed;
+                stats.passed += passed;
+                stats.ignored += ignored;
+                stats.exec_time = stats
+                    .exec_time
+                    .into_iter()
+                    .chain(exec_time)
+                    .reduce(f64::max);
+            }
+        }
+    }
+
+    let stats = stats.unwrap_or(Stats {
+        failed: (failed.len() + other_failed.len()) as u32,
+        passed: ok_count,
+        ignored: ignored.len() as u32,
+        exec_time: None,
+    });
+
+    tracing::info!(
+        failed = failed.len(),
+        other_failed = other_failed.len(),
+        ignored = ignored.len(),
+        ?failure_kinds,
+        "parsed the events"
+    );
+    Events {
+        failed,
+        passed,
+        other_failed,
+        ignored,
+        exec_times,
+        failure_kinds,
+        stats,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `cargo nextest run --message-format libtest-json` over two test binaries.
+    const NEXTEST: &str = r#"{"type":"suite","event":"started","test_count":2,"nextest":{"crate":"clippy","test_binary":"compile-test","kind":"test"}}
+{"type":"test","event":"started","name":"clippy::compile-test$[ui] tests/ui/needless_return.rs"}
+{"type":"test","event":"started","name":"clippy::compile-test$[ui] tests/ui/redundant_clone.rs"}
+{"type":"test","name":"clippy::compile-test$[ui] tests/ui/needless_return.rs","event":"ok","exec_time":0.81}
+{"type":"test","name":"clippy::compile-test$[ui] tests/ui/redundant_clone.rs","event":"failed","exec_time":1.25,"stdout":"actual output differed from expected"}
+{"type":"suite","event":"failed","passed":1,"failed":1,"ignored":0,"measured":0,"filtered_out":0,"exec_time":2.5,"nextest":{"crate":"clippy","test_binary":"compile-test","kind":"test"}}
+{"type":"suite","event":"started","test_count":2,"nextest":{"crate":"clippy","test_binary":"dogfood","kind":"test"}}
+{"type":"test","event":"started","name":"clippy::dogfood$dogfood"}
+{"type":"test","name":"clippy::dogfood$dogfood","event":"failed","exec_time":3.0}
+{"type":"test","name":"clippy::dogfood$run_metadata","event":"ignored"}
+{"type":"suite","event":"failed","passed":0,"failed":1,"ignored":1,"measured":0,"filtered_out":0,"exec_time":3.5,"nextest":{"crate":"clippy","test_binary":"dogfood","kind":"test"}}
+"#;
+
+    #[test]
+    fn sums_the_suites_of_nextest() {
+        let events = parse_events(NEXTEST);
+        assert_eq!(events.failed, ["tests/ui/redundant_clone.rs"]);
+        assert_eq!(events.passed, ["tests/ui/needless_return.rs"]);
+        assert_eq!(events.other_failed, ["clippy::dogfood$dogfood"]);
+        assert_eq!(events.exec_times["tests/ui/redundant_clone.rs"], 1.25);
+
+        let Stats {
+            failed,
+            passed,
+            ignored,
+            exec_time,
+        } = events.stats;
+        assert_eq!((failed, passed, ignored), (2, 1, 1));
+        // The binaries run in parallel.
+        assert_eq!(exec_time, Some(3.5));
+    }
+
+    #[test]
+    fn reads_a_single_suite_of_libtest() {
+        let json = r#"{ "type": "suite", "event": "started", "test_count": 3 }
+{ "type": "test", "event": "started", "name": "[ui] tests/ui/foo.rs" }
+{ "type": "test", "name": "[ui] tests/ui/foo.rs", "event": "failed", "stdout": "" }
+{ "type": "test", "name": "[ui] tests/ui/bar.rs", "event": "ok" }
+{ "type": "test", "name": "[ui] tests/ui/baz.rs", "event": "ignored", "message": "only-windows" }
+{ "type": "suite", "event": "failed", "passed": 1, "failed": 1, "ignored": 1, "measured": 0, "filtered_out": 0, "exec_time": 12.5 }
+"#;
+        let events = parse_events(json);
+        assert_eq!(events.failed, ["tests/ui/foo.rs"]);
+        assert_eq!(events.ignored[0].reason.as_deref(), Some("only-windows"));
+        let Stats {
+            failed,
+            passed,
+            ignored,
+            exec_time,
+        } = events.stats;
+        assert_eq!((failed, passed, ignored, exec_time), (1, 1, 1, Some(12.5)));
+    }
+}
@@ -0,0 +1,77 @@
+use std::{fs, path::PathBuf, process::Command};
+
+use ansi_to_tui::IntoText;
+use color_eyre::{eyre::eyre, Result};
+use ratatui::text::Text;
+
+use crate::Stream;
+
+/// Output of the external diff tool for one stream of one test, kept until the test changes.
+#[derive(Debug, Clone)]
+pub struct ExternalDiff {
+    pub test: usize,
+    pub stream: Stream,
+    pub text: Text<'static>,
+}
+
+/// Runs the user's diff `tool` on `expected` and `actual` and returns its output with the colors
+/// it printed.
+///
+/// The tool is a shell command where `{expected}` and `{actual}` are replaced with the paths of
+/// the two files, which are appended to it if neither is used, like `difft --color=always`.
+pub fn run(tool: &str, file_name: &str, expected: &str, actual: &str) -> Result<Text<'static>> {
+    let output = command(tool, file_name, expected, actual)?.output()?;
+    // Diff tools tend to exit with 1 when the files differ, only complain if there's no output.
+    if output.stdout.is_empty() && !output.status.success() {
+        return Err(eyre!(
+            "{tool} exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(output.stdout.into_text()?)
+}
+
+/// Runs the user's merge `tool` on a copy of `expected` and on `actual`, in the terminal, with the
+/// same placeholders as [`run`]. Returns the copy if it was edited.
+pub fn merge(tool: &str, file_name: &str, expected: &str, actual: &str) -> Result<Option<String>> {
+    let mut command = command(tool, file_name, expected, actual)?;
+    let status = command.status()?;
+    if !status.success() {
+        return Err(eyre!("{tool} exited with {status}"));
+    }
+    let merged = fs::read_to_string(&temp_paths(file_name).0)?;
+    Ok((merged != expected).then_some(merged))
+}
+
+fn temp_paths(file_name: &str) -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir().join("compiletest-differ");
+    (
+        dir.join("expected").join(file_name),
+        dir.join("actual").join(file_name),
+    )
+}
+
+/// Writes `expected` and `actual` to temporary files and prepares `tool` to run on them.
+fn command(tool: &str, file_name: &str, expected: &str, actual: &str) -> Result<Command> {
+    let (expected_path, actual_path) = temp_paths(file_name);
+    for (path, text) in [(&expected_path, expected), (&actual_path, actual)] {
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, text)?;
+    }
+
+    // Passed through the environment so that the paths don't need quoting.
+    let script = if tool.contains("{expected}") || tool.contains("{actual}") {
+        tool.replace("{expected}", r#""$EXPECTED""#)
+            .replace("{actual}", r#""$ACTUAL""#)
+    } else {
+        format!(r#"{tool} "$EXPECTED" "$ACTUAL""#)
+    };
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(script)
+        .env("EXPECTED", &expected_path)
+        .env("ACTUAL", &actual_path);
+    Ok(command)
+}
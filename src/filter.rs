@@ -0,0 +1,37 @@
+use color_eyre::Result;
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+
+/// A pattern selecting test paths like `tests/ui/async-await/foo.rs`.
+#[derive(Debug, Clone)]
+pub enum PathFilter {
+    /// Patterns without glob metacharacters match anywhere in the path, so that
+    /// `tests/ui/async-await` selects the whole directory.
+    Substring(String),
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl PathFilter {
+    pub fn new(pattern: &str, regex: bool) -> Result<Self> {
+        Ok(if regex {
+            Self::Regex(Regex::new(pattern)?)
+        } else if pattern.contains(['*', '?', '[', '{']) {
+            Self::Glob(Glob::new(pattern)?.compile_matcher())
+        } else {
+            Self::Substring(pattern.to_owned())
+        })
+    }
+
+    pub fn parse_all(patterns: &[String], regex: bool) -> Result<Vec<Self>> {
+        patterns.iter().map(|p| Self::new(p, regex)).collect()
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        match self {
+            Self::Substring(s) => path.contains(s.as_str()),
+            Self::Glob(glob) => glob.is_match(path),
+            Self::Regex(regex) => regex.is_match(path),
+        }
+    }
+}
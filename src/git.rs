@@ -0,0 +1,76 @@
+use std::{ffi::OsStr, io, path::Path};
+
+use crate::remote;
+
+/// Runs git in `repo` and returns what it printed, or what it complained about if it failed.
+///
+/// The output is captured so that nothing is printed over the TUI.
+fn run<S: AsRef<OsStr>>(repo: &Path, args: impl IntoIterator<Item = S>) -> io::Result<String> {
+    let mut command = remote::command(repo, "git", args);
+    let output = command.output()?;
+    if output.status.success() {
+        tracing::debug!(?command, "ran git");
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    } else {
+        let error = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        tracing::debug!(?command, error, "git failed");
+        Err(io::Error::other(error))
+    }
+}
+
+/// Like [`run`], for queries where failing just means there's no answer.
+fn git(repo: &Path, args: &[&str]) -> Option<String> {
+    run(repo, args).ok()
+}
+
+/// The checked out branch and commit of `repo`, like `master@1a2b3c4d`.
+pub fn head(repo: &Path) -> Option<String> {
+    let hash = git(repo, &["rev-parse", "--short", "HEAD"])?;
+    // `HEAD` when detached.
+    let branch = git(repo, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+    Some(format!("{branch}@{hash}"))
+}
+
+/// Whether `path` has changes that aren't committed, staged or not.
+pub fn is_modified(repo: &Path, path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    git(
+        repo,
+        &["status", "--porcelain", "--untracked-files=no", "--", &path],
+    )
+    .is_some_and(|status| !status.is_empty())
+}
+
+/// Stages `paths`.
+pub fn add(repo: &Path, paths: &[&Path]) -> io::Result<()> {
+    let args = ["add", "--"].map(OsStr::new);
+    run(
+        repo,
+        args.into_iter().chain(paths.iter().map(|p| p.as_os_str())),
+    )
+    .map_err(|e| io::Error::other(format!("git add failed: {e}")))?;
+    Ok(())
+}
+
+/// Commits `paths`, and only them, with `message`. Returns the hash of the commit.
+pub fn commit(repo: &Path, paths: &[&Path], message: &str) -> io::Result<String> {
+    // New expected files need to be known to git for `--only`.
+    add(repo, paths)?;
+    let args = ["commit", "--quiet", "--only", "-m", message, "--"].map(OsStr::new);
+    run(
+        repo,
+        args.into_iter().chain(paths.iter().map(|p| p.as_os_str())),
+    )
+    .map_err(|e| io::Error::other(format!("git commit failed: {e}")))?;
+    run(repo, ["rev-parse", "--short", "HEAD"])
+}
+
+/// The last commit that touched `path`, like `1a2b3c4d (Jane Doe, 3 weeks ago) Fix the thing`.
+pub fn last_commit(repo: &Path, path: &Path) -> Option<String> {
+    let path = path.to_string_lossy();
+    git(
+        repo,
+        &["log", "-1", "--format=%h (%an, %ar) %s", "--", &path],
+    )
+    .filter(|commit| !commit.is_empty())
+}
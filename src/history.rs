@@ -0,0 +1,188 @@
+//! `history` in the config: the results of every run that's reviewed, in an SQLite database, to
+//! tell when a test started failing.
+
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use color_eyre::{eyre::eyre, Result};
+use rusqlite::{params, Connection};
+
+use crate::{outputs::CachedStreams, remote, session::fnv1a};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        input TEXT NOT NULL,
+        -- When the JSON output was written, in seconds since the epoch.
+        finished INTEGER NOT NULL,
+        git_head TEXT,
+        UNIQUE (input, finished)
+    );
+    CREATE TABLE IF NOT EXISTS results (
+        run INTEGER NOT NULL REFERENCES runs (id),
+        test TEXT NOT NULL,
+        status TEXT NOT NULL,
+        diff_hash TEXT,
+        PRIMARY KEY (run, test)
+    );
+    CREATE INDEX IF NOT EXISTS results_by_test ON results (test);
+";
+
+/// How a test did in a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// With a hash of its expected and actual outputs, to tell when the diff changed.
+    Failed(Option<u64>),
+}
+
+#[derive(Debug)]
+pub struct History {
+    db: Connection,
+}
+
+impl History {
+    pub fn path() -> Option<PathBuf> {
+        Some(
+            dirs::data_dir()?
+                .join("compiletest-differ")
+                .join("history.sqlite"),
+        )
+    }
+
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let db = Connection::open(path)
+            .map_err(|e| eyre!("Can't open the history at {}: {e}", path.display()))?;
+        db.execute_batch(SCHEMA)?;
+        Ok(Self { db })
+    }
+
+    /// Records the `results` of the run whose JSON output is `input`, unless that output was
+    /// already recorded. Returns whether it was new.
+    pub fn record<'a>(
+        &mut self,
+        input: &Path,
+        git_head: Option<&str>,
+        results: impl FnOnce() -> Vec<(&'a str, Outcome)>,
+    ) -> Result<bool> {
+        let finished = remote::modified(input)?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs() as i64);
+        let input = input.canonicalize().unwrap_or_else(|_| input.to_owned());
+        let transaction = self.db.transaction()?;
+        let inserted = transaction.execute(
+            "INSERT OR IGNORE INTO runs (input, finished, git_head) VALUES (?1, ?2, ?3)",
+            params![input.to_string_lossy(), finished, git_head],
+        )?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+        let run = transaction.last_insert_rowid();
+        let results = results();
+        {
+            let mut insert = transaction.prepare(
+                "INSERT OR REPLACE INTO results (run, test, status, diff_hash) \
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (test, outcome) in &results {
+                let (status, hash) = match outcome {
+                    Outcome::Passed => ("passed", None),
+                    Outcome::Failed(hash) => ("failed", hash.map(|h| format!("{h:016x}"))),
+                };
+                insert.execute(params![run, test, status, hash])?;
+            }
+        }
+        transaction.commit()?;
+        tracing::info!(run, results = results.len(), "recorded the run");
+        Ok(true)
+    }
+
+    /// The runs that had `test`, oldest first, with since when it's been failing.
+    pub fn describe(&self, test: &str) -> Result<String> {
+        let mut query = self.db.prepare(
+            "SELECT datetime(runs.finished, 'unixepoch', 'localtime'), runs.git_head, \
+                    results.status, results.diff_hash \
+             FROM results JOIN runs ON runs.id = results.run \
+             WHERE results.test = ?1 ORDER BY runs.finished",
+        )?;
+        let rows = query
+            .query_map([test], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        if rows.is_empty() {
+            let runs: i64 = self
+                .db
+                .query_row("SELECT count(*) FROM runs", [], |row| row.get(0))?;
+            return Ok(format!("{test} isn't in any of the {runs} recorded runs"));
+        }
+
+        let failing = rows
+            .iter()
+            .rev()
+            .take_while(|(_, _, status, _)| status == "failed")
+            .count();
+        let mut out = match failing {
+            0 => format!("{test} passed in the latest of {} runs", rows.len()),
+            n => {
+                let (since, head, ..) = &rows[rows.len() - n];
+                let head = head
+                    .as_deref()
+                    .map(|h| format!(" ({h})"))
+                    .unwrap_or_default();
+                format!(
+                    "{test} has failed in the last {n} of {} runs, since {since}{head}",
+                    rows.len()
+                )
+            }
+        };
+        out.push_str("\n\n");
+        // Of the previous failure.
+        let mut previous_hash = None;
+        for (time, head, status, hash) in &rows {
+            let mut changed = "";
+            if status == "failed" {
+                if previous_hash.is_some_and(|previous| previous != hash) {
+                    changed = "  (the diff changed)";
+                }
+                previous_hash = Some(hash);
+            }
+            let line = format!(
+                "{time}  {:24}  {status:6}  {}{changed}",
+                head.as_deref().unwrap_or("-"),
+                hash.as_deref().map_or("", |h| &h[..8]),
+            );
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+        Ok(out)
+    }
+}
+
+/// A hash of the expected and actual outputs of `streams`, if there are any.
+pub fn diff_hash(streams: &CachedStreams) -> Option<u64> {
+    let present = streams.present();
+    if present.is_empty() {
+        return None;
+    }
+    let mut bytes = vec![];
+    for data in present {
+        for text in [data.stream.name(), &data.expect, &data.actual] {
+            bytes.extend_from_slice(text.as_bytes());
+            bytes.push(0);
+        }
+    }
+    Some(fnv1a(&bytes))
+}
@@ -0,0 +1,80 @@
+use std::{
+    ffi::OsStr,
+    io::{self, Write},
+    path::Path,
+    process::Stdio,
+    thread,
+};
+
+use crate::remote;
+
+/// Runs the `hook` command of the config for the expected output `file` of `test`, in the rust
+/// checkout. `{test}` and `{file}` are replaced with the paths, which are appended if there's no
+/// placeholder. The error has what the hook printed.
+fn run(
+    name: &str,
+    hook: &str,
+    rust_path: &Path,
+    test: &str,
+    file: &Path,
+    input: &str,
+) -> io::Result<()> {
+    let (test, file) = (
+        remote::quote(OsStr::new(test)),
+        remote::quote(file.as_os_str()),
+    );
+    let script = if hook.contains("{test}") || hook.contains("{file}") {
+        hook.replace("{test}", &test).replace("{file}", &file)
+    } else {
+        format!("{hook} {test} {file}")
+    };
+    // Captured, so that nothing is printed over the TUI.
+    let mut child = remote::command(rust_path, "sh", ["-c", &script])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // Written while the output is read, as a hook that prints a lot before reading all of its
+    // input would wait for us to read while we wait for it to read.
+    let output = thread::scope(|scope| {
+        scope.spawn(move || {
+            // A hook that doesn't read its input closes the pipe, that's fine.
+            let _ = stdin.write_all(input.as_bytes());
+        });
+        child.wait_with_output()
+    })?;
+    tracing::info!(name, script, status = %output.status, "ran a hook");
+    if output.status.success() {
+        return Ok(());
+    }
+    let mut error = format!("The {name} hook failed with {}", output.status);
+    for printed in [&output.stdout, &output.stderr] {
+        let printed = String::from_utf8_lossy(printed);
+        if !printed.trim().is_empty() {
+            error.push_str("\n\n");
+            error.push_str(printed.trim_end());
+        }
+    }
+    Err(io::Error::other(error))
+}
+
+/// Runs the `pre_bless` command of the config before `file` of `test` is overwritten with
+/// `new`, which the hook gets on stdin. If it fails, the bless shouldn't happen.
+pub fn pre_bless(
+    hook: &str,
+    rust_path: &Path,
+    test: &str,
+    file: &Path,
+    new: &str,
+) -> io::Result<()> {
+    run("pre-bless", hook, rust_path, test, file, new)
+}
+
+/// Runs the `post_bless` command of the config for each file written by blessing `test`.
+pub fn post_bless(hook: &str, rust_path: &Path, test: &str, files: &[&Path]) -> io::Result<()> {
+    for file in files {
+        run("post-bless", hook, rust_path, test, file, "")?;
+    }
+    Ok(())
+}
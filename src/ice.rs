@@ -0,0 +1,83 @@
+use ratatui::{
+    style::Stylize,
+    text::{Line, Text},
+};
+
+/// An internal compiler error found in a stderr.
+#[derive(Debug, Clone, Default)]
+pub struct Ice<'a> {
+    /// The `error: internal compiler error` and `thread 'rustc' panicked` lines.
+    pub message: Vec<&'a str>,
+    pub backtrace: Vec<&'a str>,
+    pub query_stack: Vec<&'a str>,
+    /// The notes at the end asking to file a bug, with the compiler version and flags.
+    pub notes: Vec<&'a str>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Other,
+    Message,
+    Backtrace,
+    QueryStack,
+}
+
+/// Returns the ICE in `stderr`, if there's one.
+pub fn detect(stderr: &str) -> Option<Ice<'_>> {
+    let is_ice_start = |line: &str| {
+        line.starts_with("error: internal compiler error")
+            || (line.starts_with("thread '") && line.contains("panicked at"))
+    };
+    if !stderr.lines().any(is_ice_start) {
+        return None;
+    }
+
+    let mut ice = Ice::default();
+    let mut section = Section::Other;
+    for line in stderr.lines() {
+        if is_ice_start(line) {
+            section = Section::Message;
+        } else if line.starts_with("stack backtrace:") {
+            section = Section::Backtrace;
+            continue;
+        } else if line.starts_with("query stack during panic:") {
+            section = Section::QueryStack;
+            continue;
+        } else if line.starts_with("end of query stack") {
+            section = Section::Other;
+            continue;
+        } else if line.starts_with("note: ") && section != Section::QueryStack {
+            section = Section::Other;
+        }
+
+        match section {
+            Section::Message => ice.message.push(line),
+            Section::Backtrace => ice.backtrace.push(line),
+            Section::QueryStack => ice.query_stack.push(line),
+            Section::Other if line.starts_with("note: ") => ice.notes.push(line),
+            Section::Other => {}
+        }
+    }
+    Some(ice)
+}
+
+impl<'a> Ice<'a> {
+    pub fn to_text(&self) -> Text<'a> {
+        let mut lines: Vec<Line<'a>> = vec![];
+        let mut section = |title: &'static str, body: &[&'a str]| {
+            if body.is_empty() {
+                return;
+            }
+            if !lines.is_empty() {
+                lines.push(Line::default());
+            }
+            lines.push(title.red().bold().into());
+            lines.extend(body.iter().map(|&line| Line::from(line)));
+        };
+        section("internal compiler error", &self.message);
+        section("query stack", &self.query_stack);
+        section("backtrace", &self.backtrace);
+        section("notes", &self.notes);
+        lines.into()
+    }
+}
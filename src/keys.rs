@@ -0,0 +1,465 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_width::UnicodeWidthStr;
+
+/// What a key does in the main view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Quit,
+    Bless,
+    MarkReviewed,
+    MarkSkipped,
+    MarkDeferred,
+    Explain,
+    Edit,
+    Merge,
+    EditExpected,
+    Copy,
+    UpdateAnnotations,
+    Repeat,
+    NextTest,
+    NextStream,
+    PreviousStream,
+    ShowStderr,
+    ShowStdout,
+    PreviousTest,
+    Reload,
+    Rerun,
+    GoTo,
+    FindTest,
+    Tree,
+    Back,
+    Forward,
+    SetBookmark,
+    GoToBookmark,
+    RotateSort,
+    JumpToLine,
+    NextHunk,
+    PreviousHunk,
+    Search,
+    ScrollDown,
+    ScrollUp,
+    HalfPageDown,
+    HalfPageUp,
+    PageDown,
+    PageUp,
+    Top,
+    Bottom,
+    CycleFocus,
+    NextCodeTab,
+    RotateDiffMode,
+    ToggleLayout,
+    ToggleCode,
+    ToggleWrap,
+    CutOff,
+    ToggleMetadata,
+    ToggleColorblind,
+    ToggleIce,
+    RustcArgs,
+    Oneline,
+    Annotations,
+    Info,
+    History,
+    OtherFailures,
+    Statistics,
+    ExternalDiff,
+    Colored,
+    Ignored,
+    PreviousMode,
+    ToggleHelpBar,
+    Help,
+}
+
+impl Command {
+    /// Whether `.` repeats it, which is for the actions on a test rather than moving around.
+    pub const fn is_repeatable(self) -> bool {
+        use Command::*;
+        matches!(
+            self,
+            Bless
+                | MarkReviewed
+                | MarkSkipped
+                | MarkDeferred
+                | Edit
+                | Merge
+                | EditExpected
+                | UpdateAnnotations
+                | Rerun
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Review,
+    Tests,
+    Diff,
+    Scrolling,
+    Views,
+}
+
+impl Category {
+    const ALL: [Category; 5] = [
+        Category::Review,
+        Category::Tests,
+        Category::Diff,
+        Category::Scrolling,
+        Category::Views,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Category::Review => "Review",
+            Category::Tests => "Moving between tests",
+            Category::Diff => "Moving in the diff",
+            Category::Scrolling => "Scrolling the focused pane",
+            Category::Views => "Views",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    ctrl: bool,
+}
+
+const fn key(c: char) -> Key {
+    special(KeyCode::Char(c))
+}
+
+const fn ctrl(c: char) -> Key {
+    Key {
+        code: KeyCode::Char(c),
+        ctrl: true,
+    }
+}
+
+const fn special(code: KeyCode) -> Key {
+    Key { code, ctrl: false }
+}
+
+pub struct Binding {
+    pub keys: &'static [Key],
+    /// How the keys are shown to the user.
+    pub label: &'static str,
+    pub description: &'static str,
+    pub category: Category,
+    pub command: Command,
+}
+
+const fn bind(
+    keys: &'static [Key],
+    label: &'static str,
+    description: &'static str,
+    category: Category,
+    command: Command,
+) -> Binding {
+    Binding {
+        keys,
+        label,
+        description,
+        category,
+        command,
+    }
+}
+
+/// Every key of the main view. Keys that take a second key (`gg`, `ma`) only list the first one.
+pub const BINDINGS: &[Binding] = {
+    use Category::*;
+    use Command::*;
+    &[
+        bind(&[key('b')], "b", "bless the current stream", Review, Bless),
+        bind(
+            &[key('M')],
+            "M",
+            "merge expected and actual with the merge tool",
+            Review,
+            Merge,
+        ),
+        bind(
+            &[key('E')],
+            "E",
+            "edit the expected output here",
+            Review,
+            EditExpected,
+        ),
+        bind(&[key('v')], "v", "mark reviewed", Review, MarkReviewed),
+        bind(&[key('S')], "S", "mark skipped", Review, MarkSkipped),
+        bind(&[key('D')], "D", "mark deferred", Review, MarkDeferred),
+        bind(&[key('x')], "x", "explain the error code", Review, Explain),
+        bind(
+            &[key('e')],
+            "e",
+            "edit the test at the diagnostic",
+            Review,
+            Edit,
+        ),
+        bind(
+            &[key('y')],
+            "y<p/b/d/x>",
+            "copy the test path, bless target, diff or ./x command",
+            Review,
+            Copy,
+        ),
+        bind(
+            &[key('u')],
+            "u",
+            "update the annotations (annotations view)",
+            Review,
+            UpdateAnnotations,
+        ),
+        bind(
+            &[key('.')],
+            ".",
+            "repeat the last bless, mark, edit, merge or rerun",
+            Review,
+            Repeat,
+        ),
+        bind(
+            &[key('q'), special(KeyCode::Esc)],
+            "q/esc",
+            "quit",
+            Review,
+            Quit,
+        ),
+        bind(&[key('n')], "n", "next test", Tests, NextTest),
+        bind(
+            &[special(KeyCode::Tab)],
+            "tab",
+            "next output of the test",
+            Tests,
+            NextStream,
+        ),
+        bind(
+            &[special(KeyCode::BackTab)],
+            "⇧tab",
+            "previous output of the test",
+            Tests,
+            PreviousStream,
+        ),
+        bind(
+            &[key('1')],
+            "1",
+            "the stderr of the test",
+            Tests,
+            ShowStderr,
+        ),
+        bind(
+            &[key('2')],
+            "2",
+            "the stdout of the test",
+            Tests,
+            ShowStdout,
+        ),
+        bind(&[key('N')], "N", "previous test", Tests, PreviousTest),
+        bind(
+            &[key('R')],
+            "R",
+            "reload the test and its outputs",
+            Tests,
+            Reload,
+        ),
+        bind(
+            &[ctrl('r')],
+            "^r",
+            "rerun the test with rustc alone, faster than ./x test",
+            Tests,
+            Rerun,
+        ),
+        bind(&[key(':')], ":N", "go to test N", Tests, GoTo),
+        bind(&[key('f')], "f", "find a test by path", Tests, FindTest),
+        bind(&[key('t')], "t", "tests by directory", Tests, Tree),
+        bind(&[key('<')], "<", "back", Tests, Back),
+        bind(&[key('>')], ">", "forward", Tests, Forward),
+        bind(&[key('m')], "m<c>", "set bookmark <c>", Tests, SetBookmark),
+        bind(
+            &[key('\'')],
+            "'<c>",
+            "go to bookmark <c>",
+            Tests,
+            GoToBookmark,
+        ),
+        bind(&[key('O')], "O", "change the sort order", Tests, RotateSort),
+        bind(&[key(']')], "]", "next hunk", Diff, NextHunk),
+        bind(&[key('[')], "[", "previous hunk", Diff, PreviousHunk),
+        bind(
+            &[special(KeyCode::Enter)],
+            "enter",
+            "show the code line of the diagnostic",
+            Diff,
+            JumpToLine,
+        ),
+        bind(&[key('/')], "/", "search, then n/N", Diff, Search),
+        bind(
+            &[key('j'), special(KeyCode::Down)],
+            "j/↓",
+            "down",
+            Scrolling,
+            ScrollDown,
+        ),
+        bind(
+            &[key('k'), special(KeyCode::Up)],
+            "k/↑",
+            "up",
+            Scrolling,
+            ScrollUp,
+        ),
+        bind(
+            &[ctrl('d')],
+            "^d",
+            "half a page down",
+            Scrolling,
+            HalfPageDown,
+        ),
+        bind(&[ctrl('u')], "^u", "half a page up", Scrolling, HalfPageUp),
+        bind(
+            &[special(KeyCode::PageDown)],
+            "PgDn",
+            "a page down",
+            Scrolling,
+            PageDown,
+        ),
+        bind(
+            &[special(KeyCode::PageUp)],
+            "PgUp",
+            "a page up",
+            Scrolling,
+            PageUp,
+        ),
+        bind(&[key('g')], "gg", "top", Scrolling, Top),
+        bind(&[key('G')], "G", "bottom", Scrolling, Bottom),
+        bind(
+            &[ctrl('w')],
+            "^w",
+            "focus the next pane",
+            Scrolling,
+            CycleFocus,
+        ),
+        bind(&[key('d')], "d", "next diff mode", Views, RotateDiffMode),
+        bind(
+            &[key('s')],
+            "s",
+            "vertical/horizontal layout",
+            Views,
+            ToggleLayout,
+        ),
+        bind(&[key('c')], "c", "show/hide the code", Views, ToggleCode),
+        bind(
+            &[key('A')],
+            "A",
+            "show the next auxiliary crate in the code pane",
+            Views,
+            NextCodeTab,
+        ),
+        bind(&[key('w')], "w", "wrap long lines", Views, ToggleWrap),
+        bind(
+            &[key('L')],
+            "L",
+            "show the lines cut off at the edge of the focused pane (marked with »)",
+            Views,
+            CutOff,
+        ),
+        bind(
+            &[key('P')],
+            "P",
+            "show/hide the metadata panel: file sizes and ages, edition, revisions",
+            Views,
+            ToggleMetadata,
+        ),
+        bind(
+            &[key('C')],
+            "C",
+            "colorblind colors",
+            Views,
+            ToggleColorblind,
+        ),
+        bind(&[key('i')], "i", "ICE view/diff", Views, ToggleIce),
+        bind(&[key('r')], "r", "rustc arguments", Views, RustcArgs),
+        bind(&[key('o')], "o", "one line rustc arguments", Views, Oneline),
+        bind(&[key('a')], "a", "annotations", Views, Annotations),
+        bind(
+            &[key('@')],
+            "@",
+            "test info: resolved paths, //@ directives, last change of the expected output",
+            Views,
+            Info,
+        ),
+        bind(
+            &[key('H')],
+            "H",
+            "the results of the test in the recorded runs",
+            Views,
+            History,
+        ),
+        bind(&[key('F')], "F", "non-UI failures", Views, OtherFailures),
+        bind(&[key('I')], "I", "ignored tests", Views, Ignored),
+        bind(
+            &[key('T')],
+            "T",
+            "statistics of all the failures",
+            Views,
+            Statistics,
+        ),
+        bind(
+            &[key('X')],
+            "X",
+            "diff with the external tool",
+            Views,
+            ExternalDiff,
+        ),
+        bind(
+            &[key('K')],
+            "K",
+            "the output of rustc with its colors, compiling the test again",
+            Views,
+            Colored,
+        ),
+        bind(&[key('p')], "p", "previous view", Views, PreviousMode),
+        bind(
+            &[key('h')],
+            "h",
+            "show/hide the help bar",
+            Views,
+            ToggleHelpBar,
+        ),
+        bind(&[key('?')], "?", "all keys", Views, Help),
+    ]
+};
+
+/// The command bound to `event`, if any.
+pub fn lookup(event: KeyEvent) -> Option<Command> {
+    let pressed = Key {
+        code: event.code,
+        ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
+    };
+    BINDINGS
+        .iter()
+        .find(|binding| binding.keys.contains(&pressed))
+        .map(|binding| binding.command)
+}
+
+/// Every binding, grouped by category.
+pub fn help_text() -> String {
+    let width = BINDINGS
+        .iter()
+        .map(|b| b.label.width())
+        .max()
+        .unwrap_or_default();
+    let mut text = String::new();
+    for category in Category::ALL {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(category.name());
+        text.push('\n');
+        for binding in BINDINGS.iter().filter(|b| b.category == category) {
+            // Padded by hand, as `format!` counts chars rather than columns.
+            let padding = " ".repeat(width - binding.label.width());
+            text.push_str(&format!(
+                "  {}{padding}  {}\n",
+                binding.label, binding.description
+            ));
+        }
+    }
+    text
+}
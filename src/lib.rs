@@ -0,0 +1,75 @@
+//! Review the failing UI tests of a compiletest run: the TUI of the `compiletest-differ` binary,
+//! and the parts of it that don't need a terminal.
+//!
+//! - [`parse_events`] reads the JSON output of libtest or cargo-nextest into the failed tests, and
+//!   [`ui_test::parse`] the output of the `ui_test` crate.
+//! - [`outputs::CachedStreams::load`] finds the test, its expected outputs and the actual ones in
+//!   the build directory, and [`outputs::CachedStreams::bless`] overwrites the expected outputs.
+//! - [`classify::Classification`] tells what kind of change a diff is.
+//! - [`report::collect`] does all of the above for a list of tests.
+//!
+//! Where the outputs are looked up is set once per process, before loading any test, as the binary
+//! does from its flags. Without these the checkout is local and the actual outputs are in the
+//! default build directory of bootstrap:
+//!
+//! - [`remote::set`] reads and writes the checkout over SSH, like `--remote`.
+//! - [`outputs::set_build_dir`] changes the layout of the build directory, like `--build-dir` and
+//!   `--stage`.
+//! - [`artifacts::load`] reads the actual outputs from an archive instead, like `--artifacts`, and
+//!   [`artifacts::set`] from the ones rebuilt by [`ui_test::UiTestRun::actual_outputs`].
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use compiletest_differ::{outputs::CachedStreams, parse_events};
+//!
+//! let rust = Path::new("/path/to/rust");
+//! let json = std::fs::read_to_string(rust.join("build/tests.json")).unwrap();
+//! for test in parse_events(&json).failed {
+//!     CachedStreams::load(rust, test).unwrap().bless().unwrap();
+//! }
+//! ```
+
+mod annotations;
+pub mod app;
+pub mod artifacts;
+pub mod ci;
+pub mod classify;
+mod clipboard;
+pub mod compare;
+pub mod compressed;
+pub mod config;
+pub mod diff;
+mod diff_cache;
+pub mod directives;
+mod editor;
+pub mod events;
+mod external;
+pub mod filter;
+pub mod git;
+pub mod history;
+pub mod hooks;
+mod ice;
+mod keys;
+mod loader;
+mod metadata;
+pub mod outputs;
+mod picker;
+pub mod preload;
+pub mod recording;
+pub mod remote;
+pub mod report;
+mod rustc;
+pub mod scan;
+mod search;
+pub mod session;
+pub mod sort;
+pub mod theme;
+pub mod timings;
+mod tree;
+pub mod ui_test;
+mod view;
+pub mod watch;
+
+pub use app::App;
+pub use events::{parse_events, Events, FailureKind, Ignored, Stats, Stream};
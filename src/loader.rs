@@ -0,0 +1,53 @@
+//! Reading the current test on a worker thread, so that the UI keeps drawing on slow filesystems
+//! and remotes.
+
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use crate::outputs::{CachedData, CachedStreams};
+
+#[derive(Debug)]
+pub struct Loader {
+    path: &'static str,
+    result: Receiver<CachedStreams>,
+}
+
+impl Loader {
+    pub fn start(rust_path: PathBuf, path: &'static str) -> Self {
+        let (sender, result) = mpsc::channel();
+        thread::spawn(move || {
+            // The receiver is gone if another test was shown in the meantime.
+            let _ = sender.send(CachedStreams::load_or_missing(&rust_path, path));
+        });
+        Self { path, result }
+    }
+
+    /// The test being read.
+    pub fn path(&self) -> &'static str {
+        self.path
+    }
+
+    /// The outputs of the test once they're read, waiting for them if `wait`.
+    pub fn get(&self, wait: bool) -> Option<CachedStreams> {
+        let result = if wait {
+            self.result.recv().map_err(|_| TryRecvError::Disconnected)
+        } else {
+            self.result.try_recv()
+        };
+        match result {
+            Ok(streams) => Some(streams),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                tracing::warn!(path = self.path, "the loading thread died");
+                Some(CachedStreams {
+                    stderr: CachedData::Missing,
+                    stdout: CachedData::Missing,
+                    ..Default::default()
+                })
+            }
+        }
+    }
+}
@@ -1,109 +1,585 @@
-use std::{fs::read_to_string, path::PathBuf};
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{self, ExitCode},
+    sync::Mutex,
+    time::Duration,
+};
 
-pub use app::App;
-use serde::Deserialize;
+use clap::Parser;
+use cli::{Args, Command, Filter, Input, ViewArgs};
+use compiletest_differ::{
+    app::{App, Config, DEFAULT_TAB_WIDTH},
+    artifacts, ci, compare, compressed,
+    config::FileConfig,
+    directives,
+    filter::PathFilter,
+    git,
+    history::{self, History, Outcome},
+    hooks,
+    outputs::{self, CachedStreams},
+    parse_events,
+    preload::Preload,
+    recording::{Recorder, Replay},
+    remote, report, scan,
+    session::{Session, Status},
+    timings::{self, Phase},
+    ui_test,
+    watch::Watch,
+    Events,
+};
+use rayon::prelude::*;
 
-pub mod app;
-mod view;
+mod cli;
 
-#[derive(Debug, Clone, Copy, Default)]
-pub enum Stream {
-    #[default]
-    Stderr,
-    Stdout,
+/// Asks whether to resume `session`. Returns the index of the test to resume at, if any.
+fn offer_resume(session: &Session, paths: &[&str]) -> io::Result<Option<usize>> {
+    let Some(index) = session
+        .current
+        .as_deref()
+        .and_then(|current| paths.iter().position(|&p| p == current))
+    else {
+        return Ok(None);
+    };
+    print!(
+        "Resume the previous session at test {}/{} ({} blessed, {} reviewed, {} skipped, {} deferred)? [Y/n] ",
+        index + 1,
+        paths.len(),
+        session.count(Status::Blessed),
+        session.count(Status::Reviewed),
+        session.count(Status::Skipped),
+        session.count(Status::Deferred),
+    );
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok((answer.is_empty() || answer.eq_ignore_ascii_case("y")).then_some(index))
 }
 
-#[derive(Deserialize)]
-#[serde(tag = "type")]
-enum Item<'a> {
-    #[serde(rename = "test")]
-    Test { name: &'a str, event: &'a str },
-    #[serde(rename = "suite")]
-    Suite {
-        failed: u32,
-        passed: u32,
-        ignored: u32,
-    },
+/// Resolves `--start-at` to an index into `paths`.
+fn start_index(paths: &[&str], start_at: &str) -> color_eyre::Result<usize> {
+    if let Ok(n) = start_at.parse::<usize>() {
+        if n == 0 || n > paths.len() {
+            color_eyre::eyre::bail!("--start-at {n} is out of range 1..={}", paths.len());
+        }
+        return Ok(n - 1);
+    }
+    paths
+        .iter()
+        .position(|&p| p == start_at)
+        .or_else(|| paths.iter().position(|p| p.contains(start_at)))
+        .ok_or_else(|| color_eyre::eyre::eyre!("No failed test matches --start-at {start_at}"))
 }
 
-#[derive(Debug, Clone, Copy, Default)]
-pub struct Stats {
-    failed: u32,
-    passed: u32,
-    ignored: u32,
+/// Where the rust checkout is.
+fn rust_path() -> PathBuf {
+    remote::checkout().map_or_else(|| PathBuf::from("/home/ardi/repos/rust"), Path::to_owned)
 }
 
-fn parse_events(events: &str) -> (Vec<&str>, Stats) {
-    let lines = events
-        .lines()
-        .filter_map(|x| serde_json::from_str::<Item>(x).ok())
-        .skip(1);
-
-    let mut failed = vec![];
-    let mut stats = None;
-    let mut ok_count = 0;
-
-    for event in lines {
-        match event {
-            Item::Test { name, event } => {
-                if event != "failed" {
-                    ok_count += 1;
-                    continue;
-                }
-                let Some((_, path)) = name.split_once("[ui] ") else {
-                    // It's not UI test
-                    continue;
-                };
-                failed.push(path);
+/// Reads the failures of the run in `file` and keeps the UI tests that match `filter`, along with
+/// the contents of `file`, which the session is keyed by.
+fn load(file: &Path, filter: &Filter) -> color_eyre::Result<(Events<'static>, &'static str)> {
+    let label = file.display().to_string();
+    let bytes = timings::time(Phase::Read, &label, || {
+        remote::read(file).and_then(compressed::decompress)
+    })
+    .map_err(|e| color_eyre::eyre::eyre!("Can't read {}: {e}", file.display()))?;
+    let test_data = String::from_utf8(bytes)
+        .map_err(|e| color_eyre::eyre::eyre!("{} isn't UTF-8: {e}", file.display()))?
+        .leak();
+    let mut events = timings::time(Phase::Parse, &label, || {
+        if !ui_test::is_output(test_data) {
+            return parse_events(test_data);
+        }
+        let run = ui_test::parse(test_data);
+        artifacts::set(run.actual_outputs(&rust_path()));
+        run.events
+    });
+    let filters = PathFilter::parse_all(&filter.filter, filter.regex)?;
+    let excludes = PathFilter::parse_all(&filter.exclude, filter.regex)?;
+    let keep = |path: &&str| {
+        (filters.is_empty() || filters.iter().any(|f| f.matches(path)))
+            && !excludes.iter().any(|f| f.matches(path))
+    };
+    events.failed.retain(keep);
+    events.passed.retain(keep);
+    if !filter.edition.is_empty() {
+        // Reads every failed test, on the remote too.
+        let rust_path = rust_path();
+        events.failed = events
+            .failed
+            .into_par_iter()
+            .filter(|path| has_edition(&rust_path, path, &filter.edition))
+            .collect();
+    }
+    Ok((events, test_data))
+}
+
+/// Whether the test at `path` is compiled with one of `editions`, or can't be read.
+fn has_edition(rust_path: &Path, path: &str, editions: &[String]) -> bool {
+    let Ok(code) = remote::read_to_string(&rust_path.join(outputs::native_path(path))) else {
+        return true;
+    };
+    directives::editions(&directives::parse(&code))
+        .iter()
+        .any(|edition| editions.iter().any(|e| e == edition))
+}
+
+/// Logs to `path`, for debugging.
+fn init_log(path: &Path) -> color_eyre::Result<()> {
+    let file = File::create(path)?;
+    tracing_subscriber::fmt()
+        .with_writer(Mutex::new(file))
+        .with_ansi(false)
+        .with_max_level(tracing::Level::TRACE)
+        .init();
+    Ok(())
+}
+
+/// Leaves the TUI, if it's running, so that reports of errors and panics are readable.
+fn restore_terminal() {
+    // Otherwise this would print escape codes into the output.
+    if crossterm::terminal::is_raw_mode_enabled().unwrap_or(false) {
+        ratatui::restore();
+    }
+}
+
+/// Installs color_eyre's panic and error reporting, with the terminal restored before a panic is
+/// reported.
+fn install_hooks() -> color_eyre::Result<()> {
+    let (panic_hook, eyre_hook) = color_eyre::config::HookBuilder::default().into_hooks();
+    // Reports are created when errors happen, including the ones shown in the TUI, so the terminal
+    // is restored where they're printed instead, in `main`.
+    eyre_hook.install()?;
+    let panic_hook = panic_hook.into_panic_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        panic_hook(info);
+    }));
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let code = install_hooks()
+        .and_then(|()| run_command(Args::parse()))
+        .unwrap_or_else(|error| {
+            restore_terminal();
+            eprintln!("Error: {error:?}");
+            ExitCode::FAILURE
+        });
+    if let Some(report) = timings::report() {
+        eprint!("{report}");
+    }
+    code
+}
+
+fn run_command(args: Args) -> color_eyre::Result<ExitCode> {
+    if let Some(path) = &args.log {
+        init_log(path)?;
+    }
+    if args.timings {
+        timings::enable();
+    }
+    if let Some(spec) = &args.remote {
+        remote::set(spec).ok_or_else(|| {
+            color_eyre::eyre::eyre!("--remote takes user@host:/path/to/rust, not {spec}")
+        })?;
+    }
+    tracing::info!(rust_path = %rust_path().display(), ?args.command, "starting");
+    let file_config = FileConfig::load()?;
+    let stage = args.stage.or(file_config.stage);
+    let mut build_dir = args.build_dir.or(file_config.build_dir);
+    let mut command = args.command.unwrap_or(Command::View(args.view));
+    // Fetched before anything reads the build directory, which can be in the download.
+    if let Command::FetchCi {
+        id,
+        repo,
+        artifact,
+        dir,
+        filter,
+    } = command
+    {
+        if remote::host().is_some() {
+            color_eyre::eyre::bail!("fetch-ci downloads locally, it can't be used with --remote");
+        }
+        let fetched = ci::fetch(&id, &repo, &artifact, dir.as_deref())?;
+        build_dir = build_dir.or(fetched.build_dir);
+        command = Command::View(ViewArgs {
+            input: Input {
+                file: fetched.input,
+                filter,
+            },
+            ..Default::default()
+        });
+    }
+    outputs::set_build_dir(build_dir, stage);
+    if let Some(path) = &args.artifacts {
+        artifacts::load(path)?;
+    }
+    match command {
+        Command::View(args) => view(args),
+        Command::BlessAll { input } => bless_all(&input),
+        Command::Report {
+            input,
+            markdown,
+            json,
+            github,
+            text,
+            html,
+        } => {
+            let (events, contents) = load(&input.file, &input.filter)?;
+            let previous = Session::path_for(&input.file, contents)
+                .as_deref()
+                .and_then(Session::load);
+            let tests = report::collect(
+                &rust_path(),
+                &events.failed,
+                &events.failure_kinds,
+                previous.as_ref(),
+            );
+            if markdown {
+                print!(
+                    "{}",
+                    report::markdown(&tests, events.stats, &events.other_failed)
+                );
+            } else if json {
+                let json = report::json(&tests, events.stats, &events.other_failed)?;
+                println!("{json}");
+            } else if github {
+                print!("{}", report::github(&tests));
+            } else if text {
+                print!(
+                    "{}",
+                    report::text(&tests, events.stats, &events.other_failed)
+                );
+            } else if html {
+                print!(
+                    "{}",
+                    report::html(&tests, events.stats, &events.other_failed)
+                );
             }
-            Item::Suite {
-                failed,
-                passed,
-                ignored,
-            } => {
-                stats = Some(Stats {
-                    failed,
-                    passed,
-                    ignored,
-                })
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::CompareRuns { old, new, filter } => {
+            print!(
+                "{}",
+                compare::compare(&load(&old, &filter)?.0, &load(&new, &filter)?.0)
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Scan { filter } => {
+            let filters = PathFilter::parse_all(&filter.filter, filter.regex)?;
+            let excludes = PathFilter::parse_all(&filter.exclude, filter.regex)?;
+            let rust_path = rust_path();
+            for test in scan::scan(&rust_path)? {
+                if (filters.is_empty() || filters.iter().any(|f| f.matches(&test)))
+                    && !excludes.iter().any(|f| f.matches(&test))
+                    && (filter.edition.is_empty()
+                        || has_edition(&rust_path, &test, &filter.edition))
+                {
+                    println!("{test}");
+                }
             }
+            Ok(ExitCode::SUCCESS)
         }
+        Command::Run { tests, output } => run(&tests, &output, stage),
+        Command::History { test } => {
+            let path = History::path()
+                .filter(|path| path.exists())
+                .ok_or_else(|| {
+                    color_eyre::eyre::eyre!("No runs recorded, set `history: true` in the config")
+                })?;
+            print!("{}", History::open(&path)?.describe(&test)?);
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::FetchCi { .. } => unreachable!("replaced with view above"),
     }
+}
 
-    let stats = stats.unwrap_or(Stats {
-        failed: failed.len() as u32,
-        passed: ok_count,
-        ignored: 0,
-    });
+/// Blesses every failed test that can be.
+fn bless_all(input: &Input) -> color_eyre::Result<ExitCode> {
+    let (events, _) = load(&input.file, &input.filter)?;
+    let file_config = FileConfig::load()?;
+    let rust_path = rust_path();
+    let mut blessed = 0;
+    let mut skipped = 0;
+    for &path in &events.failed {
+        if events.failure_kinds.contains_key(path) {
+            println!("Skipping {path}, it didn't fail because of its output");
+            skipped += 1;
+            continue;
+        }
+        let streams = match CachedStreams::load(&rust_path, path) {
+            Ok(streams) => streams,
+            Err(e) => {
+                println!("Skipping {path}: {e}");
+                skipped += 1;
+                continue;
+            }
+        };
+        if streams.present().iter().any(|data| data.is_ice()) {
+            println!("Skipping {path}, its actual output is an internal compiler error");
+            skipped += 1;
+            continue;
+        }
+        if let Some(hook) = &file_config.pre_bless {
+            let checked = streams.present().iter().try_for_each(|data| {
+                hooks::pre_bless(hook, &rust_path, path, &data.expected_path, &data.actual)
+            });
+            if let Err(e) = checked {
+                println!("Skipping {path}: {e}");
+                skipped += 1;
+                continue;
+            }
+        }
+        streams.bless()?;
+        if file_config.git_add {
+            git::add(&rust_path, &streams.expected_paths())?;
+        }
+        if let Some(hook) = &file_config.post_bless {
+            hooks::post_bless(hook, &rust_path, path, &streams.expected_paths())?;
+        }
+        blessed += 1;
+    }
+    println!("Blessed {blessed} tests");
+    Ok(if skipped > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
 
-    (failed, stats)
+/// Runs `tests` with `./x test`, writing the JSON output to `output`, and reviews the failures.
+fn run(tests: &[String], output: &Path, stage: Option<u32>) -> color_eyre::Result<ExitCode> {
+    let rust_path = rust_path();
+    let output = rust_path.join(output);
+    println!("Running {} into {}", tests.join(" "), output.display());
+    let stage = stage.map(|stage| format!("--stage={stage}"));
+    let args = ["test"]
+        .into_iter()
+        .chain(tests.iter().map(String::as_str))
+        .chain(stage.as_deref())
+        .chain(["--force-rerun", "--", "-Zunstable-options", "--format=json"]);
+    // Test failures make x exit with an error, only the output matters.
+    let run = remote::command(&rust_path, rust_path.join("x"), args)
+        .stderr(process::Stdio::inherit())
+        .output()?;
+    remote::write(&output, run.stdout)?;
+    view(ViewArgs {
+        input: Input {
+            file: output,
+            filter: Filter::default(),
+        },
+        ..Default::default()
+    })
 }
 
-fn main() -> color_eyre::Result<()> {
-    color_eyre::install()?;
-    let file = std::env::args()
-        .nth(1)
-        .unwrap_or("/home/ardi/repos/rust/blah.json".to_owned());
-    let test_data = read_to_string(&file)
-        .expect("Can't find json output")
-        .leak();
-    let (paths, stats) = parse_events(test_data);
+/// Opens the TUI.
+fn view(args: ViewArgs) -> color_eyre::Result<ExitCode> {
+    let file_config = FileConfig::load()?;
+    let theme = file_config.theme(args.theme.as_deref(), args.background)?;
+    let (events, contents) = load(&args.input.file, &args.input.filter)?;
+    let Events {
+        failed: mut paths,
+        passed,
+        other_failed,
+        ignored,
+        exec_times,
+        failure_kinds,
+        stats,
+    } = events;
+
+    let rust_path = rust_path();
+    let git_head = git::head(&rust_path);
+    let runs = if file_config.history {
+        let path = History::path()
+            .ok_or_else(|| color_eyre::eyre::eyre!("No data directory for the history"))?;
+        let mut runs = History::open(&path)?;
+        // Only loads the outputs the first time the run is reviewed.
+        runs.record(&args.input.file, git_head.as_deref(), || {
+            let failed = paths.iter().map(|&test| {
+                let streams = CachedStreams::load_or_missing(&rust_path, test);
+                (test, Outcome::Failed(history::diff_hash(&streams)))
+            });
+            passed
+                .iter()
+                .map(|&test| (test, Outcome::Passed))
+                .chain(failed)
+                .collect()
+        })?;
+        Some(runs)
+    } else {
+        None
+    };
+
+    let filter = &args.input.filter;
+    if paths.is_empty()
+        && (!filter.filter.is_empty() || !filter.exclude.is_empty() || !filter.edition.is_empty())
+    {
+        println!(
+            "None of the {} failed tests match the filters",
+            stats.failed
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
 
     if paths.is_empty() {
         println!(
-            "No failed tests: {} ok and {} ignored",
+            "No failed UI tests: {} ok and {} ignored",
             stats.passed, stats.ignored
         );
-        return Ok(());
+        if !other_failed.is_empty() {
+            println!("{} other tests failed:", other_failed.len());
+            for name in &other_failed {
+                println!("    {name}");
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
     }
 
-    let terminal = ratatui::init();
-    let app = App {
+    let session_path = Session::path_for(&args.input.file, contents);
+    let previous = session_path.as_deref().and_then(Session::load);
+    if !args.status.is_empty() {
+        let previous = previous.as_ref().ok_or_else(|| {
+            color_eyre::eyre::eyre!("--status needs a previous session for this input")
+        })?;
+        paths.retain(|path| {
+            previous
+                .statuses
+                .get(*path)
+                .is_some_and(|s| args.status.contains(s))
+        });
+        if paths.is_empty() {
+            println!("No tests with the requested status");
+            return Ok(ExitCode::SUCCESS);
+        }
+    }
+
+    let mut session = Session::default();
+    let mut resume_at = None;
+    if let Some(previous) = previous {
+        if !args.fresh && args.list.is_none() {
+            resume_at = offer_resume(&previous, &paths)?;
+        }
+        if resume_at.is_some() {
+            session = previous;
+        }
+    }
+
+    let mut imported = vec![];
+    for path in &args.import {
+        let (added, kept) = session
+            .import(path)
+            .map_err(|e| color_eyre::eyre::eyre!("Can't import {}: {e}", path.display()))?;
+        imported.push(format!("{added} marks from {}", path.display()));
+        if kept > 0 {
+            imported.push(format!("kept {kept} different marks"));
+        }
+    }
+
+    let replay = args
+        .replay
+        .as_deref()
+        .map(|path| {
+            Replay::load(path)
+                .map_err(|e| color_eyre::eyre::eyre!("Can't replay {}: {e}", path.display()))
+        })
+        .transpose()?;
+    let mut message = (!imported.is_empty()).then(|| format!("Imported {}", imported.join(", ")));
+    if let Some(replay) = &replay {
+        if replay.input != args.input.file {
+            message = Some(format!(
+                "The replay was recorded on {}, not this input",
+                replay.input.display()
+            ));
+        }
+    }
+    let recorder = args
+        .record
+        .as_deref()
+        .map(|path| {
+            Recorder::create(path, &args.input.file)
+                .map_err(|e| color_eyre::eyre::eyre!("Can't record to {}: {e}", path.display()))
+        })
+        .transpose()?;
+
+    let current_test = match &args.start_at {
+        Some(start_at) => start_index(&paths, start_at)?,
+        None => resume_at.unwrap_or(0),
+    };
+
+    let run_started = stats.exec_time.and_then(|seconds| {
+        let written = remote::modified(&args.input.file).ok()?;
+        written.checked_sub(Duration::from_secs_f64(seconds))
+    });
+    let mut app = App {
+        config: Config {
+            theme,
+            tab_width: file_config.tab_width.unwrap_or(DEFAULT_TAB_WIDTH),
+            ..Default::default()
+        },
+        diff_tool: args.diff_tool.or(file_config.diff_tool),
+        merge_tool: args.merge_tool.or(file_config.merge_tool),
+        x_command: file_config
+            .x_command
+            .unwrap_or_else(|| "./x test {path} --force-rerun --bless".to_owned()),
+        git_add: file_config.git_add,
+        pre_bless: file_config.pre_bless,
+        post_bless: file_config.post_bless,
+        wrap_around: file_config.wrap_around,
+        after_bless: file_config.after_bless,
+        confirm_bless: file_config.confirm_bless,
+        commit: args.commit,
+        fail_on: args.fail_on,
+        current_test,
+        run_order: paths.clone(),
+        other_failed,
+        ignored,
+        exec_times,
+        failure_kinds,
         paths,
         stats,
-        rust_path: PathBuf::from("/home/ardi/repos/rust"),
+        run_started,
+        git_head,
+        runs,
+        rust_path,
+        session,
+        session_path,
+        export_path: args.export,
+        recorder,
+        replay,
+        message,
         ..Default::default()
     };
+    app.sort_tests(args.sort);
+    if let Some(format) = args.list {
+        let tests = report::collect(&app.rust_path, &app.paths, &app.failure_kinds, None);
+        print!("{}", report::list(&tests, format));
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.watch {
+        if remote::host().is_some() || artifacts::get().is_some() {
+            color_eyre::eyre::bail!("--watch only works with a local build directory");
+        }
+        let mut suites: Vec<_> = app
+            .paths
+            .iter()
+            .filter_map(|path| path.split('/').nth(1))
+            .collect();
+        suites.sort_unstable();
+        suites.dedup();
+        let build_dirs: Vec<_> = suites
+            .into_iter()
+            .map(|suite| outputs::suite_build_dir(&app.rust_path, suite))
+            .collect();
+        app.watch = Some(Watch::new(&build_dirs, &args.input.file)?);
+    }
+    if args.preload {
+        app.preload = Some(Preload::start(app.rust_path.clone(), app.paths.clone()));
+    }
+    let terminal = ratatui::init();
     let result = app.run(terminal);
     ratatui::restore();
     result
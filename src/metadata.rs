@@ -0,0 +1,143 @@
+//! The metadata panel: the sizes and ages of the files of a test, and the directives that change
+//! how it's compiled.
+
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use ratatui::{
+    style::Stylize,
+    text::{Line, Span, Text},
+};
+
+use crate::{
+    directives,
+    outputs::{native_path, CachedStreams},
+    remote,
+};
+
+#[derive(Debug, Clone)]
+struct File {
+    label: String,
+    bytes: usize,
+    lines: usize,
+    /// `None` for the outputs from an artifacts archive.
+    modified: Option<SystemTime>,
+}
+
+impl File {
+    fn new(label: String, path: Option<&Path>, text: &str) -> Self {
+        Self {
+            label,
+            bytes: text.len(),
+            lines: text.lines().count(),
+            modified: path.and_then(|path| remote::modified(path).ok()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    files: Vec<File>,
+    edition: Option<String>,
+    revisions: Vec<String>,
+}
+
+impl Metadata {
+    /// Of the test in `streams`, `None` if none of its outputs differ.
+    pub fn gather(rust_path: &Path, streams: &CachedStreams) -> Option<Self> {
+        let present = streams.present();
+        let first = present.first()?;
+        let test_path = rust_path.join(native_path(&first.test_name));
+        let mut files = vec![File::new(
+            "test".to_owned(),
+            Some(&test_path),
+            &first.test_code,
+        )];
+        for data in &present {
+            let stream = data.stream.name();
+            if !data.expect.is_empty() {
+                files.push(File::new(
+                    format!("expected {stream}"),
+                    Some(&data.expected_path),
+                    &data.expect,
+                ));
+            }
+            if !data.actual.is_empty() {
+                files.push(File::new(
+                    format!("actual {stream}"),
+                    data.actual_path.as_deref(),
+                    &data.actual,
+                ));
+            }
+        }
+
+        let directives = directives::parse(&first.test_code);
+        let value = |name| {
+            directives
+                .iter()
+                .find(|d| d.revision.is_none() && d.name == name)
+                .and_then(|d| d.value)
+        };
+        Some(Self {
+            files,
+            edition: value("edition").map(str::to_owned),
+            revisions: value("revisions")
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(str::to_owned)
+                .collect(),
+        })
+    }
+
+    pub fn to_text(&self) -> Text<'static> {
+        let now = SystemTime::now();
+        let mut lines = vec![];
+        for file in &self.files {
+            lines.push(Line::from(file.label.clone()).bold());
+            let modified = match file.modified {
+                Some(time) => format!(", {}", ago(now.duration_since(time).unwrap_or_default())),
+                None => String::new(),
+            };
+            lines.push(Line::from(format!(
+                "  {} lines, {}{modified}",
+                file.lines,
+                size(file.bytes)
+            )));
+        }
+        lines.push(Line::default());
+        lines.push(Line::from(vec![
+            "edition: ".bold(),
+            match &self.edition {
+                Some(edition) => Span::raw(edition.clone()),
+                None => "the default".dim(),
+            },
+        ]));
+        lines.push(Line::from(vec![
+            "revisions: ".bold(),
+            match self.revisions.as_slice() {
+                [] => "none".dim(),
+                revisions => Span::raw(revisions.join(", ")),
+            },
+        ]));
+        lines.into()
+    }
+}
+
+fn size(bytes: usize) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        _ => format!("{:.1} KiB", bytes as f64 / 1024.0),
+    }
+}
+
+fn ago(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    match seconds {
+        0..60 => format!("{seconds}s ago"),
+        60..3600 => format!("{}m ago", seconds / 60),
+        3600..86400 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
@@ -0,0 +1,522 @@
+//! The outputs of a UI test: where they are, reading them and blessing them.
+
+use std::{
+    fmt::Write,
+    io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    artifacts,
+    diff::{diff_size, similarity},
+    directives, ice, remote, rustc,
+    timings::{self, Phase},
+    Stream,
+};
+
+#[derive(Debug, Clone)]
+pub struct TestData {
+    pub test_code: String,
+    pub actual: String,
+    pub expect: String,
+    pub stream: Stream,
+    pub test_name: String,
+    /// The rustc command of the test, rebuilt as it isn't in the logs, see
+    /// [`crate::rustc::command_line`].
+    pub rustc_args: String,
+    // Used for blessing
+    pub expected_path: PathBuf,
+    /// Where the actual output was read from, unless it's missing or from `--artifacts`.
+    pub actual_path: Option<PathBuf>,
+    /// The auxiliary crates built by the test, shown as extra tabs of the code pane.
+    pub aux: Vec<AuxFile>,
+    /// Set when one of the outputs isn't text: `expect` and `actual` are hexdumps then, and
+    /// these are the bytes to bless.
+    pub raw_actual: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuxFile {
+    /// Relative to the directory of the test, like `auxiliary/foo.rs`.
+    pub path: String,
+    pub code: String,
+}
+
+impl TestData {
+    /// Whether the outputs are compared as hexdumps.
+    pub fn is_binary(&self) -> bool {
+        self.raw_actual.is_some()
+    }
+
+    /// Whether the actual output is an internal compiler error, which is rarely worth blessing.
+    pub fn is_ice(&self) -> bool {
+        ice::detect(&self.actual).is_some()
+    }
+
+    /// What blessing writes to the expected output.
+    pub fn actual_bytes(&self) -> &[u8] {
+        self.raw_actual.as_deref().unwrap_or(self.actual.as_bytes())
+    }
+
+    /// The title and text of the code pane at `tab`, 0 being the test.
+    pub fn code(&self, tab: usize) -> (String, &str) {
+        match tab.checked_sub(1).and_then(|i| self.aux.get(i)) {
+            Some(aux) => (
+                format!("{} ({tab}/{})", aux.path, self.aux.len()),
+                &aux.code,
+            ),
+            None => ("code".to_owned(), &self.test_code),
+        }
+    }
+
+    /// Why the actual output may not be what the test outputs now: the test or an auxiliary
+    /// crate was edited after it was written, or it was written before the run, which started at
+    /// `run_started`.
+    pub fn staleness(
+        &self,
+        rust_path: &Path,
+        run_started: Option<SystemTime>,
+    ) -> Option<&'static str> {
+        let written = remote::modified(self.actual_path.as_deref()?).ok()?;
+        let test = rust_path.join(native_path(&self.test_name));
+        let test_dir = test.parent().unwrap_or(rust_path);
+        let edited = self
+            .aux
+            .iter()
+            .map(|aux| test_dir.join(native_path(&aux.path)))
+            .chain([test.clone()])
+            .filter_map(|path| remote::modified(&path).ok())
+            .max();
+        // The times of a remote only have seconds.
+        let slack = Duration::from_secs(2);
+        if edited.is_some_and(|edited| edited > written + slack) {
+            Some("the test was edited after this output was written")
+        } else if run_started.is_some_and(|started| written + slack < started) {
+            Some("this output was written before the run")
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) enum CachedData {
+    #[default]
+    Unloaded,
+    Missing,
+    Present(Box<TestData>),
+}
+
+/// The default of `--build-dir`, where bootstrap puts the test outputs.
+const DEFAULT_BUILD_DIR: &str = "build/{triple}/test/{suite}";
+
+/// The default of `--stage`, the one `./x test` builds.
+const DEFAULT_STAGE: u32 = 1;
+
+/// What [`suite_build_dir`] expands.
+#[derive(Debug)]
+struct BuildDir {
+    template: String,
+    /// `None` without `--stage`.
+    stage: Option<u32>,
+}
+
+static BUILD_DIR: OnceLock<BuildDir> = OnceLock::new();
+
+/// Sets the layout of the test outputs, once at startup.
+pub fn set_build_dir(template: Option<String>, stage: Option<u32>) {
+    let _ = BUILD_DIR.set(BuildDir {
+        template: template.unwrap_or_else(|| DEFAULT_BUILD_DIR.to_owned()),
+        stage,
+    });
+}
+
+/// Where compiletest puts the outputs of a test suite, `foo/bar/bar.stderr` for
+/// `tests/ui/foo/bar.rs` in the `ui` suite.
+pub fn suite_build_dir(rust_path: &Path, suite: &str) -> PathBuf {
+    let (template, stage) = BUILD_DIR
+        .get()
+        .map_or((DEFAULT_BUILD_DIR, DEFAULT_STAGE), |dir| {
+            (dir.template.as_str(), dir.stage.unwrap_or(DEFAULT_STAGE))
+        });
+    let mut dir = template
+        .replace("{suite}", suite)
+        .replace("{stage}", &stage.to_string());
+    if dir.contains("{triple}") {
+        dir = dir.replace("{triple}", host_triple(rust_path));
+    }
+    // Absolute paths replace the checkout, for build directories outside of it.
+    rust_path.join(dir)
+}
+
+/// The rustcs that may have run the tests, most likely first: the one of `--stage` in the build
+/// directory of the host, or without it the stage 1 compiler and then the stage 2 one.
+///
+/// The build directory of the host is where `--build-dir` has the triple, like `build/{triple}`
+/// in `build/{triple}/test/{suite}`, or else two levels above the suite.
+pub(crate) fn compilers(rust_path: &Path) -> Vec<PathBuf> {
+    let (template, stage) = BUILD_DIR.get().map_or((DEFAULT_BUILD_DIR, None), |dir| {
+        (dir.template.as_str(), dir.stage)
+    });
+    let components: Vec<_> = Path::new(template).components().collect();
+    let host_dir: PathBuf = match components
+        .iter()
+        .position(|c| c.as_os_str().to_string_lossy().contains("{triple}"))
+    {
+        Some(i) => components[..=i].iter().collect(),
+        None => components[..components.len().saturating_sub(2)]
+            .iter()
+            .collect(),
+    };
+    let host_dir = host_dir
+        .to_string_lossy()
+        .replace("{triple}", host_triple(rust_path))
+        .replace("{stage}", &stage.unwrap_or(DEFAULT_STAGE).to_string());
+    let stages = match stage {
+        Some(stage) => vec![stage],
+        None => vec![1, 2],
+    };
+    stages
+        .into_iter()
+        .map(|stage| {
+            rust_path
+                .join(&host_dir)
+                .join(format!("stage{stage}"))
+                .join("bin")
+                .join("rustc")
+        })
+        .collect()
+}
+
+/// The target triple of the host, which bootstrap names the build directory after.
+fn host_triple(rust_path: &Path) -> &'static str {
+    static HOST: OnceLock<String> = OnceLock::new();
+    HOST.get_or_init(|| {
+        // Bootstrap links `build/host` to the directory of the host, where symlinks work.
+        let linked = remote::read_link(&rust_path.join("build").join("host"))
+            .ok()
+            .and_then(|target| Some(target.file_name()?.to_str()?.to_owned()));
+        let host = linked
+            .or_else(|| {
+                let output = remote::command(rust_path, "rustc", ["-vV"]).output().ok()?;
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .find_map(|line| line.strip_prefix("host: "))
+                    .map(str::to_owned)
+            })
+            .unwrap_or_else(|| {
+                let (arch, os) = (std::env::consts::ARCH, std::env::consts::OS);
+                match os {
+                    "macos" => format!("{arch}-apple-darwin"),
+                    "windows" => format!("{arch}-pc-windows-msvc"),
+                    _ => format!("{arch}-unknown-{os}-gnu"),
+                }
+            });
+        tracing::info!(host, "host triple");
+        host
+    })
+}
+
+/// The expected and actual outputs as text, or as hexdumps with the actual bytes if either isn't
+/// text, as a diff of mangled text would be useless.
+fn decode(expected: Option<Vec<u8>>, actual: Option<Vec<u8>>) -> (String, String, Option<Vec<u8>>) {
+    let (expected, actual) = (expected.unwrap_or_default(), actual.unwrap_or_default());
+    let is_text = |bytes: &[u8]| !bytes.contains(&0) && std::str::from_utf8(bytes).is_ok();
+    if is_text(&expected) && is_text(&actual) {
+        let text = |bytes| String::from_utf8(bytes).expect("checked above");
+        return (text(expected), text(actual), None);
+    }
+    (hexdump(&expected), hexdump(&actual), Some(actual))
+}
+
+/// Like `hexdump -C`: the offset, 16 bytes in hex and the printable ones.
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x} ", i * 16);
+        for j in 0..16 {
+            if j % 8 == 0 {
+                out.push(' ');
+            }
+            match chunk.get(j) {
+                Some(byte) => {
+                    let _ = write!(out, "{byte:02x} ");
+                }
+                None => out.push_str("   "),
+            }
+        }
+        let printable: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        let _ = writeln!(out, " |{printable}|");
+    }
+    out
+}
+
+/// A path from a test name or a directive, which always use `/`, with the separators of the host.
+pub fn native_path(path: &str) -> PathBuf {
+    path.split('/').collect()
+}
+
+/// Where the outputs of a stream were looked for, to debug the paths when one is missing.
+#[derive(Debug, Clone)]
+pub struct Resolved {
+    pub stream: Stream,
+    pub expected_path: PathBuf,
+    pub expected_exists: bool,
+    /// `None` when the actual outputs come from an artifacts archive.
+    pub actual_path: Option<PathBuf>,
+    pub actual_exists: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CachedStreams {
+    pub(crate) stderr: CachedData,
+    pub(crate) stdout: CachedData,
+    pub(crate) resolved: Vec<Resolved>,
+}
+
+impl CachedStreams {
+    /// Reads the test at `path_str` and its expected and actual outputs, a missing file being a
+    /// missing stream. Fails for the tests outside of `tests/`, whose outputs aren't in the build
+    /// directory of a suite.
+    #[tracing::instrument(skip(rust_path))]
+    pub fn load(rust_path: &Path, path_str: &str) -> io::Result<Self> {
+        timings::time(Phase::Load, path_str, || Self::read(rust_path, path_str))
+    }
+
+    /// Like [`Self::load`], with both streams missing for the tests it fails for.
+    pub fn load_or_missing(rust_path: &Path, path_str: &str) -> Self {
+        Self::load(rust_path, path_str).unwrap_or_else(|e| {
+            tracing::warn!(path = path_str, error = %e, "couldn't load the test");
+            Self {
+                stderr: CachedData::Missing,
+                stdout: CachedData::Missing,
+                resolved: vec![],
+            }
+        })
+    }
+
+    fn read(rust_path: &Path, path_str: &str) -> io::Result<Self> {
+        let mut streams = Self::default();
+        let path = native_path(path_str);
+        let test_code_path = rust_path.join(&path);
+        let expected_stderr_path = test_code_path.with_extension("stderr");
+        let expected_stdout_path = test_code_path.with_extension("stdout");
+        let mut target_path = path
+            .strip_prefix("tests")
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{path_str} isn't under tests/"),
+                )
+            })?
+            .components();
+        let suite = target_path
+            .next()
+            .and_then(|suite| suite.as_os_str().to_str())
+            .unwrap_or_default();
+        let actual_path = suite_build_dir(rust_path, suite)
+            .join(target_path.as_path())
+            .with_extension("")
+            .join(path.file_stem().unwrap());
+        let actual_stderr_path = actual_path.with_extension("stderr");
+        let actual_stdout_path = actual_path.with_extension("stdout");
+
+        // Most of the outputs usually don't exist, but when a stream is unexpectedly missing the
+        // paths that were tried are the first thing to look at.
+        let read_bytes = |path: &Path| match remote::read(path) {
+            Ok(bytes) => {
+                tracing::debug!(path = %path.display(), "read");
+                Some(bytes)
+            }
+            Err(e) => {
+                tracing::debug!(path = %path.display(), error = %e, "couldn't read");
+                None
+            }
+        };
+        let read = |path: &Path| String::from_utf8(read_bytes(path)?).ok();
+
+        let Some(test_code) = read(&test_code_path) else {
+            tracing::warn!(path = %test_code_path.display(), "the test doesn't exist");
+            // TODO: Handle this
+            streams.stderr = CachedData::Missing;
+            streams.stdout = CachedData::Missing;
+            return Ok(streams);
+        };
+        let test_dir = test_code_path.parent().unwrap_or(rust_path);
+        let aux: Vec<_> = directives::aux_files(&directives::parse(&test_code))
+            .into_iter()
+            .map(|file| {
+                let path = format!("auxiliary/{file}");
+                let code = remote::read_to_string(&test_dir.join(native_path(&path)))
+                    .unwrap_or_else(|e| format!("Couldn't read {path}: {e}"));
+                AuxFile { path, code }
+            })
+            .collect();
+        let rustc_args = rustc::command_line(rust_path, path_str, &test_code);
+        let expected_stderr = read_bytes(&expected_stderr_path);
+        let expected_stdout = read_bytes(&expected_stdout_path);
+        let (actual_stderr, actual_stdout) = match artifacts::get() {
+            Some(artifacts) => (
+                artifacts.actual(path_str, "stderr"),
+                artifacts.actual(path_str, "stdout"),
+            ),
+            None => (
+                read_bytes(&actual_stderr_path),
+                read_bytes(&actual_stdout_path),
+            ),
+        };
+        streams.resolved = [
+            (Stream::Stderr, &expected_stderr_path, &expected_stderr),
+            (Stream::Stdout, &expected_stdout_path, &expected_stdout),
+        ]
+        .into_iter()
+        .zip([
+            (&actual_stderr_path, &actual_stderr),
+            (&actual_stdout_path, &actual_stdout),
+        ])
+        .map(
+            |((stream, expected_path, expected), (actual_path, actual))| Resolved {
+                stream,
+                expected_path: expected_path.clone(),
+                expected_exists: expected.is_some(),
+                actual_path: artifacts::get().is_none().then(|| actual_path.clone()),
+                actual_exists: actual.is_some(),
+            },
+        )
+        .collect();
+
+        // Where a stream came from, if it came from a file.
+        let found_at = |path: PathBuf, actual: &Option<Vec<u8>>| {
+            (artifacts::get().is_none() && actual.is_some()).then_some(path)
+        };
+        if expected_stderr.is_some() || actual_stderr.is_some() && expected_stderr != actual_stderr
+        {
+            let actual_path = found_at(actual_stderr_path, &actual_stderr);
+            let (expect, actual, raw_actual) = decode(expected_stderr, actual_stderr);
+            let stream = TestData {
+                test_code: test_code.clone(),
+                actual,
+                expect,
+                stream: Stream::Stderr,
+                test_name: path_str.to_owned(),
+                rustc_args: rustc_args.clone(),
+                expected_path: expected_stderr_path,
+                actual_path,
+                aux: aux.clone(),
+                raw_actual,
+                // TODO: Where do I get this info
+                // number_of_errs: 1,
+            };
+            streams.stderr = CachedData::Present(Box::new(stream));
+        } else {
+            streams.stderr = CachedData::Missing;
+        }
+
+        if expected_stdout.is_some() || actual_stdout.is_some() && expected_stdout != actual_stdout
+        {
+            let actual_path = found_at(actual_stdout_path, &actual_stdout);
+            let (expect, actual, raw_actual) = decode(expected_stdout, actual_stdout);
+            let stream = TestData {
+                test_code: test_code.clone(),
+                actual,
+                expect,
+                stream: Stream::Stdout,
+                test_name: path_str.to_owned(),
+                rustc_args,
+                expected_path: expected_stdout_path,
+                actual_path,
+                aux,
+                raw_actual,
+                // TODO: Where do I get this info
+                // number_of_outs: 1,
+            };
+            streams.stdout = CachedData::Present(Box::new(stream));
+        } else {
+            streams.stdout = CachedData::Missing;
+            // if matches!(streams.stderr, CachedData::Missing) {
+            //     unreachable!("what");
+            // }
+        }
+        Ok(streams)
+    }
+
+    /// Overwrites the expected outputs with the actual ones.
+    pub fn bless(&self) -> io::Result<()> {
+        for data in [&self.stderr, &self.stdout] {
+            if let CachedData::Present(data) = data {
+                remote::write(&data.expected_path, data.actual_bytes())?;
+                tracing::info!(path = %data.expected_path.display(), "blessed");
+            }
+        }
+        Ok(())
+    }
+
+    /// The streams that have an expected or actual output.
+    pub fn present(&self) -> Vec<&TestData> {
+        [&self.stderr, &self.stdout]
+            .into_iter()
+            .filter_map(|data| match data {
+                CachedData::Present(data) => Some(&**data),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// `stream`, if it differs.
+    pub fn get(&self, stream: Stream) -> Option<&TestData> {
+        let data = match stream {
+            Stream::Stderr => &self.stderr,
+            Stream::Stdout => &self.stdout,
+        };
+        match data {
+            CachedData::Present(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// The expected outputs that [`Self::bless`] writes.
+    pub fn expected_paths(&self) -> Vec<&Path> {
+        [&self.stderr, &self.stdout]
+            .into_iter()
+            .filter_map(|data| match data {
+                CachedData::Present(data) => Some(data.expected_path.as_path()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Number of hunks, and of lines added or removed, over all the streams, not counting moved
+    /// diagnostics.
+    pub fn diff_size(&self) -> (usize, usize) {
+        self.present()
+            .iter()
+            .map(|data| diff_size(&data.expect, &data.actual))
+            .fold((0, 0), |(hunks, lines), (h, l)| (hunks + h, lines + l))
+    }
+
+    /// How similar the expected and actual outputs are over all the streams, from 0 to 1.
+    pub fn similarity(&self) -> Option<f32> {
+        let present = self.present();
+        if present.is_empty() {
+            return None;
+        }
+        let (same, lines) = present
+            .iter()
+            .map(|data| similarity(&data.expect, &data.actual))
+            .fold((0.0, 0), |(same, lines), (ratio, n)| {
+                (same + ratio * n as f32, lines + n)
+            });
+        Some(if lines == 0 { 1.0 } else { same / lines as f32 })
+    }
+}
@@ -0,0 +1,90 @@
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::Stylize,
+    text::Line,
+    widgets::{Block, Clear, List, ListState, Paragraph},
+    Frame,
+};
+
+/// Fuzzy finder over the failing tests.
+#[derive(Debug, Clone, Default)]
+pub struct Picker {
+    pub query: String,
+    /// Index into the filtered matches.
+    pub selected: usize,
+}
+
+impl Picker {
+    /// Indices of the `paths` matching the query, best match first.
+    pub fn matches(&self, paths: &[&str]) -> Vec<usize> {
+        let mut scored: Vec<_> = paths
+            .iter()
+            .enumerate()
+            .filter_map(|(i, path)| Some((fuzzy_score(&self.query, path)?, i)))
+            .collect();
+        // Stable, so equally good matches keep the test order.
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        scored.into_iter().map(|(_, i)| i).collect()
+    }
+
+    pub fn select_next(&mut self, len: usize) {
+        self.selected = (self.selected + 1).min(len.saturating_sub(1));
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect, paths: &[&str], current: usize) {
+        let matches = self.matches(paths);
+        let [input, list] =
+            Layout::vertical([Constraint::Length(3), Constraint::Fill(1)]).areas(area);
+
+        frame.render_widget(Clear, area);
+        let title = Line::from(format!(" find test ({}/{}) ", matches.len(), paths.len())).bold();
+        frame.render_widget(
+            Paragraph::new(format!("> {}", self.query)).block(Block::bordered().title_top(title)),
+            input,
+        );
+
+        let items = matches.iter().map(|&i| {
+            let line = Line::from(format!("{:>4} {}", i + 1, paths[i]));
+            if i == current {
+                line.bold()
+            } else {
+                line
+            }
+        });
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered())
+                .highlight_symbol("> ")
+                .highlight_style(ratatui::style::Modifier::REVERSED),
+            list,
+            &mut state,
+        );
+    }
+}
+
+/// Subsequence match of `query` in `candidate`, rewarding consecutive characters and matches
+/// at the start of path components. `None` if it doesn't match at all.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let mut score = 0;
+    let mut chars = candidate.char_indices();
+    let mut prev_match: Option<usize> = None;
+    for q in query.chars() {
+        let q = q.to_ascii_lowercase();
+        let (i, _) = chars.by_ref().find(|(_, c)| c.to_ascii_lowercase() == q)?;
+        score += 1;
+        if prev_match.is_some_and(|p| p + 1 == i) {
+            score += 5;
+        }
+        if i == 0 || matches!(candidate.as_bytes()[i - 1], b'/' | b'-' | b'_' | b'.') {
+            score += 3;
+        }
+        prev_match = Some(i);
+    }
+    // Prefer shorter paths when everything else is equal.
+    Some(score * 1000 - candidate.len() as i64)
+}
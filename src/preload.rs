@@ -0,0 +1,106 @@
+//! `--preload`: loading and diffing all the tests up front on a thread pool, so that moving
+//! between them doesn't wait for the disk or the remote.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use rayon::prelude::*;
+
+use crate::outputs::CachedStreams;
+
+/// A test as loaded by a worker.
+struct Loaded {
+    path: &'static str,
+    streams: CachedStreams,
+    similarity: Option<f32>,
+}
+
+#[derive(Debug)]
+pub struct Preload {
+    results: Receiver<Loaded>,
+    total: usize,
+    done: usize,
+    /// Loaded tests that haven't been shown yet.
+    streams: HashMap<&'static str, CachedStreams>,
+    /// Tests that were shown, whose outputs are read again from then on.
+    shown: HashSet<&'static str>,
+    /// Whether the outputs are kept, not only their similarities.
+    keep_streams: bool,
+}
+
+impl Preload {
+    /// Starts loading `paths` in the background.
+    pub fn start(rust_path: PathBuf, paths: Vec<&'static str>) -> Self {
+        Self::spawn(rust_path, paths, true)
+    }
+
+    /// Starts computing the similarities of `paths` in the background, for the test tree, without
+    /// keeping their outputs.
+    pub fn similarities(rust_path: PathBuf, paths: Vec<&'static str>) -> Self {
+        Self::spawn(rust_path, paths, false)
+    }
+
+    fn spawn(rust_path: PathBuf, paths: Vec<&'static str>, keep_streams: bool) -> Self {
+        let (sender, results) = mpsc::channel();
+        let total = paths.len();
+        thread::spawn(move || {
+            paths.into_par_iter().for_each_with(sender, |sender, path| {
+                let streams = CachedStreams::load_or_missing(&rust_path, path);
+                let similarity = streams.similarity();
+                // The receiver is gone when the review ended before the loading did.
+                let _ = sender.send(Loaded {
+                    path,
+                    streams,
+                    similarity,
+                });
+            });
+        });
+        tracing::info!(total, keep_streams, "preloading");
+        Self {
+            results,
+            total,
+            done: 0,
+            streams: HashMap::new(),
+            shown: HashSet::new(),
+            keep_streams,
+        }
+    }
+
+    /// Collects the tests loaded since the last call, with their similarities.
+    pub fn poll(&mut self) -> Vec<(&'static str, f32)> {
+        let mut similarities = vec![];
+        let was_loading = self.progress().is_some();
+        for loaded in self.results.try_iter() {
+            self.done += 1;
+            if self.shown.contains(loaded.path) {
+                continue;
+            }
+            if let Some(similarity) = loaded.similarity {
+                similarities.push((loaded.path, similarity));
+            }
+            if self.keep_streams {
+                self.streams.insert(loaded.path, loaded.streams);
+            }
+        }
+        if was_loading && self.progress().is_none() {
+            tracing::info!(total = self.total, "preloaded");
+        }
+        similarities
+    }
+
+    /// The loaded outputs of `path`, which are only good until they're shown, as they may be
+    /// blessed or rewritten afterwards.
+    pub fn take(&mut self, path: &'static str) -> Option<CachedStreams> {
+        self.shown.insert(path);
+        self.streams.remove(path)
+    }
+
+    /// How many tests are loaded and out of how many, while it's still loading.
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        (self.done < self.total).then_some((self.done, self.total))
+    }
+}
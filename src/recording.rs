@@ -0,0 +1,139 @@
+//! `--record` and `--replay`: the keys pressed in a review with when they were pressed, to
+//! reproduce bugs and to show how a review goes.
+//!
+//! A recording is a line per key, the milliseconds since the start and the key, like `1520 j` or
+//! `2210 C-d`, after a `#` line with the input it was recorded on.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{LineWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+const NAMED: &[(KeyCode, &str)] = &[
+    (KeyCode::Enter, "Enter"),
+    (KeyCode::Esc, "Esc"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::BackTab, "BackTab"),
+    (KeyCode::Backspace, "Backspace"),
+    (KeyCode::Up, "Up"),
+    (KeyCode::Down, "Down"),
+    (KeyCode::Left, "Left"),
+    (KeyCode::Right, "Right"),
+    (KeyCode::PageUp, "PageUp"),
+    (KeyCode::PageDown, "PageDown"),
+    (KeyCode::Char(' '), "Space"),
+];
+
+/// `key` as written in a recording, `None` for keys that the review doesn't use.
+fn encode(key: KeyEvent) -> Option<String> {
+    let name = match NAMED.iter().find(|(code, _)| *code == key.code) {
+        Some((_, name)) => (*name).to_owned(),
+        None => match key.code {
+            KeyCode::Char(c) => c.to_string(),
+            _ => return None,
+        },
+    };
+    Some(if key.modifiers.contains(KeyModifiers::CONTROL) {
+        format!("C-{name}")
+    } else {
+        name
+    })
+}
+
+fn decode(key: &str) -> Option<KeyEvent> {
+    let (key, modifiers) = match key.strip_prefix("C-") {
+        Some(key) if !key.is_empty() => (key, KeyModifiers::CONTROL),
+        _ => (key, KeyModifiers::NONE),
+    };
+    let code = match NAMED.iter().find(|(_, name)| *name == key) {
+        Some((code, _)) => *code,
+        None => {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => KeyCode::Char(c),
+                _ => return None,
+            }
+        }
+    };
+    Some(KeyEvent::new(code, modifiers))
+}
+
+#[derive(Debug)]
+pub struct Recorder {
+    file: LineWriter<File>,
+    started: Instant,
+}
+
+impl Recorder {
+    /// Starts recording to `path` the review of `input`.
+    pub fn create(path: &Path, input: &Path) -> Result<Self> {
+        let mut file = LineWriter::new(File::create(path)?);
+        writeln!(file, "# {}", input.display())?;
+        Ok(Self {
+            file,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, key: KeyEvent) {
+        let Some(key) = encode(key) else {
+            return;
+        };
+        let millis = self.started.elapsed().as_millis();
+        if let Err(e) = writeln!(self.file, "{millis} {key}") {
+            tracing::warn!(error = %e, "couldn't record a key");
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Replay {
+    keys: VecDeque<(Duration, KeyEvent)>,
+    started: Instant,
+    /// What the recording was of.
+    pub input: PathBuf,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut input = PathBuf::new();
+        let mut keys = VecDeque::new();
+        for (i, line) in text.lines().enumerate() {
+            if let Some(recorded) = line.strip_prefix("# ") {
+                input = PathBuf::from(recorded);
+                continue;
+            }
+            let key = line
+                .split_once(' ')
+                .and_then(|(millis, key)| Some((millis.parse().ok()?, decode(key)?)))
+                .ok_or_else(|| eyre!("Line {} of {} isn't a key: {line}", i + 1, path.display()))?;
+            keys.push_back((Duration::from_millis(key.0), key.1));
+        }
+        Ok(Self {
+            keys,
+            started: Instant::now(),
+            input,
+        })
+    }
+
+    /// How long until the next key is due, `None` when the replay is over.
+    pub fn wait(&self) -> Option<Duration> {
+        let (at, _) = self.keys.front()?;
+        Some(at.saturating_sub(self.started.elapsed()))
+    }
+
+    /// The next key if it's due.
+    pub fn next_key(&mut self) -> Option<KeyEvent> {
+        if self.wait()? > Duration::ZERO {
+            return None;
+        }
+        self.keys.pop_front().map(|(_, key)| key)
+    }
+}
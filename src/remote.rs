@@ -0,0 +1,196 @@
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::OnceLock,
+    time::{Duration, SystemTime},
+};
+
+/// The `user@host` of `--remote` and the rust checkout on it, set once at startup. Without it
+/// everything is local.
+static REMOTE: OnceLock<(String, PathBuf)> = OnceLock::new();
+
+/// Makes every function here work on the remote of `spec`, like `user@host:/path/to/rust`.
+pub fn set(spec: &str) -> Option<()> {
+    let (host, path) = spec.split_once(':')?;
+    if host.is_empty() || !path.starts_with('/') {
+        return None;
+    }
+    let _ = REMOTE.set((host.to_owned(), PathBuf::from(path)));
+    Some(())
+}
+
+/// The host of `--remote`.
+pub fn host() -> Option<&'static str> {
+    REMOTE.get().map(|(host, _)| host.as_str())
+}
+
+/// The rust checkout on the remote.
+pub fn checkout() -> Option<&'static Path> {
+    REMOTE.get().map(|(_, path)| path.as_path())
+}
+
+/// `ssh` to the remote, sharing one connection between the many commands that run over it.
+fn ssh(host: &str) -> Command {
+    let socket = std::env::temp_dir().join("compiletest-differ-ssh-%C");
+    let mut command = Command::new("ssh");
+    command
+        .args(["-o", "ControlMaster=auto", "-o", "ControlPersist=60", "-o"])
+        .arg(format!("ControlPath={}", socket.display()))
+        .arg(host)
+        .arg("--");
+    command
+}
+
+/// Quotes `word` for the shell, like the one on the remote that gets a command as a single string.
+pub fn quote(word: &OsStr) -> String {
+    format!("'{}'", word.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// `program` with `args`, run in `dir` locally or on the remote.
+pub fn command<S: AsRef<OsStr>>(
+    dir: &Path,
+    program: impl AsRef<OsStr>,
+    args: impl IntoIterator<Item = S>,
+) -> Command {
+    let Some(host) = host() else {
+        let mut command = Command::new(program);
+        command.current_dir(dir).args(args);
+        return command;
+    };
+    let mut script = format!(
+        "cd {} && {}",
+        quote(dir.as_os_str()),
+        quote(program.as_ref())
+    );
+    for arg in args {
+        script.push(' ');
+        script.push_str(&quote(arg.as_ref()));
+    }
+    let mut command = ssh(host);
+    command.arg(script);
+    command
+}
+
+/// Runs a `command` whose output is the answer, like `cat`.
+fn output(mut command: Command) -> io::Result<Vec<u8>> {
+    let output = command.stdin(Stdio::null()).output()?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        let error = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        Err(io::Error::other(error))
+    }
+}
+
+/// Like [`fs::read`], on the remote if there's one.
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let Some(host) = host() else {
+        return fs::read(path);
+    };
+    let mut command = ssh(host);
+    command.arg(format!("cat {}", quote(path.as_os_str())));
+    output(command)
+}
+
+/// Like [`fs::read_to_string`], on the remote if there's one.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    if host().is_none() {
+        return fs::read_to_string(path);
+    }
+    String::from_utf8(read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Like [`fs::write`], on the remote if there's one.
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let Some(host) = host() else {
+        return fs::write(path, contents);
+    };
+    let mut command = ssh(host);
+    command
+        .arg(format!("cat > {}", quote(path.as_os_str())))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let mut child = command.spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(contents.as_ref())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(io::Error::other(format!(
+            "Couldn't write {} on {host}: {error}",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Like [`Path::exists`], on the remote if there's one.
+pub fn exists(path: &Path) -> bool {
+    let Some(host) = host() else {
+        return path.exists();
+    };
+    let mut command = ssh(host);
+    command.arg(format!("test -e {}", quote(path.as_os_str())));
+    output(command).is_ok()
+}
+
+/// Like [`fs::read_link`], on the remote if there's one.
+pub fn read_link(path: &Path) -> io::Result<PathBuf> {
+    let Some(host) = host() else {
+        return fs::read_link(path);
+    };
+    let mut command = ssh(host);
+    command.arg(format!("readlink {}", quote(path.as_os_str())));
+    let target = String::from_utf8_lossy(&output(command)?).trim().to_owned();
+    Ok(PathBuf::from(target))
+}
+
+/// Like [`fs::Metadata::modified`], on the remote if there's one.
+pub fn modified(path: &Path) -> io::Result<SystemTime> {
+    let Some(host) = host() else {
+        return fs::metadata(path)?.modified();
+    };
+    let path = quote(path.as_os_str());
+    let mut command = ssh(host);
+    // GNU's stat, then the BSD one of macOS, then the plain one for its error.
+    command.arg(format!(
+        "stat -c %Y {path} 2>/dev/null || stat -f %m {path} 2>/dev/null || stat {path}"
+    ));
+    let printed = String::from_utf8_lossy(&output(command)?).trim().to_owned();
+    let seconds = printed.parse().map_err(|_| {
+        io::Error::other(format!(
+            "Unsupported stat on {host}, it printed {printed:?} rather than the modification time"
+        ))
+    })?;
+    Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// The files under `dir`, recursively.
+pub fn files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    if let Some(host) = host() {
+        let mut command = ssh(host);
+        command.arg(format!("find {} -type f", quote(dir.as_os_str())));
+        let found = String::from_utf8_lossy(&output(command)?).into_owned();
+        return Ok(found.lines().map(PathBuf::from).collect());
+    }
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
@@ -0,0 +1,582 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    app::{first_changed_location, DiffMode},
+    classify::{is_diagnostic_header, Classification},
+    diff::{feed, line_diff, text_diff, DiffLine, DiffSink, DiffStats, LineTag},
+    outputs::CachedStreams,
+    session::{Session, Status},
+    tree, FailureKind, Stats, Stream,
+};
+
+/// A failed UI test as it goes into a report.
+#[derive(Debug, Clone, Serialize)]
+pub struct TestReport<'a> {
+    pub path: &'a str,
+    pub streams: Vec<StreamReport>,
+    pub failure_kind: Option<&'a FailureKind>,
+    /// From the saved session, if the test was reviewed.
+    pub status: Option<Status>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamReport {
+    pub stream: Stream,
+    /// Relative to the rust checkout.
+    pub expected_path: PathBuf,
+    #[serde(flatten)]
+    pub stats: DiffStats,
+    pub classification: Classification,
+    /// 1-based line of the expected output where the diff starts.
+    pub first_difference: usize,
+    /// Line of the test that the first changed diagnostic points to.
+    pub test_line: Option<usize>,
+    /// Unified diff from the expected to the actual output.
+    pub diff: String,
+    #[serde(skip)]
+    pub expect: String,
+    #[serde(skip)]
+    pub actual: String,
+}
+
+/// Loads the outputs of the tests at `paths` and diffs them.
+pub fn collect<'a>(
+    rust_path: &Path,
+    paths: &[&'a str],
+    failure_kinds: &'a HashMap<&str, FailureKind>,
+    session: Option<&Session>,
+) -> Vec<TestReport<'a>> {
+    paths
+        .iter()
+        .map(|&path| {
+            let file_name = Path::new(path)
+                .file_name()
+                .and_then(|f| f.to_str())
+                .unwrap_or_default();
+            let streams = CachedStreams::load_or_missing(rust_path, path)
+                .present()
+                .into_iter()
+                .map(|data| {
+                    let diff = line_diff(&data.expect, &data.actual);
+                    StreamReport {
+                        stream: data.stream,
+                        expected_path: data
+                            .expected_path
+                            .strip_prefix(rust_path)
+                            .unwrap_or(&data.expected_path)
+                            .to_owned(),
+                        stats: DiffStats::new(&diff),
+                        classification: Classification::new(&data.expect, &data.actual),
+                        first_difference: first_difference(&diff),
+                        test_line: first_changed_location(&diff, file_name),
+                        diff: text_diff(&data.expect, &data.actual)
+                            .unified_diff()
+                            .header("expected", "actual")
+                            .to_string(),
+                        expect: data.expect.clone(),
+                        actual: data.actual.clone(),
+                    }
+                })
+                .collect();
+            TestReport {
+                path,
+                streams,
+                failure_kind: failure_kinds.get(path),
+                status: session.and_then(|s| s.statuses.get(path).copied()),
+            }
+        })
+        .collect()
+}
+
+/// A summary for a GitHub comment, with the diff of each test folded in a `<details>`.
+pub fn markdown(tests: &[TestReport<'_>], stats: Stats, other_failed: &[&str]) -> String {
+    let mut out = String::new();
+    // Writing to a `String` can't fail.
+    let _ = writeln!(
+        out,
+        "**{} failed** ({} UI tests, {} others), {} passed, {} ignored\n",
+        stats.failed,
+        tests.len(),
+        other_failed.len(),
+        stats.passed,
+        stats.ignored
+    );
+    for test in tests {
+        let _ = writeln!(
+            out,
+            "<details><summary><code>{}</code>{}</summary>\n",
+            test.path,
+            test_details(test)
+        );
+        for stream in &test.streams {
+            // Diffs can contain backticks, a longer fence than any run in it keeps it closed.
+            let fence = "`".repeat(longest_backtick_run(&stream.diff).max(2) + 1);
+            let _ = writeln!(out, "{fence}diff\n{}{fence}\n", stream.diff);
+        }
+        let _ = writeln!(out, "</details>\n");
+    }
+    if !other_failed.is_empty() {
+        let _ = writeln!(out, "Other failures:\n");
+        for name in other_failed {
+            let _ = writeln!(out, "- `{name}`");
+        }
+    }
+    out
+}
+
+/// Everything in [`markdown`], for other tools to consume.
+pub fn json(
+    tests: &[TestReport<'_>],
+    stats: Stats,
+    other_failed: &[&str],
+) -> serde_json::Result<String> {
+    #[derive(Serialize)]
+    struct Report<'a> {
+        stats: Stats,
+        tests: &'a [TestReport<'a>],
+        other_failed: &'a [&'a str],
+    }
+    serde_json::to_string_pretty(&Report {
+        stats,
+        tests,
+        other_failed,
+    })
+}
+
+/// What [`list`] prints of every test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ListFormat {
+    /// Only the paths.
+    Paths,
+    /// The path, stream, inserted and deleted lines and classification of every stream.
+    Long,
+}
+
+/// The paths of the tests one per line, for shell pipelines.
+pub fn list(tests: &[TestReport<'_>], format: ListFormat) -> String {
+    let mut out = String::new();
+    for test in tests {
+        match format {
+            ListFormat::Paths => {
+                let _ = writeln!(out, "{}", test.path);
+            }
+            ListFormat::Long => {
+                for stream in &test.streams {
+                    let DiffStats {
+                        inserted, deleted, ..
+                    } = stream.stats;
+                    let _ = writeln!(
+                        out,
+                        "{}\t{}\t+{inserted}\t-{deleted}\t{}",
+                        test.path,
+                        stream.stream.name(),
+                        stream.classification.name()
+                    );
+                }
+            }
+        }
+    }
+    out
+}
+
+/// GitHub Actions workflow commands that annotate the tests, at the first diagnostic that changed.
+pub fn github(tests: &[TestReport<'_>]) -> String {
+    let mut out = String::new();
+    for test in tests {
+        let file = escape_property(test.path);
+        let annotations: Vec<_> = match test.failure_kind {
+            Some(FailureKind::Timeout) => vec![(1, "The test timed out".to_owned())],
+            Some(FailureKind::Panic { message }) => vec![(
+                1,
+                format!(
+                    "compiletest panicked{}",
+                    message.as_deref().map(|m| format!(": {m}")).unwrap_or_default()
+                ),
+            )],
+            None => test
+                .streams
+                .iter()
+                .map(|stream| {
+                    let DiffStats {
+                        inserted, deleted, ..
+                    } = stream.stats;
+                    let message = format!(
+                        "The {} differs from {} (+{inserted}/-{deleted}), starting at its line {}\n\n{}",
+                        stream.stream.name(),
+                        stream.expected_path.display(),
+                        stream.first_difference,
+                        stream.diff
+                    );
+                    (stream.test_line.unwrap_or(1), message)
+                })
+                .collect(),
+        };
+        for (line, message) in annotations {
+            let _ = writeln!(
+                out,
+                "::error file={file},line={line},title=UI test failed::{}",
+                escape_data(&message)
+            );
+        }
+    }
+    out
+}
+
+/// The summary line of the run, with the numbers of failures.
+fn summary(tests: &[TestReport<'_>], stats: Stats, other_failed: &[&str]) -> String {
+    format!(
+        "{} failed ({} UI tests, {} others), {} passed, {} ignored",
+        stats.failed,
+        tests.len(),
+        other_failed.len(),
+        stats.passed,
+        stats.ignored
+    )
+}
+
+/// An overview of the failures: how many there are per directory and per classification, and
+/// the diagnostics that were most often added and removed.
+pub fn statistics(tests: &[TestReport<'_>], stats: Stats, other_failed: &[&str]) -> String {
+    /// The counts, most common first, then alphabetically.
+    fn by_count<K: Ord>(counts: HashMap<K, usize>) -> Vec<(K, usize)> {
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by(|(a, m), (b, n)| n.cmp(m).then(a.cmp(b)));
+        counts
+    }
+    const TOP: usize = 10;
+
+    let mut per_dir = HashMap::new();
+    let mut classifications = HashMap::new();
+    let (mut added, mut removed) = (HashMap::new(), HashMap::new());
+    for test in tests {
+        *per_dir.entry(tree::dir_of(test.path)).or_insert(0) += 1;
+        for stream in &test.streams {
+            *classifications
+                .entry(stream.classification.name())
+                .or_insert(0) += 1;
+            for line in line_diff(&stream.expect, &stream.actual) {
+                let counts = match line.tag {
+                    LineTag::Insert => &mut added,
+                    LineTag::Delete => &mut removed,
+                    _ => continue,
+                };
+                if is_diagnostic_header(line.value) {
+                    *counts.entry(line.value.trim_end().to_owned()).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+    let streams = tests.iter().flat_map(|test| &test.streams);
+    let trivial = streams
+        .clone()
+        .filter(|stream| stream.classification.is_trivial())
+        .count();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{}\n", summary(tests, stats, other_failed));
+    let _ = writeln!(
+        out,
+        "{trivial} trivial diffs (only positions changed), {} substantive\n",
+        streams.count() - trivial
+    );
+    let _ = writeln!(out, "By classification:");
+    for (classification, count) in by_count(classifications) {
+        let _ = writeln!(out, "  {count:5}  {classification}");
+    }
+    let _ = writeln!(out, "\nBy directory:");
+    for (dir, count) in by_count(per_dir) {
+        let _ = writeln!(out, "  {count:5}  {dir}");
+    }
+    for (title, counts) in [("added", added), ("removed", removed)] {
+        let _ = writeln!(out, "\nMost often {title} diagnostics:");
+        let counts = by_count(counts);
+        if counts.is_empty() {
+            let _ = writeln!(out, "  none");
+        }
+        for (message, count) in counts.into_iter().take(TOP) {
+            let _ = writeln!(out, "  {count:5}  {message}");
+        }
+    }
+    out
+}
+
+/// What goes after the path of `test`: the streams with their diff sizes, why it failed if it's
+/// not the output and its status.
+fn test_details(test: &TestReport<'_>) -> String {
+    let mut summary = String::new();
+    for stream in &test.streams {
+        let DiffStats {
+            inserted, deleted, ..
+        } = stream.stats;
+        let _ = write!(summary, " {} +{inserted}/-{deleted}", stream.stream.name());
+    }
+    match test.failure_kind {
+        Some(FailureKind::Timeout) => summary.push_str(" (timed out)"),
+        Some(FailureKind::Panic { .. }) => summary.push_str(" (compiletest panicked)"),
+        None => {}
+    }
+    if let Some(status) = test.status {
+        let _ = write!(summary, " [{}]", status.name());
+    }
+    summary
+}
+
+/// Colored text for a terminal, the [`DiffSink`] of [`text`].
+#[derive(Default)]
+struct Ansi {
+    out: String,
+}
+
+impl Ansi {
+    fn push(&mut self, marker: &str, tag: LineTag, value: &str) {
+        let color = match tag {
+            LineTag::Equal => {
+                let _ = write!(self.out, "{marker}{value}");
+                return;
+            }
+            LineTag::Delete => 31,
+            LineTag::Insert => 32,
+            LineTag::MovedFrom | LineTag::MovedTo => 34,
+        };
+        // Colored up to the newline, so that pagers don't carry the color over.
+        let (value, newline) = match value.strip_suffix('\n') {
+            Some(value) => (value, "\n"),
+            None => (value, ""),
+        };
+        let _ = write!(self.out, "\x1b[{color}m{marker}{value}\x1b[0m{newline}");
+    }
+}
+
+impl DiffSink<'_> for Ansi {
+    fn line(&mut self, line: DiffLine<'_>) {
+        let marker = match line.tag {
+            LineTag::Equal => "  ",
+            LineTag::Delete => "- ",
+            LineTag::Insert => "+ ",
+            LineTag::MovedFrom | LineTag::MovedTo => "~ ",
+        };
+        self.push(marker, line.tag, line.value);
+    }
+
+    fn fragment(&mut self, tag: LineTag, value: &str) {
+        self.push("", tag, value);
+    }
+}
+
+/// The whole diff of every test in color, for reading in a terminal or with `less -R`.
+pub fn text(tests: &[TestReport<'_>], stats: Stats, other_failed: &[&str]) -> String {
+    let mut out = format!("{}\n", summary(tests, stats, other_failed));
+    for test in tests {
+        let _ = writeln!(out, "\n\x1b[1m{}{}\x1b[0m", test.path, test_details(test));
+        for stream in &test.streams {
+            let mut sink = Ansi::default();
+            feed(&stream.expect, &stream.actual, DiffMode::Line, &mut sink);
+            let _ = writeln!(out, "\x1b[2m{}\x1b[0m", stream.expected_path.display());
+            out.push_str(&sink.out);
+        }
+    }
+    for name in other_failed {
+        let _ = writeln!(out, "Also failed: {name}");
+    }
+    out
+}
+
+/// A `<pre>` with the changes in `<del>` and `<ins>`, the [`DiffSink`] of [`html`].
+#[derive(Default)]
+struct Html {
+    out: String,
+}
+
+impl DiffSink<'_> for Html {
+    fn line(&mut self, line: DiffLine<'_>) {
+        self.fragment(line.tag, line.value);
+    }
+
+    fn fragment(&mut self, tag: LineTag, value: &str) {
+        let value = escape_html(value);
+        let _ = match tag {
+            LineTag::Equal => write!(self.out, "{value}"),
+            LineTag::Delete => write!(self.out, "<del>{value}</del>"),
+            LineTag::Insert => write!(self.out, "<ins>{value}</ins>"),
+            LineTag::MovedFrom | LineTag::MovedTo => {
+                write!(self.out, r#"<span class="moved">{value}</span>"#)
+            }
+        };
+    }
+}
+
+/// A standalone page with the diff of each test folded, to share or attach to an issue.
+pub fn html(tests: &[TestReport<'_>], stats: Stats, other_failed: &[&str]) -> String {
+    let mut out = String::from(concat!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>UI test failures</title>\n",
+        "<style>del { background: #fdd; } ins { background: #dfd; text-decoration: none; } ",
+        ".moved { color: #36c; } pre { padding-left: 1em; }</style></head><body>\n"
+    ));
+    let _ = writeln!(out, "<p>{}</p>", summary(tests, stats, other_failed));
+    for test in tests {
+        let _ = writeln!(
+            out,
+            "<details><summary><code>{}</code>{}</summary>",
+            escape_html(test.path),
+            escape_html(&test_details(test))
+        );
+        for stream in &test.streams {
+            let mut sink = Html::default();
+            feed(&stream.expect, &stream.actual, DiffMode::Line, &mut sink);
+            let _ = writeln!(
+                out,
+                "<p>{}</p>\n<pre>{}</pre>",
+                escape_html(&stream.expected_path.display().to_string()),
+                sink.out
+            );
+        }
+        let _ = writeln!(out, "</details>");
+    }
+    if !other_failed.is_empty() {
+        let _ = writeln!(out, "<p>Other failures:</p>\n<ul>");
+        for name in other_failed {
+            let _ = writeln!(out, "<li><code>{}</code></li>", escape_html(name));
+        }
+        let _ = writeln!(out, "</ul>");
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 1-based line of the expected side where `diff` first differs.
+fn first_difference(diff: &[DiffLine<'_>]) -> usize {
+    let equal = diff
+        .iter()
+        .take_while(|line| line.tag == LineTag::Equal)
+        .count();
+    equal + 1
+}
+
+/// Escapes the message of a workflow command.
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a `key=value` parameter of a workflow command.
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The report of a stderr that went from `expect` to `actual`, like [`collect`] makes it.
+    fn stderr_report(expect: &str, actual: &str) -> StreamReport {
+        let diff = line_diff(expect, actual);
+        StreamReport {
+            stream: Stream::Stderr,
+            expected_path: PathBuf::from("tests/ui/macros/format.stderr"),
+            stats: DiffStats::new(&diff),
+            classification: Classification::new(expect, actual),
+            first_difference: first_difference(&diff),
+            test_line: first_changed_location(&diff, "format.rs"),
+            diff: text_diff(expect, actual)
+                .unified_diff()
+                .header("expected", "actual")
+                .to_string(),
+            expect: expect.to_owned(),
+            actual: actual.to_owned(),
+        }
+    }
+
+    fn test_report<'a>(path: &'a str, expect: &str, actual: &str) -> TestReport<'a> {
+        TestReport {
+            path,
+            streams: vec![stderr_report(expect, actual)],
+            failure_kind: None,
+            status: None,
+        }
+    }
+
+    #[test]
+    fn finds_the_longest_run_of_backticks() {
+        assert_eq!(longest_backtick_run(""), 0);
+        assert_eq!(longest_backtick_run("no backticks"), 0);
+        assert_eq!(longest_backtick_run("`a` and ``b``"), 2);
+        assert_eq!(longest_backtick_run("```rust\n`x`\n```"), 3);
+        assert_eq!(longest_backtick_run("ends with ````"), 4);
+    }
+
+    #[test]
+    fn fences_diffs_with_more_backticks_than_they_have() {
+        let expect = "\
+error: expected expression, found `,`
+  --> $DIR/format.rs:2:13
+";
+        let actual = "\
+error: expected expression, found `,`
+  --> $DIR/format.rs:2:14
+   = help: wrap it in a code block: ```rust
+";
+        let tests = [test_report("tests/ui/macros/format.rs", expect, actual)];
+        let out = markdown(&tests, Stats::default(), &[]);
+        let diff = &tests[0].streams[0].diff;
+        assert!(out.contains(&format!("````diff\n{diff}````\n")), "{out}");
+
+        // Without any, the usual fence does.
+        let tests = [test_report(
+            "tests/ui/macros/format.rs",
+            "error: a\n",
+            "error: b\n",
+        )];
+        let out = markdown(&tests, Stats::default(), &[]);
+        let diff = &tests[0].streams[0].diff;
+        assert!(out.contains(&format!("```diff\n{diff}```\n")), "{out}");
+    }
+
+    #[test]
+    fn escapes_workflow_commands() {
+        assert_eq!(
+            escape_data("100% done\r\nnext: a, b"),
+            "100%25 done%0D%0Anext: a, b"
+        );
+        assert_eq!(
+            escape_property("tests/ui/a:b,c%d\n.rs"),
+            "tests/ui/a%3Ab%2Cc%25d%0A.rs"
+        );
+
+        let tests = [test_report(
+            "tests/ui/fmt/50%,a:b.rs",
+            "error: unused 100% of the time\n",
+            "error: unused 50% of the time\n",
+        )];
+        let out = github(&tests);
+        // One command, whose message keeps its `:` and `,`, with the newlines of the diff escaped.
+        assert_eq!(out.lines().count(), 1, "{out}");
+        assert!(
+            out.starts_with("::error file=tests/ui/fmt/50%25%2Ca%3Ab.rs,line=1,title=UI test failed::The stderr differs"),
+            "{out}"
+        );
+        assert!(
+            out.contains(
+                "%0A-error: unused 100%25 of the time%0A+error: unused 50%25 of the time%0A"
+            ),
+            "{out}"
+        );
+    }
+}
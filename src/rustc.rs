@@ -0,0 +1,197 @@
+//! Running the compiler of the checkout on a test outside of compiletest.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Output,
+    sync::OnceLock,
+};
+
+use ansi_to_tui::IntoText;
+use color_eyre::{eyre::eyre, Result};
+use ratatui::text::Text;
+use regex::Regex;
+
+use crate::{
+    directives,
+    outputs::{compilers, native_path},
+    remote,
+};
+
+/// The rustc that `./x test ui` runs the tests with, in the build directory and of the stage
+/// given by `--build-dir` and `--stage`, see [`compilers`]. Looked up once.
+pub fn compiler(rust_path: &Path) -> Option<PathBuf> {
+    static COMPILER: OnceLock<Option<PathBuf>> = OnceLock::new();
+    COMPILER
+        .get_or_init(|| {
+            compilers(rust_path)
+                .into_iter()
+                .find(|rustc| remote::exists(rustc))
+        })
+        .clone()
+}
+
+/// The flags the directives of `test_code` pass to rustc: the edition and `compile-flags`.
+pub fn directive_args(test_code: &str) -> Vec<String> {
+    let mut args = vec![];
+    for directive in directives::parse(test_code) {
+        match (directive.revision, directive.name, directive.value) {
+            (None, "edition", Some(edition)) => args.push(format!("--edition={edition}")),
+            (None, "compile-flags", Some(flags)) => {
+                args.extend(flags.split_whitespace().map(str::to_owned));
+            }
+            _ => {}
+        }
+    }
+    args
+}
+
+/// Compiles the test at `test_name` and returns what rustc prints, with its colors and the real
+/// paths and line numbers, as a user would see it. Auxiliary crates aren't built.
+pub fn colored_output(rust_path: &Path, test_name: &str, test_code: &str) -> Result<Text<'static>> {
+    let mut args = common_args(test_name, test_code);
+    args.push("--color=always".to_owned());
+    let output = run(rust_path, &args)?;
+    let mut printed = output.stderr;
+    printed.extend(output.stdout);
+    if printed.is_empty() {
+        return Ok(Text::from(format!(
+            "rustc exited with {} and printed nothing",
+            output.status
+        )));
+    }
+    Ok(printed.into_text()?)
+}
+
+/// Compiles the test at `test_name` like compiletest would and returns its stderr normalized the
+/// same way, to stand in for the actual output of `./x test` without its overhead. Auxiliary
+/// crates and revisions aren't supported.
+pub fn rerun(rust_path: &Path, test_name: &str, test_code: &str) -> Result<String> {
+    let output = run(rust_path, &ui_test_args(test_name, test_code))?;
+    let mut stderr =
+        String::from_utf8_lossy(&output.stderr).replace("/rustc/FAKE_PREFIX", "$SRC_DIR");
+    for directive in directives::parse(test_code) {
+        if directive.revision.is_some() || directive.name != "normalize-stderr-test" {
+            continue;
+        }
+        let Some((from, to)) = directive.value.and_then(normalize_rule) else {
+            tracing::warn!(line = directive.line, "couldn't parse a normalize rule");
+            continue;
+        };
+        stderr = Regex::new(from)?
+            .replace_all(&stderr, to.replace("\\n", "\n"))
+            .into_owned();
+    }
+    Ok(stderr)
+}
+
+/// What `rustc --explain` says about the error `code`.
+pub fn explain(rust_path: &Path, code: &str) -> Result<String> {
+    let output = run(rust_path, &["--explain".to_owned(), code.to_owned()])?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `"from" -> "to"`.
+fn normalize_rule(rule: &str) -> Option<(&str, &str)> {
+    let (from, to) = rule.strip_prefix('"')?.split_once("\" -> \"")?;
+    Some((from, to.strip_suffix('"')?))
+}
+
+/// The command of [`rerun`] for the `RustcArgs` view, as a shell command line run from the
+/// checkout. It's rebuilt from the directives of the test and the flags compiletest passes to
+/// every UI test, so it works with any log but may miss the details of a particular compiletest.
+pub fn command_line(rust_path: &Path, test_name: &str, test_code: &str) -> String {
+    let rustc = compiler(rust_path)
+        .map(|rustc| {
+            rustc
+                .strip_prefix(rust_path)
+                .map(Path::to_path_buf)
+                .unwrap_or(rustc)
+        })
+        .unwrap_or_else(|| PathBuf::from("rustc"));
+    let mut line = quote(&rustc.display().to_string());
+    for arg in ui_test_args(test_name, test_code) {
+        line.push(' ');
+        line.push_str(&quote(&arg));
+    }
+    line
+}
+
+/// `word` as is if the shell would read it as is, in single quotes otherwise.
+fn quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_owned()
+    } else {
+        remote::quote(word.as_ref())
+    }
+}
+
+/// The arguments compiletest passes to rustc for the test, with its output normalized.
+fn ui_test_args(test_name: &str, test_code: &str) -> Vec<String> {
+    let test_dir = native_path(test_name)
+        .parent()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default();
+    let mut args = common_args(test_name, test_code);
+    args.extend(
+        [
+            "-Zui-testing",
+            "-Zdeduplicate-diagnostics=no",
+            "-Zwrite-long-types-to-disk=no",
+            "-Zsimulate-remapped-rust-src-base=/rustc/FAKE_PREFIX",
+            "-Ztranslate-remapped-path-to-local-path=no",
+            "-Aunused",
+            "-Ainternal_features",
+            "--color=never",
+        ]
+        .map(str::to_owned),
+    );
+    args.push(format!("--remap-path-prefix={test_dir}=$DIR"));
+    args
+}
+
+/// The arguments of every run: the test, where what it builds goes, and its directives.
+fn common_args(test_name: &str, test_code: &str) -> Vec<String> {
+    let out_dir = std::env::temp_dir()
+        .join("compiletest-differ")
+        .join("rustc");
+    let mut args = vec![
+        native_path(test_name).display().to_string(),
+        "--out-dir".to_owned(),
+        out_dir.display().to_string(),
+    ];
+    // Like compiletest, only tests that have to build go past the analysis.
+    let builds = directives::parse(test_code)
+        .iter()
+        .any(|d| d.name.starts_with("build-") || d.name.starts_with("run-"));
+    if !builds {
+        args.push("--emit=metadata".to_owned());
+    }
+    args.extend(directive_args(test_code));
+    args
+}
+
+fn run(rust_path: &Path, args: &[String]) -> Result<Output> {
+    let rustc = compiler(rust_path).ok_or_else(|| {
+        let tried: Vec<_> = compilers(rust_path)
+            .iter()
+            .map(|rustc| rustc.display().to_string())
+            .collect();
+        eyre!("No rustc at {}", tried.join(" or "))
+    })?;
+    tracing::info!(rustc = %rustc.display(), ?args, "running rustc");
+    Ok(remote::command(rust_path, &rustc, args).output()?)
+}
+
+/// What [`colored_output`] printed for a test, kept until the test changes.
+#[derive(Debug, Clone)]
+pub struct ColoredOutput {
+    pub test: usize,
+    pub text: Text<'static>,
+}
@@ -0,0 +1,51 @@
+use std::{io, path::Path};
+
+use crate::{
+    outputs::{suite_build_dir, CachedStreams},
+    remote,
+};
+
+/// Paths of the UI tests whose actual output in the build directory differs from the expected
+/// one, found by walking the build directory instead of reading the output of a run.
+pub fn scan(rust_path: &Path) -> io::Result<Vec<String>> {
+    let build_dir = suite_build_dir(rust_path, "ui");
+    let mut tests = vec![];
+    for path in remote::files(&build_dir)? {
+        // The outputs of `tests/ui/foo/bar.rs` are in `foo/bar/bar.stderr`.
+        let (Some(stem), Some(test_dir)) = (path.file_stem(), path.parent()) else {
+            continue;
+        };
+        let is_output = matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("stderr" | "stdout")
+        );
+        if !is_output || test_dir.file_name() != Some(stem) {
+            continue;
+        }
+        let source = test_dir.with_extension("rs");
+        let Ok(relative) = source.strip_prefix(&build_dir) else {
+            continue;
+        };
+        // Named like in the output of a run, with `/` on every host.
+        let Some(components) = relative
+            .iter()
+            .map(|c| c.to_str())
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+        let test = format!("tests/ui/{}", components.join("/"));
+        if tests.contains(&test) || !remote::exists(&rust_path.join(&test)) {
+            continue;
+        }
+        let differs = CachedStreams::load_or_missing(rust_path, &test)
+            .present()
+            .iter()
+            .any(|data| data.expect != data.actual);
+        if differs {
+            tests.push(test);
+        }
+    }
+    tests.sort();
+    Ok(tests)
+}
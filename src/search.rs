@@ -0,0 +1,76 @@
+use ratatui::{
+    style::{Style, Stylize},
+    text::{Line, Span, Text},
+};
+
+use crate::theme::Theme;
+
+#[derive(Debug, Clone, Default)]
+pub struct Search {
+    pub query: String,
+    /// Still typing the query.
+    pub editing: bool,
+    /// Diff scroll position when the search started, incremental matches are searched from here.
+    pub origin: u16,
+}
+
+impl Search {
+    pub fn prompt(&self) -> Line<'_> {
+        let mut line = Line::from(vec!["/".bold(), self.query.as_str().into()]);
+        if self.editing {
+            line.push_span("█".slow_blink());
+        } else {
+            line.push_span(" (n/N: next/previous match, esc: clear)".dim());
+        }
+        line
+    }
+}
+
+/// Rows containing `query`, given the text of every row of one or more panes.
+pub fn match_rows<'a>(panes: impl IntoIterator<Item = Vec<&'a str>>, query: &str) -> Vec<usize> {
+    if query.is_empty() {
+        return vec![];
+    }
+    let mut rows: Vec<usize> = panes
+        .into_iter()
+        .flat_map(|rows| {
+            rows.into_iter()
+                .enumerate()
+                .filter(|(_, row)| row.contains(query))
+                .map(|(i, _)| i)
+        })
+        .collect();
+    rows.sort_unstable();
+    rows.dedup();
+    rows
+}
+
+/// Highlights every occurrence of `query` in `text`, keeping the existing styles.
+pub fn highlight<'a>(text: Text<'a>, query: &str, theme: &Theme) -> Text<'a> {
+    if query.is_empty() {
+        return text;
+    }
+    let highlight = |style: Style| style.patch(theme.highlight);
+    let lines = text.lines.into_iter().map(|line| {
+        let mut spans = vec![];
+        for span in &line.spans {
+            let content = span.content.as_ref();
+            if !content.contains(query) {
+                spans.push(span.clone());
+                continue;
+            }
+            let mut last = 0;
+            for (i, m) in content.match_indices(query) {
+                spans.push(Span::styled(content[last..i].to_owned(), span.style));
+                spans.push(Span::styled(m.to_owned(), highlight(span.style)));
+                last = i + m.len();
+            }
+            spans.push(Span::styled(content[last..].to_owned(), span.style));
+        }
+        Line { spans, ..line }
+    });
+    Text {
+        lines: lines.collect(),
+        ..text
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::remote;
+
+/// The outcome of reviewing a test.
+///
+/// New variants go at the end, sessions are stored by variant index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Blessed,
+    /// Moved past without blessing, or explicitly marked as such.
+    Skipped,
+    /// Looked at and found fine without blessing.
+    Reviewed,
+    /// Needs another look later.
+    Deferred,
+}
+
+impl Status {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Status::Blessed => "blessed",
+            Status::Skipped => "skipped",
+            Status::Reviewed => "reviewed",
+            Status::Deferred => "deferred",
+        }
+    }
+}
+
+/// Which tests make the review exit with an error, so that scripts can tell whether it resolved
+/// everything.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum FailOn {
+    /// Always exit successfully.
+    Never,
+    /// Tests that were neither blessed nor marked as reviewed.
+    #[default]
+    Unreviewed,
+    /// Tests that weren't blessed.
+    Unblessed,
+}
+
+/// The marks of a review as JSON, so that people can split a review and merge their progress.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Marks {
+    statuses: BTreeMap<String, Status>,
+}
+
+/// Review progress over one JSON input, saved so that triage can be resumed later.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// Path of the test being reviewed.
+    pub current: Option<String>,
+    pub statuses: BTreeMap<String, Status>,
+}
+
+impl Session {
+    /// Where the session for the JSON at `input` is stored, keyed by its canonical path (or where it
+    /// is on the remote) and its `contents`, so that the marks don't carry over to a new run
+    /// written to the same file.
+    pub fn path_for(input: &Path, contents: &str) -> Option<PathBuf> {
+        let mut bytes = match remote::host() {
+            Some(host) => format!("{host}:{}", input.display()).into_bytes(),
+            None => input
+                .canonicalize()
+                .ok()?
+                .into_os_string()
+                .into_encoded_bytes(),
+        };
+        bytes.push(0);
+        bytes.extend(contents.as_bytes());
+        let hash = fnv1a(&bytes);
+        Some(
+            dirs::cache_dir()?
+                .join("compiletest-differ")
+                .join(format!("{hash:016x}.session")),
+        )
+    }
+
+    pub fn load(path: &Path) -> Option<Self> {
+        postcard::from_bytes(&fs::read(path).ok()?).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, postcard::to_allocvec(self)?)?;
+        Ok(())
+    }
+
+    /// Writes the marks to `path`, for [`Session::import`].
+    pub fn export(&self, path: &Path) -> Result<()> {
+        let marks = Marks {
+            statuses: self.statuses.clone(),
+        };
+        fs::write(path, serde_json::to_string_pretty(&marks)?)?;
+        Ok(())
+    }
+
+    /// Adds the marks that [`Session::export`] wrote to `path` to the tests without one. Returns
+    /// how many were added and how many tests keep a different mark they had here.
+    pub fn import(&mut self, path: &Path) -> Result<(usize, usize)> {
+        let marks: Marks = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let (mut added, mut kept) = (0, 0);
+        for (test, status) in marks.statuses {
+            match self.statuses.get(&test) {
+                None => {
+                    self.statuses.insert(test, status);
+                    added += 1;
+                }
+                Some(&here) if here != status => kept += 1,
+                Some(_) => {}
+            }
+        }
+        Ok((added, kept))
+    }
+
+    pub fn count(&self, status: Status) -> usize {
+        self.statuses.values().filter(|&&s| s == status).count()
+    }
+
+    /// Like [`Session::count`], but only over `paths`.
+    pub fn count_among(&self, paths: &[&str], status: Status) -> usize {
+        paths
+            .iter()
+            .filter(|p| self.statuses.get(**p) == Some(&status))
+            .count()
+    }
+
+    /// Number of tests among `paths` that `fail_on` considers unresolved.
+    pub fn unresolved(&self, paths: &[&str], fail_on: FailOn) -> usize {
+        let resolved = |status: Option<&Status>| match fail_on {
+            FailOn::Never => true,
+            FailOn::Unreviewed => matches!(status, Some(Status::Blessed | Status::Reviewed)),
+            FailOn::Unblessed => status == Some(&Status::Blessed),
+        };
+        paths
+            .iter()
+            .filter(|p| !resolved(self.statuses.get(**p)))
+            .count()
+    }
+
+    /// Marks the test at `path` with `status`, or clears the mark if it already had it.
+    pub fn toggle(&mut self, path: &str, status: Status) {
+        if self.statuses.get(path) == Some(&status) {
+            self.statuses.remove(path);
+        } else {
+            self.statuses.insert(path.to_owned(), status);
+        }
+    }
+
+    /// Records that the test at `path` was moved past, unless it already has a status.
+    pub fn skip(&mut self, path: &str) {
+        self.statuses
+            .entry(path.to_owned())
+            .or_insert(Status::Skipped);
+    }
+}
+
+/// A hash that's stable across runs and Rust versions, unlike `DefaultHasher`.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
+}
@@ -0,0 +1,37 @@
+/// Order in which the failing tests are reviewed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortOrder {
+    /// The order of the test run.
+    #[default]
+    Run,
+    Path,
+    /// By directory, with the files of a directory before its subdirectories.
+    Directory,
+    /// Smallest diff first: fewest hunks, then fewest changed lines. The tests that failed for
+    /// another reason than their output go last.
+    DiffSize,
+    /// Slowest test first, by the reported `exec_time`.
+    Duration,
+}
+
+impl SortOrder {
+    pub const fn rotate_next(&mut self) {
+        *self = match self {
+            SortOrder::Run => SortOrder::Path,
+            SortOrder::Path => SortOrder::Directory,
+            SortOrder::Directory => SortOrder::DiffSize,
+            SortOrder::DiffSize => SortOrder::Duration,
+            SortOrder::Duration => SortOrder::Run,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            SortOrder::Run => "run",
+            SortOrder::Path => "path",
+            SortOrder::Directory => "directory",
+            SortOrder::DiffSize => "diff size",
+            SortOrder::Duration => "duration",
+        }
+    }
+}
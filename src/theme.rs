@@ -0,0 +1,216 @@
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+
+/// A style as written in the config file, like `(fg: "green", bold: true)`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Paint {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub reversed: bool,
+}
+
+impl Paint {
+    const NONE: Paint = Paint {
+        fg: None,
+        bg: None,
+        bold: false,
+        dim: false,
+        reversed: false,
+    };
+
+    const fn fg(color: Color) -> Self {
+        Paint {
+            fg: Some(color),
+            ..Paint::NONE
+        }
+    }
+
+    const fn on(mut self, color: Color) -> Self {
+        self.bg = Some(color);
+        self
+    }
+
+    const fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    const fn dim(mut self) -> Self {
+        self.dim = true;
+        self
+    }
+}
+
+impl From<Paint> for Style {
+    fn from(paint: Paint) -> Self {
+        let mut style = Style::new();
+        if let Some(fg) = paint.fg {
+            style = style.fg(fg);
+        }
+        if let Some(bg) = paint.bg {
+            style = style.bg(bg);
+        }
+        for (on, modifier) in [
+            (paint.bold, Modifier::BOLD),
+            (paint.dim, Modifier::DIM),
+            (paint.reversed, Modifier::REVERSED),
+        ] {
+            if on {
+                style = style.add_modifier(modifier);
+            }
+        }
+        style
+    }
+}
+
+/// The colors of everything that's colored, missing entries are taken from the default theme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Lines only in the actual output.
+    pub insert: Paint,
+    /// Lines only in the expected output.
+    pub delete: Paint,
+    /// Diagnostics that only changed position.
+    pub moved: Paint,
+    pub border: Paint,
+    /// Border of the pane that has the focus.
+    pub focused_border: Paint,
+    /// Search matches and the code line a diagnostic points to.
+    pub highlight: Paint,
+    /// The hunks on the scrollbar and the lines cut off on the right of a pane.
+    pub marker: Paint,
+    /// The part of the progress bar for the tests reviewed.
+    pub progress: Paint,
+    /// The part of the progress bar for the tests loaded, see `--preload`.
+    pub loading: Paint,
+    /// The rest of the progress bar.
+    pub unfilled: Paint,
+    /// The row of the banner over the diff.
+    pub banner: Paint,
+    /// Banners about timeouts and stale outputs.
+    pub warning: Paint,
+    /// Banners about compiletest panicking.
+    pub error: Paint,
+    /// In the help bar, the key to bless.
+    pub bless_key: Paint,
+    /// The keys to mark the test and to go back to the previous mode.
+    pub mark_key: Paint,
+    /// The keys to move around the tests and the diff.
+    pub move_key: Paint,
+    /// The keys to show other views and the help.
+    pub view_key: Paint,
+    /// The keys to change how the diff is shown.
+    pub mode_key: Paint,
+    /// Prefix the changed lines with `+`/`-` so that they can be told apart without colors.
+    pub markers: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::DEFAULT
+    }
+}
+
+impl Theme {
+    pub const DEFAULT: Theme = Theme {
+        insert: Paint::fg(Color::Green).bold(),
+        delete: Paint::fg(Color::Red),
+        moved: Paint::fg(Color::Blue).dim(),
+        border: Paint::NONE,
+        focused_border: Paint::fg(Color::Cyan),
+        highlight: Paint {
+            reversed: true,
+            ..Paint::NONE
+        },
+        marker: Paint::fg(Color::Yellow),
+        progress: Paint::fg(Color::Green),
+        loading: Paint::fg(Color::Cyan),
+        unfilled: Paint::fg(Color::DarkGray),
+        banner: Paint::NONE.on(Color::DarkGray).bold(),
+        warning: Paint::fg(Color::Black).on(Color::Yellow),
+        error: Paint::fg(Color::White).on(Color::Red),
+        bless_key: Paint::fg(Color::Blue).bold(),
+        mark_key: Paint::fg(Color::Yellow).bold(),
+        move_key: Paint::fg(Color::Cyan).bold(),
+        view_key: Paint::fg(Color::Green).bold(),
+        mode_key: Paint::fg(Color::Red).bold(),
+        markers: false,
+    };
+
+    /// For palettes where red and green text is hard to read: the changes get a background.
+    const HIGH_CONTRAST: Theme = Theme {
+        insert: Paint::fg(Color::Black).on(Color::LightGreen),
+        delete: Paint::fg(Color::White).on(Color::Red),
+        moved: Paint::fg(Color::LightCyan),
+        focused_border: Paint::fg(Color::Yellow).bold(),
+        highlight: Paint::fg(Color::Black).on(Color::Yellow),
+        ..Theme::DEFAULT
+    };
+
+    /// Blue and orange from the Okabe-Ito palette instead of red and green, with markers.
+    pub const COLORBLIND: Theme = Theme {
+        insert: Paint::fg(Color::Rgb(0, 114, 178)).bold(),
+        delete: Paint::fg(Color::Rgb(230, 159, 0)),
+        moved: Paint::fg(Color::Rgb(204, 121, 167)).dim(),
+        markers: true,
+        ..Theme::DEFAULT
+    };
+
+    /// The default for light backgrounds, where bold light green is hard to see.
+    pub const LIGHT: Theme = Theme {
+        insert: Paint::fg(Color::Rgb(0, 120, 0)),
+        delete: Paint::fg(Color::Rgb(175, 0, 0)),
+        moved: Paint::fg(Color::Rgb(0, 70, 170)),
+        focused_border: Paint::fg(Color::Blue),
+        marker: Paint::fg(Color::Rgb(175, 95, 0)),
+        progress: Paint::fg(Color::Rgb(0, 120, 0)),
+        loading: Paint::fg(Color::Blue),
+        unfilled: Paint::fg(Color::Gray),
+        banner: Paint::NONE.on(Color::Gray).bold(),
+        warning: Paint::fg(Color::Black).on(Color::LightYellow),
+        bless_key: Paint::fg(Color::Blue).bold(),
+        mark_key: Paint::fg(Color::Rgb(175, 95, 0)).bold(),
+        move_key: Paint::fg(Color::Rgb(0, 95, 135)).bold(),
+        view_key: Paint::fg(Color::Rgb(0, 120, 0)).bold(),
+        mode_key: Paint::fg(Color::Rgb(175, 0, 0)).bold(),
+        ..Theme::DEFAULT
+    };
+
+    /// The built-in themes by name.
+    pub const BUILT_IN: [(&str, Theme); 4] = [
+        ("default", Theme::DEFAULT),
+        ("light", Theme::LIGHT),
+        ("high-contrast", Theme::HIGH_CONTRAST),
+        ("colorblind", Theme::COLORBLIND),
+    ];
+}
+
+/// The background color of the terminal, which decides the default theme.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Background {
+    /// Guess from `COLORFGBG`, dark if it isn't set.
+    #[default]
+    Auto,
+    Light,
+    Dark,
+}
+
+impl Background {
+    pub fn is_light(self) -> bool {
+        match self {
+            Background::Light => true,
+            Background::Dark => false,
+            // Set by some terminals to `fg;bg` or `fg;default;bg`, with ANSI color indices where 7
+            // and 15 are the whites.
+            Background::Auto => std::env::var("COLORFGBG")
+                .ok()
+                .and_then(|colors| colors.rsplit(';').next()?.parse::<u8>().ok())
+                .is_some_and(|bg| bg == 7 || bg == 15),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! `--timings`: how long reading the run, loading the tests, diffing and drawing took, reported
+//! when the review ends, to find what's slow on large runs.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Reading and decompressing the JSON output.
+    Read,
+    /// Parsing the events of the JSON output.
+    Parse,
+    /// Reading a test and its outputs.
+    Load,
+    /// Diffing the outputs of the test shown, for its stats and hunks.
+    Diff,
+    /// Drawing a frame, with the diff text of the test shown.
+    Draw,
+}
+
+impl Phase {
+    const ALL: [Phase; 5] = [
+        Phase::Read,
+        Phase::Parse,
+        Phase::Load,
+        Phase::Diff,
+        Phase::Draw,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Phase::Read => "read",
+            Phase::Parse => "parse",
+            Phase::Load => "load",
+            Phase::Diff => "diff",
+            Phase::Draw => "draw",
+        }
+    }
+}
+
+/// What was measured, `None` until [`enable`] so that nothing is kept without `--timings`.
+static TIMINGS: Mutex<Option<Vec<(Phase, String, Duration)>>> = Mutex::new(None);
+
+/// How many of the slowest labels of each phase are listed.
+const SLOWEST: usize = 5;
+
+pub fn enable() {
+    *TIMINGS.lock().unwrap() = Some(vec![]);
+}
+
+/// Runs `f`, counting how long it took in `phase` for `label` if timings are enabled.
+pub fn time<T>(phase: Phase, label: &str, f: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = f();
+    let elapsed = started.elapsed();
+    if let Some(timings) = TIMINGS.lock().unwrap().as_mut() {
+        timings.push((phase, label.to_owned(), elapsed));
+    }
+    result
+}
+
+/// The count, total, mean and maximum of each phase, with the slowest, `None` if timings aren't
+/// enabled.
+pub fn report() -> Option<String> {
+    let timings = TIMINGS.lock().unwrap();
+    let timings = timings.as_ref()?;
+    let mut report = String::new();
+    for phase in Phase::ALL {
+        let mut measured: Vec<_> = timings
+            .iter()
+            .filter(|(p, _, _)| *p == phase)
+            .map(|(_, label, elapsed)| (label, *elapsed))
+            .collect();
+        if measured.is_empty() {
+            continue;
+        }
+        measured.sort_by_key(|&(_, elapsed)| std::cmp::Reverse(elapsed));
+        let total: Duration = measured.iter().map(|&(_, elapsed)| elapsed).sum();
+        let _ = writeln!(
+            report,
+            "{:5}  {:>5}x  total {:>10.2?}  mean {:>10.2?}  max {:>10.2?}",
+            phase.name(),
+            measured.len(),
+            total,
+            total / measured.len() as u32,
+            measured[0].1,
+        );
+        // The slowest time of each label, as the same test is diffed and drawn again and again.
+        let mut slowest: Vec<(&String, Duration, usize)> = vec![];
+        let mut index: HashMap<&String, usize> = HashMap::new();
+        for &(label, elapsed) in &measured {
+            match index.get(label) {
+                Some(&i) => slowest[i].2 += 1,
+                None => {
+                    index.insert(label, slowest.len());
+                    slowest.push((label, elapsed, 1));
+                }
+            }
+        }
+        if slowest.len() > 1 {
+            for (label, elapsed, count) in slowest.iter().take(SLOWEST) {
+                let times = if *count > 1 {
+                    format!(" ({count}x)")
+                } else {
+                    String::new()
+                };
+                let _ = writeln!(report, "         {elapsed:>10.2?}  {label}{times}");
+            }
+        }
+    }
+    Some(report)
+}
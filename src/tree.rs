@@ -0,0 +1,152 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use ratatui::{
+    layout::Rect,
+    style::{Modifier, Stylize},
+    text::Line,
+    widgets::{Block, Clear, List, ListState},
+    Frame,
+};
+
+use crate::session::{Session, Status};
+
+/// The failing tests grouped by directory.
+#[derive(Debug, Clone, Default)]
+pub struct Tree {
+    pub collapsed: HashSet<String>,
+    /// Index into the visible rows.
+    pub selected: usize,
+    /// Whether the similarities are still being computed, to show those that aren't as pending.
+    pub loading: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Row<'a> {
+    Dir {
+        dir: &'a str,
+        count: usize,
+    },
+    /// A test, with its index in the paths.
+    Test(usize),
+}
+
+pub fn dir_of(path: &str) -> &str {
+    Path::new(path)
+        .parent()
+        .and_then(|p| p.to_str())
+        .unwrap_or_default()
+}
+
+impl Tree {
+    /// Directories in the order they first appear in `paths`, each followed by its tests unless
+    /// it's collapsed.
+    pub fn rows<'a>(&self, paths: &[&'a str]) -> Vec<Row<'a>> {
+        let mut dirs: Vec<(&str, Vec<usize>)> = vec![];
+        for (i, path) in paths.iter().enumerate() {
+            let dir = dir_of(path);
+            match dirs.iter_mut().find(|(d, _)| *d == dir) {
+                Some((_, tests)) => tests.push(i),
+                None => dirs.push((dir, vec![i])),
+            }
+        }
+
+        let mut rows = vec![];
+        for (dir, tests) in dirs {
+            rows.push(Row::Dir {
+                dir,
+                count: tests.len(),
+            });
+            if !self.collapsed.contains(dir) {
+                rows.extend(tests.into_iter().map(Row::Test));
+            }
+        }
+        rows
+    }
+
+    pub fn select_next(&mut self, len: usize) {
+        self.selected = (self.selected + 1).min(len.saturating_sub(1));
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn toggle(&mut self, dir: &str) {
+        if !self.collapsed.remove(dir) {
+            self.collapsed.insert(dir.to_owned());
+        }
+    }
+
+    /// Selects the row of the test at `index`, expanding its directory if needed.
+    pub fn reveal(&mut self, paths: &[&str], index: usize) {
+        self.collapsed.remove(dir_of(paths[index]));
+        self.selected = self
+            .rows(paths)
+            .iter()
+            .position(|row| matches!(row, Row::Test(i) if *i == index))
+            .unwrap_or_default();
+    }
+
+    pub fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        paths: &[&str],
+        current: usize,
+        session: &Session,
+        similarities: &HashMap<&str, f32>,
+    ) {
+        let items = self.rows(paths).into_iter().map(|row| match row {
+            Row::Dir { dir, count } => {
+                let blessed = paths
+                    .iter()
+                    .filter(|p| dir_of(p) == dir)
+                    .filter(|p| session.statuses.get(**p) == Some(&Status::Blessed))
+                    .count();
+                let arrow = if self.collapsed.contains(dir) {
+                    '▸'
+                } else {
+                    '▾'
+                };
+                Line::from(format!("{arrow} {dir}/ ({blessed}/{count} blessed)")).bold()
+            }
+            Row::Test(i) => {
+                let name = Path::new(paths[i])
+                    .file_name()
+                    .and_then(|f| f.to_str())
+                    .unwrap_or_default();
+                let status = session
+                    .statuses
+                    .get(paths[i])
+                    .map(|s| format!(" [{}]", s.name()))
+                    .unwrap_or_default();
+                let similarity = match similarities.get(paths[i]) {
+                    Some(s) => format!(" {:.0}%", s * 100.0),
+                    None if self.loading => " …".to_owned(),
+                    None => String::new(),
+                };
+                let line = Line::from(format!("    {name}{status}{similarity}"));
+                if i == current {
+                    line.underlined()
+                } else {
+                    line
+                }
+            }
+        });
+
+        let title =
+            Line::from(" tests by directory (enter: open/fold, B: bless directory) ").bold();
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title_top(title))
+                .highlight_style(Modifier::REVERSED),
+            area,
+            &mut state,
+        );
+    }
+}
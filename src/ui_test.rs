@@ -0,0 +1,423 @@
+//! The output of the `ui_test` crate, the harness of Miri, Clippy and many other projects, as an
+//! input instead of the JSON output of libtest.
+//!
+//! `ui_test` prints a line per test, like `tests/fail/foo.rs ... FAILED`, and for each failure
+//! the differences between the expected and actual outputs, but it doesn't write the actual
+//! outputs anywhere. They're rebuilt from the expected files and those diffs, and read from
+//! [`Artifacts`] like the ones of `--artifacts`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use crate::{
+    artifacts::Artifacts,
+    events::{Events, Ignored, Stats},
+    outputs::native_path,
+    remote, Stream,
+};
+
+/// The differences between an expected output and the actual one, as printed by `ui_test`.
+#[derive(Debug, Clone)]
+struct OutputDiff<'a> {
+    test: &'a str,
+    stream: Stream,
+    /// The diff lines, starting with ` `, `-` or `+`, and the markers of skipped lines.
+    lines: Vec<String>,
+}
+
+/// The tests of a run of `ui_test`, and the diffs of the failed ones.
+#[derive(Debug, Clone, Default)]
+pub struct UiTestRun<'a> {
+    pub events: Events<'a>,
+    diffs: Vec<OutputDiff<'a>>,
+}
+
+/// `line` without its colors.
+fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Up to the final letter of the escape sequence.
+            chars.by_ref().find(char::is_ascii_alphabetic);
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// The `part` of `stripped` in the original `line`, which has it uncolored.
+fn borrowed<'a>(line: &'a str, part: &str) -> Option<&'a str> {
+    let start = line.find(part)?;
+    Some(&line[start..start + part.len()])
+}
+
+/// The test and the result of a line like `tests/fail/foo.rs (revision `a`) ... FAILED`.
+fn status(stripped: &str) -> Option<(&str, &str)> {
+    let (test, result) = stripped.split_once(" ... ")?;
+    let test = test.split(" (").next()?.trim();
+    test.ends_with(".rs").then_some((test, result.trim()))
+}
+
+/// Whether `text` is the output of `ui_test` rather than JSON events.
+pub fn is_output(text: &str) -> bool {
+    let lines = || text.lines().map(strip_ansi);
+    !lines().any(|line| line.trim_start().starts_with('{'))
+        && lines().any(|line| status(&line).is_some() || line.starts_with("FAILED TEST: "))
+}
+
+/// Reads the output of `ui_test`. The failed tests that aren't under `tests/` are in
+/// [`Events::other_failed`], as their outputs can't be looked up.
+pub fn parse(text: &str) -> UiTestRun<'_> {
+    let mut failed = vec![];
+    let mut passed = vec![];
+    let mut ignored = vec![];
+    let mut stats = None;
+    let mut diffs = vec![];
+    // The test of the `FAILED TEST` block the lines are in.
+    let mut current = None;
+    let mut diff: Option<OutputDiff> = None;
+
+    for line in text.lines() {
+        let stripped = strip_ansi(line);
+        if let Some(diff) = &mut diff {
+            let is_diff_line = stripped.starts_with([' ', '-', '+']) || stripped.starts_with("...");
+            if is_diff_line && !stripped.is_empty() {
+                diff.lines.push(stripped);
+                continue;
+            }
+        }
+        if let Some(done) = diff.take() {
+            diffs.push(done);
+        }
+
+        if let Some(test) = stripped.strip_prefix("FAILED TEST: ") {
+            let test = test.split(" (").next().unwrap_or(test).trim();
+            current = borrowed(line, test);
+        } else if let Some(output) = stripped.strip_prefix("+++ <") {
+            let stream = match output.strip_suffix(" output>") {
+                Some("stderr") => Stream::Stderr,
+                Some("stdout") => Stream::Stdout,
+                _ => continue,
+            };
+            if let Some(test) = current {
+                diff = Some(OutputDiff {
+                    test,
+                    stream,
+                    lines: vec![],
+                });
+            }
+        } else if let Some(result) = stripped.strip_prefix("test result: ") {
+            stats = Some(parse_stats(result));
+        } else if let Some((test, result)) = status(&stripped) {
+            let Some(test) = borrowed(line, test) else {
+                continue;
+            };
+            if result.starts_with("FAILED") {
+                failed.push(test);
+            } else if let Some(reason) = result.strip_prefix("ignored") {
+                let reason = reason.trim().trim_start_matches('(').trim_end_matches(')');
+                ignored.push(Ignored {
+                    name: test,
+                    reason: (!reason.is_empty()).then(|| reason.to_owned()),
+                });
+            } else if result == "ok" {
+                passed.push(test);
+            }
+        }
+    }
+    diffs.extend(diff);
+
+    // A test with revisions has a line per revision.
+    let mut seen = HashSet::new();
+    failed.retain(|test| seen.insert(*test));
+    passed.retain(|test| seen.insert(*test));
+    let (failed, other_failed): (Vec<_>, Vec<_>) = failed
+        .into_iter()
+        .partition(|test| test.starts_with("tests/"));
+    let passed: Vec<_> = passed
+        .into_iter()
+        .filter(|test| test.starts_with("tests/"))
+        .collect();
+    let stats = stats.unwrap_or(Stats {
+        failed: (failed.len() + other_failed.len()) as u32,
+        passed: passed.len() as u32,
+        ignored: ignored.len() as u32,
+        exec_time: None,
+    });
+
+    tracing::info!(
+        failed = failed.len(),
+        other_failed = other_failed.len(),
+        diffs = diffs.len(),
+        "parsed the ui_test output"
+    );
+    UiTestRun {
+        events: Events {
+            failed,
+            passed,
+            other_failed,
+            ignored,
+            stats,
+            ..Default::default()
+        },
+        diffs,
+    }
+}
+
+/// `1 failed; 10 passed; 2 ignored; 3 filtered out` after the `FAIL.` or `ok.`.
+fn parse_stats(result: &str) -> Stats {
+    let mut stats = Stats::default();
+    let counts = result.split_once(". ").map_or(result, |(_, counts)| counts);
+    for count in counts.split(';') {
+        let Some((n, what)) = count.trim().split_once(' ') else {
+            continue;
+        };
+        let Ok(n) = n.parse() else {
+            continue;
+        };
+        match what {
+            "failed" => stats.failed = n,
+            "passed" => stats.passed = n,
+            "ignored" => stats.ignored = n,
+            _ => {}
+        }
+    }
+    stats
+}
+
+impl UiTestRun<'_> {
+    /// The actual outputs of the failed tests, rebuilt from the expected files in `rust_path` and
+    /// the diffs. A stream without a diff didn't differ, so its actual output is the expected one.
+    pub fn actual_outputs(&self, rust_path: &Path) -> Artifacts {
+        let mut artifacts = Artifacts::default();
+        let mut diffs: HashMap<_, _> = self
+            .diffs
+            .iter()
+            .map(|diff| ((diff.test, diff.stream), diff))
+            .collect();
+        for &test in &self.events.failed {
+            for stream in [Stream::Stderr, Stream::Stdout] {
+                let expected_path = rust_path
+                    .join(native_path(test))
+                    .with_extension(stream.name());
+                let expected = remote::read_to_string(&expected_path).ok();
+                let actual = match diffs.remove(&(test, stream)) {
+                    Some(diff) => {
+                        let actual = apply(expected.as_deref().unwrap_or_default(), &diff.lines);
+                        if actual.is_none() {
+                            tracing::warn!(
+                                test,
+                                ?stream,
+                                "the diff doesn't match the expected file"
+                            );
+                        }
+                        actual
+                    }
+                    None => expected,
+                };
+                if let Some(actual) = actual {
+                    artifacts.insert(test, stream.name(), actual.into_bytes());
+                }
+            }
+        }
+        artifacts
+    }
+}
+
+/// `expected` with the changes of `diff`, `None` if they don't fit. The lines of `diff` that aren't
+/// changes are found in `expected` after the previous one, as the unchanged lines between them
+/// may be skipped.
+fn apply(expected: &str, diff: &[String]) -> Option<String> {
+    // `ui_test` shows the whitespace other than spaces as `░`, mostly tabs.
+    let shown = |line: &str| {
+        line.replace(
+            |c: char| c.is_whitespace() && c != ' ' && c != '\n' && c != '\r',
+            "░",
+        )
+    };
+    let mut expected = expected.lines();
+    let mut actual = vec![];
+    for line in diff {
+        let (marker, text) = line.split_at(1);
+        match marker {
+            "+" => actual.push(text.replace('░', "\t")),
+            " " | "-" => loop {
+                let next = expected.next()?;
+                if shown(next) == text {
+                    if marker == " " {
+                        actual.push(next.to_owned());
+                    }
+                    break;
+                }
+                actual.push(next.to_owned());
+            },
+            _ => {}
+        }
+    }
+    actual.extend(expected.map(str::to_owned));
+    let mut actual = actual.join("\n");
+    if !actual.is_empty() {
+        actual.push('\n');
+    }
+    Some(actual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A run of Miri's tests, trimmed.
+    const OUTPUT: &str = "\
+Running tests/compiletest.rs (target/debug/deps/compiletest-0b7a4b9c1c0c4a5e)
+tests/fail/dangling_pointers/dangling_zst_deref.rs ... FAILED
+tests/fail/data_race/alloc_read_race.rs (revision `stack`) ... FAILED
+tests/fail/data_race/alloc_read_race.rs (revision `tree`) ... FAILED
+tests/pass/vec.rs ... ok
+tests/pass/shims/fs.rs ... ignored (only on windows)
+
+FAILED TEST: tests/fail/dangling_pointers/dangling_zst_deref.rs
+command: \"miri\" \"--error-format=json\" \"tests/fail/dangling_pointers/dangling_zst_deref.rs\"
+
+actual output differed from expected
+Execute `./miri test --bless` to update `tests/fail/dangling_pointers/dangling_zst_deref.stderr` to the actual output
+--- tests/fail/dangling_pointers/dangling_zst_deref.stderr
++++ <stderr output>
+ error: Undefined Behavior: memory access failed: ALLOC has been freed, so this pointer is dangling
+...
+-  = help: this indicates a bug in the program: it performed an invalid operation, and caused Undefined Behavior
++  = help: this indicates a potential bug in the program: it performed an invalid operation, but the Stacked Borrows rules it violated are still experimental
+   = help: see https://doc.rust-lang.org/nightly/reference/behavior-considered-undefined.html for further information
++  = note: BACKTRACE:
+
+full stderr:
+
+FAILED TEST: tests/fail/data_race/alloc_read_race.rs (revision `stack`)
+command: \"miri\" \"tests/fail/data_race/alloc_read_race.rs\"
+
+error: test got exit status: 0, but expected 1
+
+FAILURES:
+    tests/fail/dangling_pointers/dangling_zst_deref.rs
+    tests/fail/data_race/alloc_read_race.rs (revision `stack`)
+    tests/fail/data_race/alloc_read_race.rs (revision `tree`)
+
+test result: FAIL. 3 failed; 1 passed; 1 ignored; 2 filtered out
+";
+
+    const EXPECTED: &str = "\
+error: Undefined Behavior: memory access failed: ALLOC has been freed, so this pointer is dangling
+  --> tests/fail/dangling_pointers/dangling_zst_deref.rs:LL:CC
+   |
+LL |     let _x = unsafe { *p };
+   |                       ^^ Undefined Behavior occurred here
+   |
+  = help: this indicates a bug in the program: it performed an invalid operation, and caused Undefined Behavior
+  = help: see https://doc.rust-lang.org/nightly/reference/behavior-considered-undefined.html for further information
+";
+
+    #[test]
+    fn parses_the_statuses_and_the_diffs() {
+        let run = parse(OUTPUT);
+        assert!(is_output(OUTPUT));
+        assert_eq!(
+            run.events.failed,
+            [
+                "tests/fail/dangling_pointers/dangling_zst_deref.rs",
+                "tests/fail/data_race/alloc_read_race.rs",
+            ]
+        );
+        assert_eq!(run.events.passed, ["tests/pass/vec.rs"]);
+        assert_eq!(run.events.ignored[0].name, "tests/pass/shims/fs.rs");
+        assert_eq!(
+            run.events.ignored[0].reason.as_deref(),
+            Some("only on windows")
+        );
+        let Stats {
+            failed,
+            passed,
+            ignored,
+            ..
+        } = run.events.stats;
+        assert_eq!((failed, passed, ignored), (3, 1, 1));
+
+        assert_eq!(run.diffs.len(), 1);
+        let diff = &run.diffs[0];
+        assert_eq!(
+            diff.test,
+            "tests/fail/dangling_pointers/dangling_zst_deref.rs"
+        );
+        assert_eq!(diff.stream, Stream::Stderr);
+        assert_eq!(diff.lines.len(), 6);
+    }
+
+    #[test]
+    fn json_is_not_the_output() {
+        let json = r#"{ "type": "test", "event": "failed", "name": "[ui] tests/ui/foo.rs" }"#;
+        assert!(!is_output(json));
+    }
+
+    #[test]
+    fn rebuilds_the_actual_output() {
+        let run = parse(OUTPUT);
+        let actual = apply(EXPECTED, &run.diffs[0].lines).unwrap();
+        assert_eq!(
+            actual,
+            "\
+error: Undefined Behavior: memory access failed: ALLOC has been freed, so this pointer is dangling
+  --> tests/fail/dangling_pointers/dangling_zst_deref.rs:LL:CC
+   |
+LL |     let _x = unsafe { *p };
+   |                       ^^ Undefined Behavior occurred here
+   |
+  = help: this indicates a potential bug in the program: it performed an invalid operation, but the Stacked Borrows rules it violated are still experimental
+  = help: see https://doc.rust-lang.org/nightly/reference/behavior-considered-undefined.html for further information
+  = note: BACKTRACE:
+"
+        );
+    }
+
+    #[test]
+    fn restores_the_tabs() {
+        let diff = [
+            " fn main() {".to_owned(),
+            "-░x".to_owned(),
+            "+░y".to_owned(),
+        ];
+        assert_eq!(
+            apply("fn main() {\n\tx\n}\n", &diff).unwrap(),
+            "fn main() {\n\ty\n}\n"
+        );
+    }
+
+    #[test]
+    fn rejects_a_diff_of_another_file() {
+        let diff = [" fn main() {".to_owned(), "-    x".to_owned()];
+        assert_eq!(apply("fn main() {\n    y\n}\n", &diff), None);
+    }
+
+    #[test]
+    fn reads_the_expected_files() {
+        let rust_path = std::env::temp_dir().join(format!("ui_test-{}", std::process::id()));
+        let dir = rust_path.join("tests/fail/dangling_pointers");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("dangling_zst_deref.stderr"), EXPECTED).unwrap();
+
+        let run = parse(OUTPUT);
+        let artifacts = run.actual_outputs(&rust_path);
+        std::fs::remove_dir_all(&rust_path).unwrap();
+
+        let test = "tests/fail/dangling_pointers/dangling_zst_deref.rs";
+        let stderr = String::from_utf8(artifacts.actual(test, "stderr").unwrap()).unwrap();
+        assert!(stderr.ends_with("  = note: BACKTRACE:\n"));
+        // Neither the stdout nor the test without a diff have expected files.
+        assert_eq!(artifacts.actual(test, "stdout"), None);
+        assert_eq!(
+            artifacts.actual("tests/fail/data_race/alloc_read_race.rs", "stderr"),
+            None
+        );
+    }
+}
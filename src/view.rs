@@ -1,37 +1,258 @@
-use ratatui::prelude::*;
-use ratatui::widgets::{Block, Paragraph};
+use ratatui::{
+    layout::Flex,
+    prelude::*,
+    widgets::{Scrollbar, ScrollbarOrientation, ScrollbarState},
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::app::{Config, DiffMode, TestData};
-use crate::Stats;
+use crate::{app::Config, outputs::Resolved, theme::Theme, Stream};
 
 pub struct FullLayout {
     pub top_bar: Rect,
+    pub progress: Rect,
+    /// A line above the diff for failures that aren't output mismatches.
+    pub banner: Option<Rect>,
     pub diff_show: DiffShow,
+    /// On the right of the main area, see [`Config::show_metadata`].
+    pub metadata: Option<Rect>,
     pub help_bar: Option<Rect>,
 }
 
 impl FullLayout {
-    pub fn new(cfg: &Config, area: Rect) -> Self {
+    /// The help bar is also used for prompts, `bottom_bar` forces it even if help is hidden.
+    pub fn new(cfg: &Config, area: Rect, bottom_bar: bool, banner: bool) -> Self {
+        let show_help_bar = !cfg.hide_help || bottom_bar;
+        let mut constraints = vec![Constraint::Min(1), Constraint::Min(1)];
+        if banner {
+            constraints.push(Constraint::Length(1));
+        }
+        constraints.push(Constraint::Percentage(100));
+        if show_help_bar {
+            constraints.push(Constraint::Min(1));
+        }
         let rects = Layout::default()
             .direction(Direction::Vertical)
-            .constraints(if !cfg.hide_help {
-                [
-                    Constraint::Min(1),
-                    Constraint::Percentage(100),
-                    Constraint::Min(1),
-                ]
-                .as_slice()
-            } else {
-                [Constraint::Min(1), Constraint::Percentage(100)].as_slice()
-            })
+            .constraints(constraints)
             .split(area);
-        let diff_show = DiffShow::new(cfg.show_mode, rects[1]);
+        let main = if banner { 3 } else { 2 };
+        let (main_area, metadata) = if cfg.show_metadata {
+            let [main_area, metadata] =
+                Layout::horizontal([Constraint::Min(1), Constraint::Length(40)]).areas(rects[main]);
+            (main_area, Some(metadata))
+        } else {
+            (rects[main], None)
+        };
+        let diff_show = DiffShow::new(cfg.show_mode, main_area);
         Self {
             top_bar: rects[0],
+            progress: rects[1],
+            banner: banner.then(|| rects[2]),
             diff_show,
-            help_bar: if !cfg.hide_help { Some(rects[2]) } else { None },
+            metadata,
+            help_bar: show_help_bar.then(|| rects[main + 1]),
+        }
+    }
+}
+
+/// A centered rectangle taking `percent_x`/`percent_y` of `area`, for popups.
+pub fn popup_area(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
+    let [area] = Layout::vertical([Constraint::Percentage(percent_y)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::horizontal([Constraint::Percentage(percent_x)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+/// A scrollbar on the right border of `area` for `len` rows of content scrolled to `position`,
+/// with a mark at each row in `markers`, like the changed hunks of a diff, in the `theme`.
+pub fn render_scrollbar(
+    frame: &mut Frame,
+    area: Rect,
+    len: usize,
+    position: usize,
+    markers: &[usize],
+    theme: &Theme,
+) {
+    let track = area.inner(Margin {
+        horizontal: 0,
+        vertical: 1,
+    });
+    if len <= track.height as usize {
+        return;
+    }
+    let mut state = ScrollbarState::new(len)
+        .position(position)
+        .viewport_content_length(track.height as usize);
+    frame.render_stateful_widget(
+        Scrollbar::new(ScrollbarOrientation::VerticalRight)
+            .begin_symbol(None)
+            .end_symbol(None),
+        track,
+        &mut state,
+    );
+
+    let x = track.right() - 1;
+    for &row in markers {
+        let y = track.y + (row * track.height as usize / len) as u16;
+        if let Some(cell) = frame.buffer_mut().cell_mut((x, y)) {
+            cell.set_symbol("◆").set_style(theme.marker);
+        }
+    }
+}
+
+/// Tabs for the differing outputs of the test on the top border of `area`, on the left of its
+/// title, with the `current` one highlighted. Numbered like the keys that show them.
+pub fn render_stream_tabs(frame: &mut Frame, area: Rect, streams: &[Stream], current: Stream) {
+    let mut spans = vec![];
+    for &stream in streams {
+        let number = match stream {
+            Stream::Stderr => 1,
+            Stream::Stdout => 2,
+        };
+        let name = Span::raw(format!(" {number} {} ", stream.name()));
+        spans.push(if stream == current {
+            name.reversed().bold()
+        } else {
+            name.dim()
+        });
+        spans.push(Span::raw("─"));
+    }
+    let tabs = Line::from(spans);
+    let area = Rect {
+        x: area.x + 2,
+        width: (tabs.width() as u16).min(area.width.saturating_sub(4)),
+        height: 1,
+        ..area
+    };
+    frame.render_widget(tabs, area);
+}
+
+/// `text` with its tabs replaced by spaces up to the next multiple of `tab_width` columns.
+pub fn expand_tabs(text: Text<'_>, tab_width: usize) -> Text<'_> {
+    let tab_width = tab_width.max(1);
+    let lines = text.lines.into_iter().map(|line| {
+        if !line.spans.iter().any(|span| span.content.contains('\t')) {
+            return line;
+        }
+        let mut column = 0;
+        let spans = line.spans.into_iter().map(|span| {
+            if !span.content.contains('\t') {
+                column += span.width();
+                return span;
+            }
+            let mut expanded = String::new();
+            for c in span.content.chars() {
+                if c == '\t' {
+                    let spaces = tab_width - column % tab_width;
+                    expanded.extend(std::iter::repeat_n(' ', spaces));
+                    column += spaces;
+                } else {
+                    expanded.push(c);
+                    column += c.width().unwrap_or(0);
+                }
+            }
+            Span::styled(expanded, span.style)
+        });
+        Line {
+            spans: spans.collect(),
+            ..line
+        }
+    });
+    Text {
+        lines: lines.collect(),
+        ..text
+    }
+}
+
+/// Marks the rows of `text` that don't fit in the bordered `area` with a `»` on their last column,
+/// for `text` scrolled down `scroll` rows without wrapping. Returns the marked lines in full.
+pub fn mark_cut_off(
+    frame: &mut Frame,
+    area: Rect,
+    text: &Text,
+    scroll: u16,
+    theme: &Theme,
+) -> Vec<String> {
+    let inner = area.inner(Margin {
+        horizontal: 1,
+        vertical: 1,
+    });
+    if inner.is_empty() {
+        return vec![];
+    }
+    let mut cut_off = vec![];
+    let visible = text
+        .lines
+        .iter()
+        .skip(scroll as usize)
+        .take(inner.height as usize);
+    for (row, line) in visible.enumerate() {
+        if line.width() <= inner.width as usize {
+            continue;
         }
+        let (x, y) = (inner.right() - 1, inner.y + row as u16);
+        let buffer = frame.buffer_mut();
+        // The last column may be the second half of a wide character, which hides the mark unless
+        // the character is cleared too.
+        if let Some(cell) = x
+            .checked_sub(1)
+            .filter(|&x| x >= inner.x)
+            .and_then(|x| buffer.cell_mut((x, y)))
+        {
+            if cell.symbol().width() > 1 {
+                cell.set_symbol(" ");
+            }
+        }
+        if let Some(cell) = buffer.cell_mut((x, y)) {
+            cell.set_symbol("»").set_style(theme.marker);
+        }
+        cut_off.push(
+            line.spans
+                .iter()
+                .map(|span| span.content.as_ref())
+                .collect(),
+        );
+    }
+    cut_off
+}
+
+/// The files each stream was read from, and whether they exist, with the `current` stream first.
+pub fn resolved_paths_text(resolved: &[Resolved], current: Stream) -> Text<'static> {
+    let exists = |exists: bool| {
+        if exists {
+            Span::raw("")
+        } else {
+            " (missing)".red()
+        }
+    };
+    let mut resolved: Vec<_> = resolved.iter().collect();
+    resolved.sort_by_key(|r| r.stream != current);
+    let mut lines = vec![];
+    for r in resolved {
+        let name = Span::raw(r.stream.name());
+        lines.push(Line::from(if r.stream == current {
+            name.bold()
+        } else {
+            name
+        }));
+        lines.push(Line::from(vec![
+            "  expected: ".dim(),
+            r.expected_path.display().to_string().into(),
+            exists(r.expected_exists),
+        ]));
+        let actual = match &r.actual_path {
+            Some(path) => path.display().to_string(),
+            None => "in the artifacts archive".to_owned(),
+        };
+        lines.push(Line::from(vec![
+            "  actual:   ".dim(),
+            actual.into(),
+            exists(r.actual_exists),
+        ]));
     }
+    lines.into()
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -44,6 +265,42 @@ pub enum ShowMode {
     RustcArgs {
         oneline: bool,
     },
+    Annotations,
+    /// The `//@` header directives of the test and the history of its expected output.
+    Info,
+    OtherFailures,
+    /// The output of the user's diff tool.
+    External,
+    Ignored,
+    /// The output of rustc with its own colors.
+    Colored,
+}
+
+impl ShowMode {
+    pub const fn is_side_by_side(self) -> bool {
+        matches!(self, ShowMode::SideBySide | ShowMode::SideBySideOnly)
+    }
+
+    pub const fn shows_code(self) -> bool {
+        matches!(self, ShowMode::SideBySide | ShowMode::Vertical)
+    }
+}
+
+/// A scrollable pane, the one with the focus is scrolled by the movement keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Pane {
+    #[default]
+    Diff,
+    Code,
+}
+
+impl Pane {
+    pub const fn rotate_next(&mut self) {
+        *self = match self {
+            Pane::Diff => Pane::Code,
+            Pane::Code => Pane::Diff,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -53,9 +310,33 @@ pub enum DiffShow {
     SideBySide { code: Rect, rhs: Rect, lhs: Rect },
     SideBySideOnly { rhs: Rect, lhs: Rect },
     RustcArgs { args: Rect, oneline: bool },
+    Annotations { area: Rect },
+    Info { area: Rect },
+    OtherFailures { area: Rect },
+    External { area: Rect },
+    Ignored { area: Rect },
+    Colored { area: Rect },
 }
 
 impl DiffShow {
+    /// Where the diff goes, as a single area even in side by side mode.
+    pub fn diff_area(&self) -> Option<Rect> {
+        match *self {
+            DiffShow::Vertical { diff, .. } | DiffShow::VerticalOnly { diff } => Some(diff),
+            DiffShow::SideBySide { lhs, rhs, .. } | DiffShow::SideBySideOnly { lhs, rhs } => {
+                Some(lhs.union(rhs))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn code_area(&self) -> Option<Rect> {
+        match *self {
+            DiffShow::Vertical { code, .. } | DiffShow::SideBySide { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+
     pub fn new(mode: ShowMode, rect: Rect) -> Self {
         match mode {
             ShowMode::SideBySide => {
@@ -98,6 +379,12 @@ impl DiffShow {
                 args: rect,
                 oneline,
             },
+            ShowMode::Annotations => DiffShow::Annotations { area: rect },
+            ShowMode::Info => DiffShow::Info { area: rect },
+            ShowMode::OtherFailures => DiffShow::OtherFailures { area: rect },
+            ShowMode::External => DiffShow::External { area: rect },
+            ShowMode::Ignored => DiffShow::Ignored { area: rect },
+            ShowMode::Colored => DiffShow::Colored { area: rect },
         }
     }
 }
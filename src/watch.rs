@@ -0,0 +1,76 @@
+//! `--watch`: noticing when compiletest rewrites the outputs, to reload them.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Watches the build directories of the suites and the JSON output of the run.
+#[derive(Debug)]
+pub struct Watch {
+    // Dropping it stops the watching.
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    input: PathBuf,
+}
+
+impl Watch {
+    /// Watches `build_dirs` recursively, as the directories of the tests are recreated when they
+    /// rerun, and the file `input`.
+    pub fn new(build_dirs: &[PathBuf], input: &Path) -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        for dir in build_dirs {
+            if dir.is_dir() {
+                watcher.watch(dir, RecursiveMode::Recursive)?;
+            } else {
+                tracing::warn!(dir = %dir.display(), "not watching a missing build directory");
+            }
+        }
+        // The directory, because the file may be replaced instead of written to.
+        let input = std::path::absolute(input)?;
+        let dir = input
+            .parent()
+            .ok_or_else(|| eyre!("{} has no parent directory", input.display()))?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        tracing::info!(?build_dirs, input = %input.display(), "watching");
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            input,
+        })
+    }
+
+    /// The watched JSON output, absolute.
+    pub fn input(&self) -> &Path {
+        &self.input
+    }
+
+    /// The files that were written, created or removed since the last call, without waiting.
+    /// Of the directory of the input, only the input itself.
+    pub fn changes(&self) -> Vec<PathBuf> {
+        let mut changes = vec![];
+        for event in self.events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    tracing::warn!(error = %e, "watching failed");
+                    continue;
+                }
+            };
+            if matches!(event.kind, EventKind::Access(_) | EventKind::Any) {
+                continue;
+            }
+            changes.extend(
+                event
+                    .paths
+                    .into_iter()
+                    .filter(|path| path == &self.input || path.parent() != self.input.parent()),
+            );
+        }
+        changes
+    }
+}